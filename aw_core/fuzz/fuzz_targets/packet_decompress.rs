@@ -0,0 +1,19 @@
+#![no_main]
+
+use aw_core::AWPacket;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(decompressed) = AWPacket::decompress(data) else {
+        return;
+    };
+
+    // The protocol parses whatever comes out as more packets
+    let mut rest = decompressed.as_slice();
+    while let Ok(serialized_len) = AWPacket::deserialize_check(rest) {
+        if AWPacket::deserialize(&rest[..serialized_len]).is_err() {
+            break;
+        }
+        rest = &rest[serialized_len..];
+    }
+});
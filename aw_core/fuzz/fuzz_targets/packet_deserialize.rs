@@ -0,0 +1,24 @@
+#![no_main]
+
+use aw_core::AWPacket;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // The header is checked before anything else is read from the connection
+    if AWPacket::deserialize_check(data).is_err() {
+        return;
+    }
+
+    let Ok((packet, _)) = AWPacket::deserialize(data) else {
+        return;
+    };
+
+    // Whatever was accepted must come out the same after going over the wire again.
+    // Compare bytes rather than packets, since NaN floats never equal themselves.
+    if let Ok(serialized) = packet.serialize() {
+        let (reparsed, consumed) =
+            AWPacket::deserialize(&serialized).expect("Serialized packet should deserialize");
+        assert_eq!(consumed, serialized.len());
+        assert_eq!(reparsed.serialize().unwrap(), serialized);
+    }
+});
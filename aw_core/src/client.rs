@@ -0,0 +1,499 @@
+//! Client side of the AW protocol, for writing bots and test clients.
+use crate::{
+    messages::{Botgram, TelegramSend},
+    AWConnection, AWCryptRSA, AWPacket, AWProtocol, PacketType, PacketTypeResult, ProtocolMessage,
+    ProtocolVersion, ReasonCode, StreamCipherError, VarID,
+};
+use byteorder::{LittleEndian, WriteBytesExt};
+use core::fmt;
+use num_traits::FromPrimitive;
+use std::error::Error;
+use std::io;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+/// How long to wait for the universe to answer a request, unless changed with
+/// [`AWClient::set_timeout`].
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The digest a protocol 6 browser sends in place of a password:
+/// the MD5 of the password's length (u32, little endian) followed by its bytes in reverse.
+pub fn login_digest(password: &str) -> Vec<u8> {
+    let mut buf = Vec::<u8>::with_capacity(4 + password.len());
+    buf.write_u32::<LittleEndian>(password.len() as u32)
+        .expect("Writing to a Vec cannot fail");
+    buf.extend(password.bytes().rev());
+
+    md5::compute(buf).to_vec()
+}
+
+#[derive(Debug)]
+pub enum ClientError {
+    Connect(io::Error),
+    StreamCipher(StreamCipherError),
+    /// The key exchange with the universe failed.
+    Handshake(String),
+    /// The universe did not answer in time.
+    Timeout,
+    Disconnected,
+    /// The universe answered a request with an error.
+    Rejected(ReasonCode),
+    /// The universe answered a request with something that could not be understood.
+    InvalidResponse(PacketType),
+}
+
+impl Error for ClientError {}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Connect(why) => write!(f, "Could not connect: {why}"),
+            ClientError::StreamCipher(why) => write!(f, "Stream cipher failed: {why}"),
+            ClientError::Handshake(why) => write!(f, "Key exchange failed: {why}"),
+            ClientError::Timeout => write!(f, "Timed out waiting for the universe"),
+            ClientError::Disconnected => write!(f, "Disconnected from the universe"),
+            ClientError::Rejected(rc) => write!(f, "The universe refused the request: {rc:?}"),
+            ClientError::InvalidResponse(packet_type) => {
+                write!(f, "Received an invalid {packet_type:?} packet")
+            }
+        }
+    }
+}
+
+/// Who to log in as.
+#[derive(Debug, Clone)]
+pub enum Login {
+    Citizen {
+        name: String,
+        password: String,
+        /// Citizen number and privilege password of another citizen to act as.
+        privilege: Option<(u32, String)>,
+    },
+    /// A tourist's name is sent in quotes, which are added if missing.
+    Tourist { name: String },
+    Bot {
+        /// Citizen number of the owner, whose privilege password the bot logs in with.
+        owner: u32,
+        privilege_password: String,
+        /// Name of the bot without brackets.
+        name: String,
+        /// Description of what the bot does.
+        application: String,
+    },
+}
+
+/// What the universe tells a client about itself after logging in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoginInfo {
+    /// Name as displayed to others, which includes quotes or brackets for tourists and bots.
+    pub name: String,
+    pub session_id: i32,
+    pub citizen_number: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorldListEntry {
+    pub name: String,
+    /// 1 for public, 2 for private and 3 for hidden.
+    pub status: u8,
+    pub users: u32,
+    pub rating: u8,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContactListEntry {
+    pub citizen_number: u32,
+    pub name: String,
+    pub world: Option<String>,
+    pub status: i32,
+    pub options: u32,
+}
+
+type PacketCallback = Box<dyn FnMut(&AWPacket) + Send>;
+
+/// Connection to a universe from the client side.
+///
+/// Requests block until the universe answers them. Any other packets which arrive in the
+/// meantime are passed to the callbacks registered with [`AWClient::on`], and can also be handled
+/// by calling [`AWClient::poll`].
+pub struct AWClient {
+    connection: AWConnection,
+    version: ProtocolVersion,
+    rsa: AWCryptRSA,
+    timeout: Duration,
+    browser_build: i32,
+    attributes: AWPacket,
+    callbacks: Vec<(PacketType, PacketCallback)>,
+}
+
+impl AWClient {
+    /// Connect to a universe and exchange keys with it, speaking the given version of the
+    /// protocol.
+    pub fn connect(
+        addr: impl ToSocketAddrs,
+        version: ProtocolVersion,
+    ) -> Result<Self, ClientError> {
+        let stream = TcpStream::connect(addr).map_err(ClientError::Connect)?;
+        let mut protocol = AWProtocol::new(stream).map_err(ClientError::StreamCipher)?;
+        protocol
+            .set_cipher_kind(version.cipher_kind())
+            .map_err(ClientError::StreamCipher)?;
+
+        let mut client = Self {
+            connection: AWConnection::new(protocol),
+            version,
+            rsa: AWCryptRSA::new(),
+            timeout: DEFAULT_TIMEOUT,
+            browser_build: 0,
+            attributes: AWPacket::new(PacketType::Attributes),
+            callbacks: Vec::new(),
+        };
+        client.exchange_keys()?;
+
+        Ok(client)
+    }
+
+    fn exchange_keys(&mut self) -> Result<(), ClientError> {
+        self.send(AWPacket::new(PacketType::PublicKeyRequest));
+        let response = self.wait_for(PacketType::PublicKeyResponse)?;
+        let server_key = response
+            .get_data(VarID::EncryptionKey)
+            .ok_or(ClientError::InvalidResponse(PacketType::PublicKeyResponse))?;
+
+        // Send our stream key encrypted with the universe's public key.
+        // Everything we send afterwards is encrypted with it.
+        let mut server_rsa = AWCryptRSA::default();
+        server_rsa.randomize();
+        server_rsa
+            .decode_public_key(&server_key)
+            .map_err(|why| ClientError::Handshake(format!("{why:?}")))?;
+        let encrypted_key = server_rsa
+            .encrypt_public(&self.connection.get_send_key())
+            .map_err(|why| ClientError::Handshake(format!("{why:?}")))?;
+        let mut packet = AWPacket::new(PacketType::StreamKeyResponse);
+        packet.add_data(VarID::EncryptionKey, encrypted_key);
+        self.send(packet);
+        self.connection.encrypt_data(true);
+
+        // The universe sends its attributes as soon as it can read what we send
+        self.attributes = self.wait_for(PacketType::Attributes)?;
+
+        // Then have the universe send its own stream key encrypted with our public key
+        let public_key = self
+            .rsa
+            .encode_public_key()
+            .ok_or_else(|| ClientError::Handshake("No public key".to_string()))?;
+        let mut packet = AWPacket::new(PacketType::PublicKeyResponse);
+        packet.add_data(VarID::EncryptionKey, public_key);
+        self.send(packet);
+
+        let response = self.wait_for(PacketType::StreamKeyResponse)?;
+        let encrypted_key = response
+            .get_data(VarID::EncryptionKey)
+            .ok_or(ClientError::InvalidResponse(PacketType::StreamKeyResponse))?;
+        let key = self
+            .rsa
+            .decrypt_private(&encrypted_key)
+            .map_err(|why| ClientError::Handshake(format!("{why:?}")))?;
+        self.connection.set_recv_key(&key);
+
+        Ok(())
+    }
+
+    /// Set how long to wait for the universe to answer a request.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Set the browser build reported when logging in, which the universe uses to create the
+    /// license it sends back.
+    pub fn set_browser_build(&mut self, build: i32) {
+        self.browser_build = build;
+    }
+
+    /// Attributes of the universe, which are sent once the keys have been exchanged.
+    pub fn attributes(&self) -> &AWPacket {
+        &self.attributes
+    }
+
+    /// Call `callback` with every packet of a type which is not the answer to a request.
+    pub fn on(
+        &mut self,
+        packet_type: PacketType,
+        callback: impl FnMut(&AWPacket) + Send + 'static,
+    ) {
+        self.callbacks.push((packet_type, Box::new(callback)));
+    }
+
+    /// Send a packet with the headers of this client's version.
+    pub fn send(&self, mut packet: AWPacket) {
+        self.version.set_client_headers(&mut packet);
+        self.connection.send(packet);
+    }
+
+    /// Handle any packets which arrive within `timeout`, passing them to the callbacks.
+    pub fn poll(&mut self, timeout: Duration) -> Result<(), ClientError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if self.next_packet(remaining)?.is_none() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Wait up to `timeout` for the next packet, which is passed to the callbacks before being
+    /// returned.
+    pub fn next_packet(&mut self, timeout: Duration) -> Result<Option<AWPacket>, ClientError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.connection.recv_timeout(remaining) {
+                Some(ProtocolMessage::Packet(packet)) => {
+                    self.dispatch(&packet);
+                    return Ok(Some(packet));
+                }
+                Some(ProtocolMessage::Disconnect) => return Err(ClientError::Disconnected),
+                Some(_) => {}
+                None => return Ok(None),
+            }
+        }
+    }
+
+    fn dispatch(&mut self, packet: &AWPacket) {
+        let PacketTypeResult::PacketType(packet_type) = packet.get_type() else {
+            return;
+        };
+
+        // The universe drops clients which stop answering heartbeats
+        if packet_type == PacketType::Heartbeat {
+            self.send(AWPacket::new(PacketType::Heartbeat));
+        }
+
+        for (callback_type, callback) in &mut self.callbacks {
+            if *callback_type == packet_type {
+                callback(packet);
+            }
+        }
+    }
+
+    /// Wait for a packet which matches `filter`, dispatching any others.
+    pub fn wait_until(
+        &mut self,
+        mut filter: impl FnMut(&AWPacket) -> bool,
+    ) -> Result<AWPacket, ClientError> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.connection.recv_timeout(remaining) {
+                Some(ProtocolMessage::Packet(packet)) if filter(&packet) => return Ok(packet),
+                Some(ProtocolMessage::Packet(packet)) => self.dispatch(&packet),
+                Some(ProtocolMessage::Disconnect) => return Err(ClientError::Disconnected),
+                Some(_) => {}
+                None => return Err(ClientError::Timeout),
+            }
+        }
+    }
+
+    /// Wait for a packet of the given type, dispatching any others.
+    pub fn wait_for(&mut self, packet_type: PacketType) -> Result<AWPacket, ClientError> {
+        self.wait_until(|packet| packet.get_type() == PacketTypeResult::PacketType(packet_type))
+    }
+
+    /// Send a request and wait for the universe to answer it with a packet of type `response`.
+    /// Fails with [`ClientError::Rejected`] unless the answer's reason code is a success.
+    pub fn request(
+        &mut self,
+        packet: AWPacket,
+        response: PacketType,
+    ) -> Result<AWPacket, ClientError> {
+        self.send(packet);
+        let packet = self.wait_for(response)?;
+        let rc = packet
+            .get_int(VarID::ReasonCode)
+            .and_then(ReasonCode::from_i32)
+            .ok_or(ClientError::InvalidResponse(response))?;
+
+        match rc {
+            ReasonCode::Success => Ok(packet),
+            rc => Err(ClientError::Rejected(rc)),
+        }
+    }
+
+    pub fn login(&mut self, login: Login) -> Result<LoginInfo, ClientError> {
+        let mut packet = AWPacket::new(PacketType::Login);
+        packet.add_int(VarID::BrowserBuild, self.browser_build);
+        packet.add_int(
+            VarID::BrowserVersion,
+            match self.version {
+                ProtocolVersion::V4 => 4,
+                ProtocolVersion::V6 => 6,
+            },
+        );
+
+        match login {
+            Login::Citizen {
+                name,
+                password,
+                privilege,
+            } => {
+                packet.add_int(VarID::UserType, 2);
+                packet.add_string(VarID::LoginUsername, name);
+                match self.version {
+                    ProtocolVersion::V4 => packet.add_string(VarID::Password, password),
+                    ProtocolVersion::V6 => {
+                        packet.add_data(VarID::PasswordDigest, login_digest(&password))
+                    }
+                }
+                if let Some((id, password)) = privilege {
+                    packet.add_uint(VarID::PrivilegeUserID, id);
+                    packet.add_string(VarID::PrivilegePassword, password);
+                }
+            }
+            Login::Tourist { name } => {
+                let name = if name.starts_with('"') {
+                    name
+                } else {
+                    format!("\"{name}\"")
+                };
+                packet.add_int(VarID::UserType, 2);
+                packet.add_string(VarID::LoginUsername, name);
+            }
+            Login::Bot {
+                owner,
+                privilege_password,
+                name,
+                application,
+            } => {
+                packet.add_int(VarID::UserType, 3);
+                packet.add_uint(VarID::LoginID, owner);
+                packet.add_string(VarID::PrivilegePassword, privilege_password);
+                packet.add_string(VarID::LoginUsername, name);
+                packet.add_string(VarID::Application, application);
+            }
+        }
+
+        let response = self.request(packet, PacketType::Login)?;
+        Ok(LoginInfo {
+            name: response
+                .get_string(VarID::CitizenName)
+                .ok_or(ClientError::InvalidResponse(PacketType::Login))?,
+            session_id: response
+                .get_int(VarID::SessionID)
+                .ok_or(ClientError::InvalidResponse(PacketType::Login))?,
+            citizen_number: response.get_uint(VarID::CitizenNumber),
+        })
+    }
+
+    /// Send a telegram to a citizen by name.
+    pub fn telegram_send(&mut self, to: &str, message: &str) -> Result<(), ClientError> {
+        let packet = TelegramSend {
+            to: to.to_string(),
+            message: message.to_string(),
+        };
+        self.request(packet.into(), PacketType::TelegramSend)
+            .map(|_| ())
+    }
+
+    /// Send a botgram to the bots owned by a citizen.
+    pub fn botgram(&mut self, citizen_number: u32, message: &str) -> Result<(), ClientError> {
+        let packet = Botgram {
+            citizen_number,
+            botgram_type: 0,
+            message: message.to_string(),
+        };
+        self.request(packet.into(), PacketType::BotgramResponse)
+            .map(|_| ())
+    }
+
+    /// Get every world the universe lists.
+    pub fn world_list(&mut self) -> Result<Vec<WorldListEntry>, ClientError> {
+        self.send(AWPacket::new(PacketType::WorldList));
+
+        let mut worlds = Vec::new();
+        loop {
+            let packet = self.wait_until(|packet| {
+                matches!(
+                    packet.get_type(),
+                    PacketTypeResult::PacketType(
+                        PacketType::WorldList | PacketType::WorldListResult
+                    )
+                )
+            })?;
+
+            if packet.get_type() == PacketTypeResult::PacketType(PacketType::WorldListResult) {
+                if packet.get_byte(VarID::WorldListMore) == Some(0) {
+                    return Ok(worlds);
+                }
+                continue;
+            }
+
+            worlds.push(WorldListEntry {
+                name: packet
+                    .get_string(VarID::WorldListName)
+                    .ok_or(ClientError::InvalidResponse(PacketType::WorldList))?,
+                status: packet.get_byte(VarID::WorldListStatus).unwrap_or(0),
+                users: packet.get_uint(VarID::WorldListUsers).unwrap_or(0),
+                rating: packet.get_byte(VarID::WorldListRating).unwrap_or(0),
+            });
+        }
+    }
+
+    /// Get every contact of the citizen who is logged in.
+    pub fn contact_list(&mut self) -> Result<Vec<ContactListEntry>, ClientError> {
+        let mut contacts = Vec::<ContactListEntry>::new();
+        let mut starting_from = 0;
+
+        // The universe sends a limited number of contacts at a time, ending each batch with an
+        // entry for citizen 0 which says whether there are more.
+        loop {
+            let mut packet = AWPacket::new(PacketType::ContactList);
+            packet.add_uint(VarID::ContactListCitizenID, starting_from);
+            self.send(packet);
+
+            loop {
+                let packet = self.wait_for(PacketType::ContactList)?;
+                let citizen_number = packet
+                    .get_uint(VarID::ContactListCitizenID)
+                    .ok_or(ClientError::InvalidResponse(PacketType::ContactList))?;
+
+                if citizen_number == 0 {
+                    if packet.get_byte(VarID::ContactListMore) == Some(1) {
+                        break;
+                    }
+                    return Ok(contacts);
+                }
+
+                starting_from = starting_from.max(citizen_number);
+                contacts.retain(|contact| contact.citizen_number != citizen_number);
+                contacts.push(ContactListEntry {
+                    citizen_number,
+                    name: packet
+                        .get_string(VarID::ContactListName)
+                        .unwrap_or_default(),
+                    world: packet.get_string(VarID::ContactListWorld),
+                    status: packet.get_int(VarID::ContactListStatus).unwrap_or(0),
+                    options: packet.get_uint(VarID::ContactListOptions).unwrap_or(0),
+                });
+            }
+        }
+    }
+
+    pub fn disconnect(&mut self) {
+        self.connection.disconnect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digest_is_reversed_md5() {
+        // MD5 of 07 00 00 00 followed by "emoclew"
+        assert_eq!(
+            login_digest("welcome"),
+            md5::compute(b"\x07\x00\x00\x00emoclew").to_vec()
+        );
+    }
+}
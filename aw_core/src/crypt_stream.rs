@@ -1,6 +1,9 @@
 use core::fmt;
+use rand::{thread_rng, RngCore};
 use std::error::Error;
 
+use crate::{AWCryptA4, AWCryptAES};
+
 #[derive(Debug)]
 pub enum StreamCipherError {
     KeyTooShort,
@@ -45,3 +48,89 @@ pub trait AWCryptStream: Sized {
     /// Decrypt bytes, returning a vector
     fn decrypt(&mut self, buffer: &[u8]) -> Result<Vec<u8>, StreamCipherError>;
 }
+
+/// Which stream cipher a connection uses, which depends on the version of the client.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StreamCipherKind {
+    /// RC4, used by AW 4 and 5
+    #[default]
+    A4,
+    /// AES, used by AW 6
+    AES,
+}
+
+/// A stream cipher whose kind is chosen at runtime.
+#[allow(clippy::large_enum_variant)]
+pub enum StreamCipher {
+    A4(AWCryptA4),
+    AES(AWCryptAES),
+}
+
+impl StreamCipher {
+    /// Create a new random key which can be used with either kind of cipher.
+    pub fn new_key() -> Vec<u8> {
+        let mut key = vec![0u8; 256];
+        thread_rng().fill_bytes(&mut key);
+        key
+    }
+
+    /// Create a stream cipher of the given kind using an existing key.
+    pub fn from_key(kind: StreamCipherKind, key: &[u8]) -> Result<Self, StreamCipherError> {
+        Ok(match kind {
+            StreamCipherKind::A4 => StreamCipher::A4(AWCryptA4::from_key(key)?),
+            StreamCipherKind::AES => StreamCipher::AES(AWCryptAES::from_key(key)?),
+        })
+    }
+
+    pub fn kind(&self) -> StreamCipherKind {
+        match self {
+            StreamCipher::A4(_) => StreamCipherKind::A4,
+            StreamCipher::AES(_) => StreamCipherKind::AES,
+        }
+    }
+
+    /// Get the initial key value used to set up the cipher
+    pub fn get_initial_random_buffer(&self) -> Vec<u8> {
+        match self {
+            StreamCipher::A4(cipher) => cipher.get_initial_random_buffer(),
+            StreamCipher::AES(cipher) => cipher.get_initial_random_buffer(),
+        }
+    }
+
+    /// Decrypt bytes, storing the result in the same buffer.
+    pub fn decrypt_in_place(&mut self, buffer: &mut [u8]) -> Result<(), StreamCipherError> {
+        match self {
+            StreamCipher::A4(cipher) => cipher.decrypt_in_place(buffer),
+            StreamCipher::AES(cipher) => cipher.decrypt_in_place(buffer),
+        }
+    }
+
+    /// Encrypt bytes, returning a vector
+    pub fn encrypt(&mut self, buffer: &[u8]) -> Result<Vec<u8>, StreamCipherError> {
+        match self {
+            StreamCipher::A4(cipher) => cipher.encrypt(buffer),
+            StreamCipher::AES(cipher) => cipher.encrypt(buffer),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kinds_are_distinct() {
+        let key = StreamCipher::new_key();
+        let data = b"Hello, World!";
+
+        let mut a4 = StreamCipher::from_key(StreamCipherKind::A4, &key).unwrap();
+        let mut aes = StreamCipher::from_key(StreamCipherKind::AES, &key).unwrap();
+        let encrypted = a4.encrypt(data).unwrap();
+        assert_ne!(encrypted, aes.encrypt(data).unwrap());
+
+        let mut decrypted = encrypted.clone();
+        let mut a4 = StreamCipher::from_key(StreamCipherKind::A4, &key).unwrap();
+        a4.decrypt_in_place(&mut decrypted).unwrap();
+        assert_eq!(&decrypted, data);
+    }
+}
@@ -4,6 +4,11 @@ pub use crypt_rsa::*;
 mod reg_lic;
 pub use reg_lic::*;
 
+#[cfg(feature = "license_generator")]
+mod license_generator;
+#[cfg(feature = "license_generator")]
+pub use license_generator::LicenseGenerator;
+
 mod crypt_stream;
 pub use crypt_stream::*;
 
@@ -16,7 +21,12 @@ pub use crypt_aes::*;
 mod net;
 pub use net::*;
 
+mod client;
+pub use client::*;
+
 mod reason_code;
 pub use reason_code::ReasonCode;
 
 pub mod encoding;
+
+pub mod messages;
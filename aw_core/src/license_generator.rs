@@ -1,4 +1,4 @@
-use aw_core::*;
+use crate::{AWCryptRSA, AWRegLic, AWRegLicData, RSAKey};
 use std::net::SocketAddrV4;
 
 /// Generates licenses for sending to clients.
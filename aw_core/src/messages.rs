@@ -0,0 +1,110 @@
+//! Messages for packets whose contents are known, shared by universes and clients.
+use crate::packet_message;
+
+packet_message! {
+    /// A client sends its public key, which the other end encrypts its stream key with.
+    pub struct PublicKeyResponse: PublicKeyResponse {
+        pub key: Vec<u8> = EncryptionKey,
+    }
+
+    /// A client sends its stream key, encrypted with the public key of the other end.
+    pub struct StreamKeyResponse: StreamKeyResponse {
+        pub encrypted_key: Vec<u8> = EncryptionKey,
+    }
+
+    /// Apply for citizenship.
+    pub struct Immigrate: Immigrate {
+        pub name: String = CitizenName,
+        pub password: String = CitizenPassword,
+        pub email: String = CitizenEmail,
+    }
+
+    pub struct ImmigrateResponse: ImmigrateResponse {
+        pub reason_code: i32 = ReasonCode,
+    }
+
+    pub struct CitizenLookupByName: CitizenLookupByName {
+        pub name: String = CitizenName,
+    }
+
+    pub struct CitizenLookupByNumber: CitizenLookupByNumber {
+        pub citizen_number: u32 = CitizenNumber,
+    }
+
+    /// Look up the citizen with the next higher number.
+    pub struct CitizenNext: CitizenNext {
+        pub citizen_number: u32 = CitizenNumber,
+    }
+
+    /// Look up the citizen with the next lower number.
+    pub struct CitizenPrev: CitizenPrev {
+        pub citizen_number: u32 = CitizenNumber,
+    }
+
+    /// Ask a citizen to become a contact.
+    pub struct ContactAdd: ContactAdd {
+        pub name: String = ContactListName,
+        pub options: u32 = ContactListOptions,
+    }
+
+    /// Change the options of an existing contact.
+    pub struct ContactChange: ContactChange {
+        pub citizen_number: u32 = ContactListCitizenID,
+        pub options: u32 = ContactListOptions,
+    }
+
+    pub struct TelegramSend: TelegramSend {
+        pub to: String = TelegramTo,
+        pub message: String = TelegramMessage,
+    }
+
+    /// Send a message to the bots of a citizen.
+    pub struct Botgram: Botgram {
+        pub citizen_number: u32 = BotgramCitizenNumber,
+        pub botgram_type: u32 = BotgramType,
+        pub message: String = BotgramMessage,
+    }
+
+    /// Ban an IP address from the universe until the expiration time.
+    pub struct EjectAdd: EjectAdd {
+        pub address: u32 = EjectionAddress,
+        pub expiration: u32 = EjectionExpiration,
+        pub comment: String = EjectionComment,
+    }
+
+    /// A world server announces itself to the universe.
+    pub struct WorldServerStart: WorldServerStart {
+        pub version: u32 = BrowserVersion,
+        pub build: u32 = WorldBuild,
+        pub port: u16 = WorldPort,
+    }
+
+    /// A world server starts one of its worlds, using the world's license.
+    pub struct WorldStart: WorldStart {
+        pub name: String = WorldName,
+        pub password: String = WorldLicensePassword,
+        pub rating: u8 = WorldRating,
+        pub free_entry: bool = WorldFreeEntry,
+    }
+
+    pub struct WorldStop: WorldStop {
+        pub name: String = WorldName,
+    }
+
+    /// A world server reports a change in one of its worlds.
+    pub struct WorldStatsUpdate: WorldStatsUpdate {
+        pub rating: u8 = WorldRating,
+        pub free_entry: bool = WorldFreeEntry,
+        pub users: u32 = WorldUsers,
+        pub name: String = WorldName,
+    }
+
+    /// A world server asks who a player entering one of its worlds is.
+    pub struct Identify: Identify {
+        pub world_name: String = WorldName,
+        pub nonce: Vec<u8> = WorldUserNonce,
+        pub session_id: u16 = SessionID,
+        pub player_ip: u32 = IdentifyUserIP,
+        pub player_port: u16 = PlayerPort,
+    }
+}
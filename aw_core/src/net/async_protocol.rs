@@ -0,0 +1,380 @@
+//! Asynchronous implementation of the AW protocol on top of tokio
+use crate::net::packet::{AWPacket, DeserializeError, PacketType};
+use crate::net::protocol::serialize_outgoing;
+use crate::{
+    Direction, PacketCapture, PacketTypeResult, ProtocolStats, StreamCipher, StreamCipherError,
+    StreamCipherKind,
+};
+use bytes::{Buf, BytesMut};
+use futures_util::{Sink, Stream};
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Outgoing data is flushed before accepting more packets once this much is waiting.
+const WRITE_BUFFER_LIMIT: usize = 0x10000;
+
+/// State of an instance of the AW protocol running over an asynchronous stream.
+///
+/// Received packets are read as a [`Stream`], and packets are sent through a [`Sink`] of either
+/// single packets or groups of packets.
+pub struct AWAsyncProtocol<S> {
+    stream: S,
+    read_buf: BytesMut,
+    /// Bytes of `read_buf` from this offset onwards have not been decrypted yet.
+    undecrypted: usize,
+    write_buf: BytesMut,
+    send_cipher: StreamCipher,
+    should_encrypt: bool,
+    recv_cipher: Option<StreamCipher>,
+    /// Set after receiving a stream key response, because nothing after it can be read until the
+    /// key is known.
+    awaiting_key: bool,
+    eof: bool,
+    capture: Option<PacketCapture>,
+    stats: Option<Arc<ProtocolStats>>,
+}
+
+impl<S> AWAsyncProtocol<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Create a new AWAsyncProtocol instance given a stream that has already been established.
+    pub fn new(stream: S) -> Result<Self, StreamCipherError> {
+        Ok(Self {
+            stream,
+            read_buf: BytesMut::new(),
+            undecrypted: 0,
+            write_buf: BytesMut::new(),
+            send_cipher: StreamCipher::from_key(
+                StreamCipherKind::default(),
+                &StreamCipher::new_key(),
+            )?,
+            should_encrypt: false,
+            recv_cipher: None,
+            awaiting_key: false,
+            eof: false,
+            capture: None,
+            stats: None,
+        })
+    }
+
+    /// Record every packet sent and received from now on.
+    pub fn set_capture(&mut self, capture: PacketCapture) {
+        self.capture = Some(capture);
+    }
+
+    /// Count every byte and packet sent and received from now on.
+    pub fn set_stats(&mut self, stats: Arc<ProtocolStats>) {
+        self.stats = Some(stats);
+    }
+
+    /// Get a reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Set which kind of stream cipher is used in both directions. This must be done before
+    /// either key is used, as the send key stays the same.
+    pub fn set_cipher_kind(&mut self, kind: StreamCipherKind) -> Result<(), StreamCipherError> {
+        let key = self.send_cipher.get_initial_random_buffer();
+        self.send_cipher = StreamCipher::from_key(kind, &key)?;
+        Ok(())
+    }
+
+    /// Set the key to receive data (i.e. the key the other end of the connection is using).
+    /// Any data which arrived while waiting for the key is decrypted with it.
+    pub fn set_recv_key(&mut self, key: &[u8]) -> Result<(), StreamCipherError> {
+        let mut cipher = StreamCipher::from_key(self.send_cipher.kind(), key)?;
+        if let Some(pending) = self.read_buf.get_mut(self.undecrypted..) {
+            cipher.decrypt_in_place(pending)?;
+        }
+        self.undecrypted = self.read_buf.len();
+        self.recv_cipher = Some(cipher);
+        self.awaiting_key = false;
+        Ok(())
+    }
+
+    /// Get the key for this side of the connection.
+    pub fn get_send_key(&self) -> Vec<u8> {
+        self.send_cipher.get_initial_random_buffer()
+    }
+
+    /// Specify whether transmitted data should be encrypted.
+    pub fn encrypt_data(&mut self, should: bool) {
+        self.should_encrypt = should;
+    }
+
+    /// Add newly read bytes to the recv buffer, decrypting them if possible.
+    fn receive(&mut self, data: &[u8]) -> io::Result<()> {
+        if let Some(stats) = &self.stats {
+            stats.record_bytes(Direction::Received, data.len());
+        }
+        self.read_buf.extend_from_slice(data);
+        if self.awaiting_key {
+            return Ok(());
+        }
+
+        if let Some(cipher) = &mut self.recv_cipher {
+            if let Some(received) = self.read_buf.get_mut(self.undecrypted..) {
+                cipher.decrypt_in_place(received).map_err(|why| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Failed to decrypt_in_place: {why:?}"),
+                    )
+                })?;
+            }
+        }
+        self.undecrypted = self.read_buf.len();
+        Ok(())
+    }
+
+    /// Get the next packet (if any) from the data which has been received and decrypted.
+    fn decode_next_packet(&mut self) -> io::Result<Option<AWPacket>> {
+        loop {
+            let data = &self.read_buf[..self.undecrypted];
+            match AWPacket::deserialize_check(data) {
+                Ok(serialized_len) => {
+                    let (packet, consumed_bytes) =
+                        AWPacket::deserialize(&data[..serialized_len])
+                            .map_err(|why| io::Error::new(io::ErrorKind::InvalidData, why))?;
+                    self.read_buf.advance(consumed_bytes);
+                    self.undecrypted -= consumed_bytes;
+
+                    if let PacketTypeResult::PacketType(PacketType::StreamKeyResponse) =
+                        packet.get_type()
+                    {
+                        if self.recv_cipher.is_none() {
+                            // Anything sent after the key is encrypted with it, even if it
+                            // arrived together with the key
+                            self.awaiting_key = true;
+                            self.undecrypted = 0;
+                        }
+                    }
+                    if let Some(capture) = &mut self.capture {
+                        capture.record(Direction::Received, &packet);
+                    }
+                    if let Some(stats) = &self.stats {
+                        stats.record_packet(Direction::Received, &packet);
+                    }
+                    return Ok(Some(packet));
+                }
+                Err(DeserializeError::Length) => return Ok(None),
+                Err(DeserializeError::InvalidHeader) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Received a packet with an invalid header",
+                    ));
+                }
+                // Replace the front of the recv buf with the decompressed packet
+                Err(DeserializeError::Compressed(serialized_len)) => {
+                    let decompressed = AWPacket::decompress(&data[..serialized_len])
+                        .map_err(|why| io::Error::new(io::ErrorKind::InvalidData, why))?;
+                    let rest = self.read_buf.split_off(serialized_len);
+                    self.read_buf.clear();
+                    self.read_buf.extend_from_slice(&decompressed);
+                    self.read_buf.unsplit(rest);
+                    self.undecrypted = self.undecrypted - serialized_len + decompressed.len();
+                }
+            }
+        }
+    }
+
+    /// Serialize packets into the send buffer.
+    fn queue_packets(&mut self, packets: &mut [AWPacket]) -> io::Result<()> {
+        let mut bytes_to_send = serialize_outgoing(packets, true).map_err(|why| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Failed to serialize packets: {why:?}"),
+            )
+        })?;
+
+        if let Some(capture) = &mut self.capture {
+            for packet in packets.iter() {
+                capture.record(Direction::Sent, packet);
+            }
+        }
+        if let Some(stats) = &self.stats {
+            for packet in packets.iter() {
+                stats.record_packet(Direction::Sent, packet);
+            }
+        }
+
+        // If the other end of the connection has been given our encryption key, we need to encrypt.
+        if self.should_encrypt {
+            bytes_to_send = self.send_cipher.encrypt(&bytes_to_send).map_err(|why| {
+                io::Error::other(format!(
+                    "Failed to encrypt data with stream cipher: {why:?}"
+                ))
+            })?;
+        }
+
+        if let Some(stats) = &self.stats {
+            stats.record_bytes(Direction::Sent, bytes_to_send.len());
+        }
+        self.write_buf.extend_from_slice(&bytes_to_send);
+        Ok(())
+    }
+
+    fn poll_ready_to_queue(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.write_buf.len() >= WRITE_BUFFER_LIMIT {
+            self.poll_flush_buf(cx)
+        } else {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn poll_flush_buf(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.write_buf.is_empty() {
+            let written = ready!(Pin::new(&mut self.stream).poll_write(cx, &self.write_buf))?;
+            if written == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.write_buf.advance(written);
+        }
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_close_stream(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.poll_flush_buf(cx))?;
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+impl<S> Stream for AWAsyncProtocol<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    type Item = io::Result<AWPacket>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let mut buf = [0u8; 0x8000];
+
+        loop {
+            // Data which has already been received may hold several packets
+            match this.decode_next_packet() {
+                Ok(Some(packet)) => return Poll::Ready(Some(Ok(packet))),
+                Ok(None) => {}
+                Err(why) => return Poll::Ready(Some(Err(why))),
+            }
+
+            if this.eof {
+                return Poll::Ready(None);
+            }
+
+            let mut read_buf = ReadBuf::new(&mut buf);
+            if let Err(why) = ready!(Pin::new(&mut this.stream).poll_read(cx, &mut read_buf)) {
+                return Poll::Ready(Some(Err(why)));
+            }
+
+            if read_buf.filled().is_empty() {
+                this.eof = true;
+                return Poll::Ready(None);
+            }
+
+            if let Err(why) = this.receive(read_buf.filled()) {
+                return Poll::Ready(Some(Err(why)));
+            }
+        }
+    }
+}
+
+impl<S> Sink<AWPacket> for AWAsyncProtocol<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_ready_to_queue(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, packet: AWPacket) -> io::Result<()> {
+        self.get_mut().queue_packets(&mut [packet])
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_flush_buf(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_close_stream(cx)
+    }
+}
+
+/// Packets sent as a group are serialized (and compressed) together.
+impl<S> Sink<Vec<AWPacket>> for AWAsyncProtocol<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_ready_to_queue(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, mut packets: Vec<AWPacket>) -> io::Result<()> {
+        self.get_mut().queue_packets(&mut packets)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_flush_buf(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_close_stream(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AWPacketVar;
+    use futures_util::{SinkExt, StreamExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    #[tokio::test]
+    async fn packets_round_trip() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // Construct a test packet large enough to be compressed.
+        let mut packet = AWPacket::new(PacketType::AvatarAdd);
+        packet.add_var(AWPacketVar::string(1u16, "Hello, World!".to_string()));
+        let data = (0..=255).collect::<Vec<u8>>();
+        packet.add_var(AWPacketVar::data(2u16, data));
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut proto = AWAsyncProtocol::new(stream).unwrap();
+
+            // The client's key arrives before anything it encrypts.
+            let key_packet = proto.next().await.unwrap().unwrap();
+            let key = key_packet.get_data(1u16).unwrap();
+            proto.set_recv_key(&key).unwrap();
+
+            let first = proto.next().await.unwrap().unwrap();
+            let second = proto.next().await.unwrap().unwrap();
+            (first, second)
+        });
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let mut proto = AWAsyncProtocol::new(stream).unwrap();
+
+        let mut key_packet = AWPacket::new(PacketType::StreamKeyResponse);
+        key_packet.add_var(AWPacketVar::data(1u16, proto.get_send_key()));
+        proto.send(key_packet).await.unwrap();
+        proto.encrypt_data(true);
+        proto
+            .send(vec![packet.clone(), packet.clone()])
+            .await
+            .unwrap();
+
+        let (first, second) = server.await.unwrap();
+        assert_eq!(first, packet);
+        assert_eq!(second, packet);
+    }
+}
@@ -0,0 +1,206 @@
+//! Recording every packet of a connection to a file, so sessions can be inspected and replayed.
+//!
+//! A capture file starts with [`CAPTURE_MAGIC`], followed by one record per packet: the time in
+//! microseconds since the unix epoch (u64), the [`Direction`] (u8), the length of the packet (u32)
+//! and the packet as serialized before compression and encryption. Numbers are little endian.
+//! Passwords are redacted before packets are written.
+use crate::{AWPacket, MAX_PACKET_SIZE};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const CAPTURE_MAGIC: &[u8; 8] = b"AWCAP\x00\x00\x01";
+
+/// Which way a packet went, seen from the end of the connection which captured it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Sent = 0,
+    Received = 1,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedPacket {
+    pub time: SystemTime,
+    pub direction: Direction,
+    pub packet: AWPacket,
+}
+
+/// A capture file which is being written.
+pub struct PacketCapture {
+    file: File,
+}
+
+impl PacketCapture {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut file = File::create(path)?;
+        file.write_all(CAPTURE_MAGIC)?;
+        Ok(Self { file })
+    }
+
+    /// Create a capture file in a directory, named after the peer and the time it connected.
+    pub fn create_in(dir: impl AsRef<Path>, peer: SocketAddr) -> io::Result<Self> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let name = format!(
+            "{}_{}_{}.awcap",
+            now.as_millis(),
+            peer.ip().to_string().replace(':', "-"),
+            peer.port()
+        );
+        Self::create(dir.as_ref().join(name))
+    }
+
+    /// Append a packet to the capture. Each record is written at once, so a capture stays
+    /// readable if the process dies.
+    pub fn record(&mut self, direction: Direction, packet: &AWPacket) {
+        if let Err(why) = self.write_record(direction, packet) {
+            log::warn!("Failed to capture {:?}: {why}", packet.get_type());
+        }
+    }
+
+    fn write_record(&mut self, direction: Direction, packet: &AWPacket) -> io::Result<()> {
+        let serialized = packet
+            .redacted()
+            .serialize()
+            .map_err(|why| io::Error::new(io::ErrorKind::InvalidInput, why))?;
+        let micros = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+
+        let mut record = Vec::<u8>::with_capacity(13 + serialized.len());
+        record.write_u64::<LittleEndian>(micros)?;
+        record.write_u8(direction as u8)?;
+        record.write_u32::<LittleEndian>(serialized.len() as u32)?;
+        record.extend(serialized);
+
+        self.file.write_all(&record)
+    }
+}
+
+/// Reads the packets of a capture file in order.
+pub struct CaptureReader<R> {
+    reader: R,
+}
+
+impl CaptureReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> CaptureReader<R> {
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0u8; CAPTURE_MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if &magic != CAPTURE_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Not an AW packet capture",
+            ));
+        }
+
+        Ok(Self { reader })
+    }
+
+    fn read_record(&mut self) -> io::Result<Option<CapturedPacket>> {
+        let micros = match self.reader.read_u64::<LittleEndian>() {
+            Ok(micros) => micros,
+            Err(why) if why.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(why) => return Err(why),
+        };
+        let direction = match self.reader.read_u8()? {
+            0 => Direction::Sent,
+            1 => Direction::Received,
+            other => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid direction {other}"),
+                ))
+            }
+        };
+        let len = self.reader.read_u32::<LittleEndian>()? as usize;
+        if len > MAX_PACKET_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Packet of {len} bytes is too large"),
+            ));
+        }
+        let mut serialized = vec![0u8; len];
+        self.reader.read_exact(&mut serialized)?;
+        let (packet, _) = AWPacket::deserialize(&serialized)
+            .map_err(|why| io::Error::new(io::ErrorKind::InvalidData, why))?;
+
+        Ok(Some(CapturedPacket {
+            time: UNIX_EPOCH + Duration::from_micros(micros),
+            direction,
+            packet,
+        }))
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = io::Result<CapturedPacket>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PacketType, VarID};
+
+    #[test]
+    fn captures_read_back() {
+        let path =
+            std::env::temp_dir().join(format!("aw_core_capture_{}.awcap", std::process::id()));
+        let mut login = AWPacket::new(PacketType::Login);
+        login.add_string(VarID::LoginUsername, "Someone".to_string());
+        login.add_string(VarID::Password, "secret".to_string());
+
+        let mut capture = PacketCapture::create(&path).unwrap();
+        capture.record(Direction::Received, &login);
+        capture.record(Direction::Sent, &AWPacket::new(PacketType::Heartbeat));
+        drop(capture);
+
+        let records = CaptureReader::open(&path)
+            .unwrap()
+            .collect::<io::Result<Vec<CapturedPacket>>>()
+            .unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].direction, Direction::Received);
+        assert_eq!(
+            records[0]
+                .packet
+                .get_string(VarID::LoginUsername)
+                .as_deref(),
+            Some("Someone")
+        );
+        assert_eq!(
+            records[0].packet.get_string(VarID::Password).as_deref(),
+            Some("<redacted>")
+        );
+        assert_eq!(records[1].direction, Direction::Sent);
+        assert!(records[0].time <= records[1].time);
+    }
+
+    #[test]
+    fn oversized_records_are_refused() {
+        let mut record = CAPTURE_MAGIC.to_vec();
+        record.extend(0u64.to_le_bytes());
+        record.push(Direction::Received as u8);
+        record.extend(u32::MAX.to_le_bytes());
+
+        let mut reader = CaptureReader::new(record.as_slice()).unwrap();
+        let error = reader.next().unwrap().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -1,16 +1,66 @@
-use crate::{AWPacket, AWPacketGroup, AWProtocol, ProtocolMessage};
+use crate::{AWPacket, AWPacketGroup, AWProtocol, ProtocolMessage, StreamCipherKind};
 use std::{
     net::SocketAddr,
-    sync::mpsc::{Receiver, Sender},
+    sync::mpsc::{Receiver, RecvTimeoutError, Sender},
+    time::{Duration, Instant},
 };
 
+#[cfg(feature = "tokio")]
+use std::sync::mpsc::SyncSender;
+
+/// Messages which may wait for a connection's task before its client is dropped for not keeping up.
+#[cfg(feature = "tokio")]
+const OUTBOUND_CAPACITY: usize = 4096;
+
+/// Messages which may wait for the main loop before a connection's task stops reading more.
+#[cfg(feature = "tokio")]
+const INBOUND_CAPACITY: usize = 1024;
+
+/// Where messages for the connection are sent, depending on how the connection is serviced.
+#[derive(Debug)]
+enum Outbound {
+    /// The connection is serviced by its own thread.
+    Thread(Sender<ProtocolMessage>),
+    /// The connection is serviced by a task on a tokio runtime, which is told to disconnect through
+    /// `overflow` when it falls too far behind.
+    #[cfg(feature = "tokio")]
+    Task {
+        sender: tokio::sync::mpsc::Sender<ProtocolMessage>,
+        overflow: std::sync::Arc<tokio::sync::Notify>,
+    },
+}
+
+impl Outbound {
+    fn send(&self, message: ProtocolMessage) {
+        match self {
+            Outbound::Thread(sender) => {
+                sender.send(message).ok();
+            }
+            #[cfg(feature = "tokio")]
+            Outbound::Task { sender, overflow } => {
+                if let Err(tokio::sync::mpsc::error::TrySendError::Full(_)) =
+                    sender.try_send(message)
+                {
+                    log::warn!(
+                        "Disconnecting a client which isn't keeping up with what it is sent"
+                    );
+                    overflow.notify_one();
+                }
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct AWConnection {
-    outbound: Sender<ProtocolMessage>,
+    outbound: Outbound,
     inbound: Receiver<ProtocolMessage>,
     a4_send_key: Vec<u8>,
     disconnected: bool,
     addr: SocketAddr,
+    /// Held until the connection is dropped, so that a listener can limit how many are open.
+    #[cfg(feature = "tokio")]
+    _permit: Option<tokio::sync::OwnedSemaphorePermit>,
 }
 
 impl AWConnection {
@@ -22,11 +72,49 @@ impl AWConnection {
         let (outbound, inbound) = protocol.start_process_loop();
 
         Self {
-            outbound,
+            outbound: Outbound::Thread(outbound),
             inbound,
             a4_send_key,
             disconnected: false,
             addr,
+            #[cfg(feature = "tokio")]
+            _permit: None,
+        }
+    }
+
+    /// Service a connection with a task on the current tokio runtime instead of a thread.
+    /// `notify` is signalled whenever there are new messages to [`AWConnection::recv`], and
+    /// `permit` is released once the connection is dropped.
+    #[cfg(feature = "tokio")]
+    pub fn spawn(
+        protocol: crate::AWAsyncProtocol<tokio::net::TcpStream>,
+        notify: SyncSender<()>,
+        permit: tokio::sync::OwnedSemaphorePermit,
+    ) -> Self {
+        let a4_send_key = protocol.get_send_key();
+        let addr = protocol
+            .get_ref()
+            .peer_addr()
+            .expect("All connected peers should have an address");
+        let (sender, outbound_rx) = tokio::sync::mpsc::channel(OUTBOUND_CAPACITY);
+        let (inbound_tx, inbound) = std::sync::mpsc::sync_channel(INBOUND_CAPACITY);
+        let overflow = std::sync::Arc::new(tokio::sync::Notify::new());
+
+        tokio::spawn(service_connection(
+            protocol,
+            outbound_rx,
+            overflow.clone(),
+            inbound_tx,
+            notify,
+        ));
+
+        Self {
+            outbound: Outbound::Task { sender, overflow },
+            inbound,
+            a4_send_key,
+            disconnected: false,
+            addr,
+            _permit: Some(permit),
         }
     }
 
@@ -35,19 +123,21 @@ impl AWConnection {
     }
 
     pub fn send(&self, packet: AWPacket) {
-        self.outbound.send(ProtocolMessage::Packet(packet)).ok();
+        self.outbound.send(ProtocolMessage::Packet(packet));
     }
 
     pub fn send_group(&self, packets: AWPacketGroup) {
         self.outbound
-            .send(ProtocolMessage::PacketGroup(packets.packets))
-            .ok();
+            .send(ProtocolMessage::PacketGroup(packets.packets));
     }
 
     pub fn set_recv_key(&self, key: &[u8]) {
-        self.outbound
-            .send(ProtocolMessage::StreamKey(key.to_vec()))
-            .ok();
+        self.outbound.send(ProtocolMessage::StreamKey(key.to_vec()));
+    }
+
+    /// Choose the stream cipher, before any keys are exchanged.
+    pub fn set_cipher_kind(&self, kind: StreamCipherKind) {
+        self.outbound.send(ProtocolMessage::CipherKind(kind));
     }
 
     pub fn get_send_key(&self) -> Vec<u8> {
@@ -55,7 +145,12 @@ impl AWConnection {
     }
 
     pub fn encrypt_data(&self, should: bool) {
-        self.outbound.send(ProtocolMessage::Encrypt(should)).ok();
+        self.outbound.send(ProtocolMessage::Encrypt(should));
+    }
+
+    /// Wait for the next message, up to a timeout.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<ProtocolMessage> {
+        self.inbound.recv_timeout(timeout).ok()
     }
 
     pub fn recv(&self) -> Vec<ProtocolMessage> {
@@ -67,13 +162,30 @@ impl AWConnection {
     }
 
     pub fn disconnect(&mut self) {
-        self.outbound.send(ProtocolMessage::Disconnect).ok();
+        self.outbound.send(ProtocolMessage::Disconnect);
         self.disconnected = true;
     }
 
     pub fn is_disconnected(&self) -> bool {
         self.disconnected
     }
+
+    /// Wait up to `timeout` for the thread or task servicing the connection to finish, which it
+    /// does once it has sent everything queued before [`AWConnection::disconnect`]. Anything
+    /// received meanwhile is discarded. Returns false if it is still running.
+    pub fn wait_closed(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.inbound.recv_timeout(remaining) {
+                Ok(ProtocolMessage::Disconnect) | Err(RecvTimeoutError::Disconnected) => {
+                    return true
+                }
+                Ok(_) => {}
+                Err(RecvTimeoutError::Timeout) => return false,
+            }
+        }
+    }
 }
 
 impl Drop for AWConnection {
@@ -81,3 +193,78 @@ impl Drop for AWConnection {
         self.disconnect();
     }
 }
+
+/// Pass packets between the connection and its [`AWConnection`] until either end disconnects.
+#[cfg(feature = "tokio")]
+async fn service_connection(
+    mut protocol: crate::AWAsyncProtocol<tokio::net::TcpStream>,
+    mut outbound: tokio::sync::mpsc::Receiver<ProtocolMessage>,
+    overflow: std::sync::Arc<tokio::sync::Notify>,
+    inbound: SyncSender<ProtocolMessage>,
+    notify: SyncSender<()>,
+) {
+    use futures_util::{SinkExt, StreamExt};
+
+    loop {
+        tokio::select! {
+            packet = protocol.next() => match packet {
+                Some(Ok(packet)) => {
+                    if !deliver(&inbound, ProtocolMessage::Packet(packet)).await {
+                        break;
+                    }
+                    notify.try_send(()).ok();
+                }
+                Some(Err(why)) => {
+                    log::debug!("Failed to receive packets: {why}");
+                    break;
+                }
+                None => break,
+            },
+            message = outbound.recv() => {
+                let result = match message {
+                    Some(ProtocolMessage::Packet(packet)) => protocol.send(packet).await,
+                    Some(ProtocolMessage::PacketGroup(packets)) => protocol.send(packets).await,
+                    Some(ProtocolMessage::StreamKey(key)) => {
+                        protocol
+                            .set_recv_key(&key)
+                            .map_err(|why| std::io::Error::other(format!("{why:?}")))
+                    }
+                    Some(ProtocolMessage::Encrypt(should)) => {
+                        protocol.encrypt_data(should);
+                        Ok(())
+                    }
+                    Some(ProtocolMessage::CipherKind(kind)) => protocol
+                        .set_cipher_kind(kind)
+                        .map_err(|why| std::io::Error::other(format!("{why:?}"))),
+                    Some(ProtocolMessage::Disconnect) | None => break,
+                };
+                if let Err(why) = result {
+                    log::debug!("Failed to send packets: {why}");
+                    break;
+                }
+            },
+            _ = overflow.notified() => break,
+        }
+    }
+
+    deliver(&inbound, ProtocolMessage::Disconnect).await;
+    notify.try_send(()).ok();
+}
+
+/// Hand a received message to the main loop, waiting while it is behind so that nothing more is
+/// read from the client until it catches up. Returns false once the main loop is gone.
+#[cfg(feature = "tokio")]
+async fn deliver(inbound: &SyncSender<ProtocolMessage>, mut message: ProtocolMessage) -> bool {
+    use std::sync::mpsc::TrySendError;
+
+    loop {
+        match inbound.try_send(message) {
+            Ok(()) => return true,
+            Err(TrySendError::Full(returned)) => {
+                message = returned;
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            Err(TrySendError::Disconnected(_)) => return false,
+        }
+    }
+}
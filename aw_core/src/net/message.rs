@@ -0,0 +1,272 @@
+//! Typed messages which are read from and written to packets.
+//!
+//! Each message is declared once with [`packet_message!`], mapping its fields to the [`VarID`]s
+//! they are sent as. This generates a struct which can be parsed from an [`AWPacket`] with
+//! `TryFrom` and turned back into one with `From`, so both ends of a connection agree on what a
+//! packet contains.
+use crate::{AWPacket, PacketTypeResult, VarID};
+use std::{error::Error, fmt};
+
+/// Why a packet could not be read as a message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MessageError {
+    /// The packet is of a different type than the message.
+    WrongType(PacketTypeResult),
+    /// A variable of the message is missing, or does not hold a value of the field's type.
+    MissingVar(VarID),
+}
+
+impl Error for MessageError {}
+
+impl fmt::Display for MessageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MessageError::WrongType(packet_type) => {
+                write!(f, "Packet is of the wrong type {packet_type:?}")
+            }
+            MessageError::MissingVar(var_id) => write!(f, "Packet is missing {var_id:?}"),
+        }
+    }
+}
+
+/// A type which can be a field of a message, stored in a single packet variable.
+pub trait MessageField: Sized {
+    /// Read the field from a packet. `None` if the variable is missing or of the wrong type.
+    fn get(packet: &AWPacket, var_id: VarID) -> Option<Self>;
+
+    /// Add the field to a packet.
+    fn add(self, packet: &mut AWPacket, var_id: VarID);
+}
+
+impl MessageField for u8 {
+    fn get(packet: &AWPacket, var_id: VarID) -> Option<Self> {
+        packet.get_byte(var_id)
+    }
+
+    fn add(self, packet: &mut AWPacket, var_id: VarID) {
+        packet.add_byte(var_id, self);
+    }
+}
+
+/// Flags are sent as a byte which is 0 or 1.
+impl MessageField for bool {
+    fn get(packet: &AWPacket, var_id: VarID) -> Option<Self> {
+        packet.get_byte(var_id).map(|byte| byte != 0)
+    }
+
+    fn add(self, packet: &mut AWPacket, var_id: VarID) {
+        packet.add_byte(var_id, self as u8);
+    }
+}
+
+impl MessageField for i32 {
+    fn get(packet: &AWPacket, var_id: VarID) -> Option<Self> {
+        packet.get_int(var_id)
+    }
+
+    fn add(self, packet: &mut AWPacket, var_id: VarID) {
+        packet.add_int(var_id, self);
+    }
+}
+
+impl MessageField for u32 {
+    fn get(packet: &AWPacket, var_id: VarID) -> Option<Self> {
+        packet.get_uint(var_id)
+    }
+
+    fn add(self, packet: &mut AWPacket, var_id: VarID) {
+        packet.add_uint(var_id, self);
+    }
+}
+
+/// Ports and session IDs are sent as ints, but must fit in a u16.
+impl MessageField for u16 {
+    fn get(packet: &AWPacket, var_id: VarID) -> Option<Self> {
+        packet
+            .get_uint(var_id)
+            .and_then(|value| u16::try_from(value).ok())
+    }
+
+    fn add(self, packet: &mut AWPacket, var_id: VarID) {
+        packet.add_uint(var_id, self.into());
+    }
+}
+
+impl MessageField for f32 {
+    fn get(packet: &AWPacket, var_id: VarID) -> Option<Self> {
+        packet.get_float(var_id)
+    }
+
+    fn add(self, packet: &mut AWPacket, var_id: VarID) {
+        packet.add_float(var_id, self);
+    }
+}
+
+impl MessageField for String {
+    fn get(packet: &AWPacket, var_id: VarID) -> Option<Self> {
+        packet.get_string(var_id)
+    }
+
+    fn add(self, packet: &mut AWPacket, var_id: VarID) {
+        packet.add_string(var_id, self);
+    }
+}
+
+impl MessageField for Vec<u8> {
+    fn get(packet: &AWPacket, var_id: VarID) -> Option<Self> {
+        packet.get_data(var_id)
+    }
+
+    fn add(self, packet: &mut AWPacket, var_id: VarID) {
+        packet.add_data(var_id, self);
+    }
+}
+
+/// Optional fields are left out of the packet when they are `None`, and never fail to parse.
+impl<T: MessageField> MessageField for Option<T> {
+    fn get(packet: &AWPacket, var_id: VarID) -> Option<Self> {
+        Some(T::get(packet, var_id))
+    }
+
+    fn add(self, packet: &mut AWPacket, var_id: VarID) {
+        if let Some(value) = self {
+            value.add(packet, var_id);
+        }
+    }
+}
+
+/// Declare messages, each sent as a packet of one type with a variable for every field.
+///
+/// ```
+/// aw_core::packet_message! {
+///     /// Ask the universe to delete a citizen.
+///     pub struct DeleteCitizen: CitizenDelete {
+///         pub citizen_number: u32 = CitizenNumber,
+///     }
+/// }
+/// ```
+///
+/// The type after the colon is a [`PacketType`](crate::PacketType), and the value after each
+/// field a [`VarID`]. Field types must implement [`MessageField`].
+#[macro_export]
+macro_rules! packet_message {
+    ($(
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident: $packet_type:ident {
+            $(
+                $(#[$field_meta:meta])*
+                $field_vis:vis $field:ident: $field_type:ty = $var_id:ident
+            ),* $(,)?
+        }
+    )*) => {$(
+        $(#[$meta])*
+        #[derive(Debug, Clone, PartialEq)]
+        $vis struct $name {
+            $(
+                $(#[$field_meta])*
+                $field_vis $field: $field_type,
+            )*
+        }
+
+        impl $name {
+            pub const PACKET_TYPE: $crate::PacketType = $crate::PacketType::$packet_type;
+        }
+
+        impl TryFrom<&$crate::AWPacket> for $name {
+            type Error = $crate::MessageError;
+
+            fn try_from(packet: &$crate::AWPacket) -> Result<Self, Self::Error> {
+                if packet.get_type() != $crate::PacketTypeResult::PacketType(Self::PACKET_TYPE) {
+                    return Err($crate::MessageError::WrongType(packet.get_type()));
+                }
+
+                Ok(Self {
+                    $(
+                        $field: <$field_type as $crate::MessageField>::get(
+                            packet,
+                            $crate::VarID::$var_id,
+                        )
+                        .ok_or($crate::MessageError::MissingVar($crate::VarID::$var_id))?,
+                    )*
+                })
+            }
+        }
+
+        impl From<$name> for $crate::AWPacket {
+            #[allow(unused_mut, unused_variables)]
+            fn from(message: $name) -> Self {
+                let mut packet = $crate::AWPacket::new($name::PACKET_TYPE);
+                $(
+                    $crate::MessageField::add(message.$field, &mut packet, $crate::VarID::$var_id);
+                )*
+                packet
+            }
+        }
+    )*};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PacketType;
+
+    packet_message! {
+        struct Everything: Address {
+            byte: u8 = WorldRating,
+            flag: bool = WorldFreeEntry,
+            int: i32 = ReasonCode,
+            uint: u32 = CitizenNumber,
+            port: u16 = WorldPort,
+            float: f32 = CAVTemplate,
+            string: String = CitizenName,
+            data: Vec<u8> = EncryptionKey,
+            missing: Option<String> = CitizenEmail,
+            present: Option<u32> = WorldUsers,
+        }
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let message = Everything {
+            byte: 3,
+            flag: true,
+            int: -1,
+            uint: u32::MAX,
+            port: 6670,
+            float: 1.5,
+            string: "Hello".to_string(),
+            data: vec![1, 2, 3],
+            missing: None,
+            present: Some(4),
+        };
+
+        let packet = AWPacket::from(message.clone());
+        assert_eq!(packet.get_vars().len(), 9);
+
+        let (packet, _) = AWPacket::deserialize(&packet.serialize().unwrap()).unwrap();
+        assert_eq!(Everything::try_from(&packet), Ok(message));
+    }
+
+    #[test]
+    fn messages_need_their_vars() {
+        let mut packet = AWPacket::new(PacketType::Address);
+        assert_eq!(
+            Everything::try_from(&packet),
+            Err(MessageError::MissingVar(VarID::WorldRating))
+        );
+
+        packet.add_string(VarID::WorldRating, "3".to_string());
+        assert_eq!(
+            Everything::try_from(&packet),
+            Err(MessageError::MissingVar(VarID::WorldRating))
+        );
+
+        let packet = AWPacket::new(PacketType::Login);
+        assert_eq!(
+            Everything::try_from(&packet),
+            Err(MessageError::WrongType(PacketTypeResult::PacketType(
+                PacketType::Login
+            )))
+        );
+    }
+}
@@ -9,3 +9,20 @@ pub use packet_var::*;
 
 mod connection;
 pub use connection::*;
+
+mod version;
+pub use version::*;
+
+mod capture;
+pub use capture::*;
+
+mod message;
+pub use message::*;
+
+mod stats;
+pub use stats::*;
+
+#[cfg(feature = "tokio")]
+mod async_protocol;
+#[cfg(feature = "tokio")]
+pub use async_protocol::*;
@@ -1,13 +1,24 @@
 //! Packet (de)serialization for AW
-use crate::{net::packet_var::AWPacketVar, PacketData};
+use crate::{net::packet_var::AWPacketVar, PacketData, VarID};
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use flate2::read::ZlibDecoder;
 use flate2::write::ZlibEncoder;
 use flate2::Compression;
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
+use std::fmt;
 use std::io::{Cursor, Read, Write};
 
+/// Packets carry their serialized length in a u16, so none is larger than this.
+pub const MAX_PACKET_SIZE: usize = u16::MAX as usize;
+
+/// Written in place of passwords by [`AWPacket::redacted`].
+const REDACTED: &str = "<redacted>";
+
+/// How many times larger than its compressed size a packet may become when decompressed.
+/// Real packets come nowhere close, but a zip bomb would.
+const MAX_DECOMPRESSION_RATIO: u64 = 128;
+
 /// Packet which can be sent over an AWProtocol.
 #[derive(Debug, PartialEq, Clone)]
 pub struct AWPacket {
@@ -33,6 +44,14 @@ impl AWPacket {
         self.opcode
     }
 
+    pub fn get_header_0(&self) -> u16 {
+        self.header_0
+    }
+
+    pub fn get_header_1(&self) -> u16 {
+        self.header_1
+    }
+
     pub fn set_header_0(&mut self, header_0: u16) {
         self.header_0 = header_0;
     }
@@ -56,6 +75,28 @@ impl AWPacket {
         &self.vars
     }
 
+    /// A copy of the packet with its passwords replaced, which can be logged or captured.
+    pub fn redacted(&self) -> AWPacket {
+        let mut packet = self.clone();
+        for var in &mut packet.vars {
+            if !VarID::from_u16(var.id).is_some_and(VarID::is_secret) {
+                continue;
+            }
+            var.data = match var.data {
+                PacketData::String(_) => PacketData::String(REDACTED.to_string()),
+                PacketData::Data(_) => PacketData::Data(REDACTED.as_bytes().to_vec()),
+                _ => PacketData::Unknown(Vec::new()),
+            };
+        }
+        packet
+    }
+
+    /// Remove every variable with the given ID from the packet.
+    pub fn remove_var(&mut self, var_id: impl Into<u16>) {
+        let var_id: u16 = var_id.into();
+        self.vars.retain(|var| var.get_var_id() != var_id);
+    }
+
     pub fn add_byte(&mut self, id: impl Into<u16>, value: u8) {
         self.add_var(AWPacketVar::byte(id.into(), value));
     }
@@ -175,7 +216,7 @@ impl AWPacket {
     pub fn serialize(&self) -> Result<Vec<u8>, String> {
         let serialize_len = self.serialize_len()?;
 
-        if serialize_len > u16::MAX.into() {
+        if serialize_len > MAX_PACKET_SIZE {
             return Err(format!("Serializing packet too large: {serialize_len}"));
         }
 
@@ -245,12 +286,21 @@ impl AWPacket {
             .get(TagHeader::length()..)
             .ok_or("Data not long enough to do any decompression".to_string())?;
 
-        let mut decoder = ZlibDecoder::new(compressed_data);
+        // Read one byte past the limit to tell whether the data would expand beyond it
+        let limit = (compressed_data.len() as u64).saturating_mul(MAX_DECOMPRESSION_RATIO);
+        let mut decoder = ZlibDecoder::new(compressed_data).take(limit.saturating_add(1));
         let mut decompressed_bytes = Vec::<u8>::new();
-        match decoder.read_to_end(&mut decompressed_bytes) {
-            Ok(_) => Ok(decompressed_bytes),
-            Err(_) => Err("Failed to decode compressed data".to_string()),
+        if decoder.read_to_end(&mut decompressed_bytes).is_err() {
+            return Err("Failed to decode compressed data".to_string());
         }
+
+        if decompressed_bytes.len() as u64 > limit {
+            return Err(format!(
+                "Compressed data expands more than {MAX_DECOMPRESSION_RATIO} times"
+            ));
+        }
+
+        Ok(decompressed_bytes)
     }
 
     /// Decode a packet and return an instance if successful.
@@ -269,7 +319,10 @@ impl AWPacket {
             .checked_add(consumed)
             .ok_or("Consumed too much data while deserializing".to_string())?;
 
-        let mut vars = Vec::<AWPacketVar>::with_capacity(header.var_count as usize);
+        // Every variable takes at least 4 bytes, so don't trust a var_count the data can't hold
+        let max_var_count = data.len() / 4;
+        let mut vars =
+            Vec::<AWPacketVar>::with_capacity(max_var_count.min(header.var_count as usize));
 
         for _ in 0..header.var_count {
             let (var, consumed) = AWPacketVar::deserialize(data)?;
@@ -480,6 +533,38 @@ impl TagHeader {
     }
 }
 
+/// Shows the type of the packet and each of its variables by name, one per line. Passwords are
+/// shown as redacted, so packets can be logged.
+impl fmt::Display for AWPacket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.opcode {
+            PacketTypeResult::PacketType(packet_type) => write!(f, "{packet_type:?}")?,
+            PacketTypeResult::Unknown(_) => write!(f, "Unknown")?,
+        }
+        write!(
+            f,
+            " ({}) headers {}/{}",
+            i16::from(self.opcode),
+            self.header_0,
+            self.header_1
+        )?;
+
+        for var in &self.vars {
+            match VarID::from_u16(var.id) {
+                Some(var_id) if var_id.is_secret() => {
+                    write!(f, "\n    {var_id:?} ({}) = {REDACTED}", var.id)?;
+                    continue;
+                }
+                Some(var_id) => write!(f, "\n    {var_id:?}")?,
+                None => write!(f, "\n    Unknown")?,
+            }
+            write!(f, " ({}) = {:?}", var.id, var.data)?;
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 pub enum DeserializeError {
     Length,
@@ -612,7 +697,6 @@ pub enum PacketType {
     CavTemplateDelete = 126,
     WorldCAVDefinitionChange = 127,
     CAVGet = 128,
-
     CavDelete = 130,
     WorldCAVResult = 131,
     MoverAdd = 144,
@@ -664,6 +748,7 @@ impl From<PacketTypeResult> for i16 {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     pub fn test_serialize() {
@@ -674,4 +759,103 @@ mod tests {
         let (deserialized, _) = AWPacket::deserialize(&serialized).unwrap();
         assert!(packet == deserialized);
     }
+
+    #[test]
+    pub fn test_compression() {
+        let mut packet = AWPacket::new(PacketType::Address);
+        packet.add_string(VarID::CitizenName, "Hello".repeat(100));
+        let serialized = packet.serialize().unwrap();
+
+        let compressed = AWPacket::compress_if_needed(&serialized).unwrap();
+        assert!(compressed.len() < serialized.len());
+        assert_eq!(AWPacket::decompress(&compressed).unwrap(), serialized);
+    }
+
+    #[test]
+    pub fn test_decompression_bomb() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&vec![0u8; 16 * 1024 * 1024]).unwrap();
+        let mut data = vec![0u8; TagHeader::length()];
+        data.extend(encoder.finish().unwrap());
+
+        assert!(AWPacket::decompress(&data).is_err());
+    }
+
+    #[test]
+    pub fn test_display_redacts_passwords() {
+        let mut packet = AWPacket::new(PacketType::Login);
+        packet.add_string(VarID::LoginUsername, "Alice".to_string());
+        packet.add_string(VarID::Password, "hunter2".to_string());
+
+        let shown = packet.to_string();
+        assert!(shown.contains("Alice"));
+        assert!(shown.contains(REDACTED));
+        assert!(!shown.contains("hunter2"));
+    }
+
+    fn packet_data() -> impl Strategy<Value = PacketData> {
+        // Strings are latin-1 on the wire and end at the first trailing null
+        let latin1 = proptest::collection::vec(1u8..=255, 0..0xFFF)
+            .prop_map(|bytes| bytes.into_iter().map(char::from).collect::<String>());
+
+        prop_oneof![
+            any::<u8>().prop_map(PacketData::Byte),
+            any::<i32>().prop_map(PacketData::Int),
+            any::<f32>()
+                .prop_filter("NaN never equals itself", |x| !x.is_nan())
+                .prop_map(PacketData::Float),
+            latin1.prop_map(PacketData::String),
+            proptest::collection::vec(any::<u8>(), 0..=0xFFF).prop_map(PacketData::Data),
+            proptest::collection::vec(any::<u8>(), 0..=0xFFF).prop_map(PacketData::Unknown),
+        ]
+    }
+
+    fn packet() -> impl Strategy<Value = AWPacket> {
+        let var = (any::<u16>(), packet_data()).prop_map(|(id, data)| AWPacketVar::new(id, data));
+
+        (
+            any::<i16>(),
+            any::<u16>(),
+            any::<u16>(),
+            proptest::collection::vec(var, 0..12),
+        )
+            .prop_map(|(opcode, header_0, header_1, vars)| AWPacket {
+                vars,
+                opcode: match PacketType::from_i16(opcode) {
+                    Some(packet_type) => PacketTypeResult::PacketType(packet_type),
+                    None => PacketTypeResult::Unknown(opcode),
+                },
+                header_0,
+                header_1,
+            })
+            .prop_filter("Too large to serialize", |packet| {
+                packet.serialize_len().unwrap() <= u16::MAX.into()
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn serialized_packets_deserialize(packet in packet()) {
+            let serialized = packet.serialize().unwrap();
+            let (deserialized, consumed) = AWPacket::deserialize(&serialized).unwrap();
+
+            prop_assert_eq!(consumed, serialized.len());
+            prop_assert_eq!(deserialized, packet);
+        }
+
+        #[test]
+        fn arbitrary_bytes_do_not_panic(data in proptest::collection::vec(any::<u8>(), 0..2048)) {
+            AWPacket::deserialize_check(&data).ok();
+            AWPacket::deserialize(&data).ok();
+            AWPacket::decompress(&data).ok();
+        }
+
+        #[test]
+        fn truncated_packets_are_rejected(packet in packet(), cut in any::<prop::sample::Index>()) {
+            let serialized = packet.serialize().unwrap();
+            let truncated = &serialized[..cut.index(serialized.len())];
+
+            prop_assert!(AWPacket::deserialize(truncated).is_err());
+        }
+    }
 }
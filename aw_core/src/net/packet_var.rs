@@ -66,6 +66,9 @@ pub struct AWPacketVar {
 pub enum VarID {
     VolumeSerial = 6,
 
+    /// Digest of the password, which AW 6 sends instead of the password
+    PasswordDigest = 17,
+
     IdentifyUserIP = 26,
 
     PositionNorth = 36,
@@ -177,6 +180,22 @@ impl From<VarID> for u16 {
     }
 }
 
+impl VarID {
+    /// Whether the variable holds a password or a password digest, which must not be written to
+    /// logs or captures.
+    pub fn is_secret(self) -> bool {
+        matches!(
+            self,
+            VarID::PasswordDigest
+                | VarID::CitizenPassword
+                | VarID::CitizenPrivilegePassword
+                | VarID::WorldLicensePassword
+                | VarID::Password
+                | VarID::PrivilegePassword
+        )
+    }
+}
+
 impl AWPacketVar {
     pub fn new(var_id: impl Into<u16>, packet_data: PacketData) -> Self {
         Self {
@@ -1,31 +1,29 @@
 //! Networking protocol implementation
 use crate::net::packet::{AWPacket, DeserializeError, PacketType};
-use crate::{AWCryptStream, StreamCipherError};
-use crate::{PacketTypeResult, ReasonCode};
+use crate::{Direction, PacketCapture, PacketTypeResult, ProtocolStats, ReasonCode};
+use crate::{StreamCipher, StreamCipherError, StreamCipherKind};
 use std::io::{self, Read, Write};
 use std::net::{SocketAddr, TcpStream};
 use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
-#[cfg(feature = "stream_cipher_aes")]
-type StreamCipherType = crate::AWCryptAES;
-#[cfg(feature = "stream_cipher_rc4")]
-type StreamCipherType = crate::AWCryptA4;
-
 /// State of an instance of the AW protocol.
 pub struct AWProtocol {
     stream: TcpStream,
     data: Vec<u8>,
-    send_cipher: StreamCipherType,
+    send_cipher: StreamCipher,
     should_encrypt: bool,
-    recv_cipher: Option<StreamCipherType>,
+    recv_cipher: Option<StreamCipher>,
     dead: bool,
     inbound_packets: Sender<ProtocolMessage>,
     outbound_packets: Receiver<ProtocolMessage>,
     other_inbound_packets: Option<Receiver<ProtocolMessage>>,
     other_outbound_packets: Option<Sender<ProtocolMessage>>,
     last_packet_type: Option<PacketType>,
+    capture: Option<PacketCapture>,
+    stats: Option<Arc<ProtocolStats>>,
 }
 
 impl AWProtocol {
@@ -37,11 +35,16 @@ impl AWProtocol {
         Ok(Self {
             stream,
             data: Vec::new(),
-            send_cipher: StreamCipherType::new()?,
+            send_cipher: StreamCipher::from_key(
+                StreamCipherKind::default(),
+                &StreamCipher::new_key(),
+            )?,
             should_encrypt: false,
             recv_cipher: None,
             dead: false,
             last_packet_type: None,
+            capture: None,
+            stats: None,
             inbound_packets: inbound_packets_tx,
             outbound_packets: outbound_packets_rx,
             other_inbound_packets: Some(inbound_packets_rx),
@@ -54,9 +57,27 @@ impl AWProtocol {
         self.stream.peer_addr()
     }
 
+    /// Record every packet sent and received from now on.
+    pub fn set_capture(&mut self, capture: PacketCapture) {
+        self.capture = Some(capture);
+    }
+
+    /// Count every byte and packet sent and received from now on.
+    pub fn set_stats(&mut self, stats: Arc<ProtocolStats>) {
+        self.stats = Some(stats);
+    }
+
+    /// Set which kind of stream cipher is used in both directions. This must be done before
+    /// either key is used, as the send key stays the same.
+    pub fn set_cipher_kind(&mut self, kind: StreamCipherKind) -> Result<(), StreamCipherError> {
+        let key = self.send_cipher.get_initial_random_buffer();
+        self.send_cipher = StreamCipher::from_key(kind, &key)?;
+        Ok(())
+    }
+
     /// Set the key to receive data (i.e. the key the other end of the connection is using).
     pub fn set_recv_key(&mut self, key: &[u8]) -> Result<(), StreamCipherError> {
-        self.recv_cipher = Some(StreamCipherType::from_key(key)?);
+        self.recv_cipher = Some(StreamCipher::from_key(self.send_cipher.kind(), key)?);
         Ok(())
     }
 
@@ -89,38 +110,19 @@ impl AWProtocol {
 
     /// Send packets.
     pub fn send(&mut self, packets: &mut [AWPacket], compression: bool) -> Result<(), ReasonCode> {
-        for packet in packets.iter_mut() {
-            let PacketTypeResult::PacketType(packet_type) = packet.get_type() else {
-                continue;
-            };
-            match packet_type {
-                PacketType::PublicKeyResponse
-                | PacketType::StreamKeyResponse
-                | PacketType::Attributes
-                // When you are the server, this header should be 1, but
-                // when you are the client, this header should be 2, or else
-                // a normal universe server will return RC_MUST_UPGRADE
-                // | PacketType::Login
-                | PacketType::Tunnel => {
-                    packet.set_header_1(1);
-                }
-                _ => {}
+        let mut bytes_to_send = serialize_outgoing(packets, compression)?;
+
+        if let Some(capture) = &mut self.capture {
+            for packet in packets.iter() {
+                capture.record(Direction::Sent, packet);
             }
         }
-
-        // Serialize one or more packets
-        let mut serialized_bytes = Vec::<u8>::new();
-        for packet in packets.iter() {
-            serialized_bytes.extend(packet.serialize().map_err(|_| ReasonCode::SendFailed)?);
+        if let Some(stats) = &self.stats {
+            for packet in packets.iter() {
+                stats.record_packet(Direction::Sent, packet);
+            }
         }
 
-        // Try to compress the serialized packet
-        let mut bytes_to_send = if compression {
-            AWPacket::compress_if_needed(&serialized_bytes).map_err(|_| ReasonCode::SendFailed)?
-        } else {
-            serialized_bytes
-        };
-
         // If the other end of the connection has been given our encryption key, we need to encrypt.
         if self.should_encrypt {
             bytes_to_send = self.send_cipher.encrypt(&bytes_to_send).map_err(|why| {
@@ -133,6 +135,9 @@ impl AWProtocol {
         self.stream
             .write_all(&bytes_to_send)
             .map_err(|_| ReasonCode::SendFailed)?;
+        if let Some(stats) = &self.stats {
+            stats.record_bytes(Direction::Sent, bytes_to_send.len());
+        }
 
         Ok(())
     }
@@ -153,6 +158,9 @@ impl AWProtocol {
         let Some(read_buffer) = buf.get_mut(..bytes_read) else {
             return Err(format!("Received a nonsense number of bytes: {bytes_read}"));
         };
+        if let Some(stats) = &self.stats {
+            stats.record_bytes(Direction::Received, bytes_read);
+        }
 
         // Decrypt incoming bytes if we have a key.
         if let Some(cipher) = &mut self.recv_cipher {
@@ -228,7 +236,15 @@ impl AWProtocol {
         loop {
             match self.check_and_deserialize_packet() {
                 // If we get a packet, return it
-                Ok(Some(packet)) => return Some(packet),
+                Ok(Some(packet)) => {
+                    if let Some(capture) = &mut self.capture {
+                        capture.record(Direction::Received, &packet);
+                    }
+                    if let Some(stats) = &self.stats {
+                        stats.record_packet(Direction::Received, &packet);
+                    }
+                    return Some(packet);
+                }
                 // If there is an error that prevented getting a packet, stop
                 Err(_) => return None,
                 // If there was no error but no packet, try again
@@ -302,7 +318,7 @@ impl AWProtocol {
                 self.send_or_kill(&mut packets, true);
             }
             ProtocolMessage::StreamKey(key) => {
-                match StreamCipherType::from_key(&key) {
+                match StreamCipher::from_key(self.send_cipher.kind(), &key) {
                     Ok(mut stream_cipher) => {
                         // There may be data that has already been sent, so we need to decrypt it now.
                         if let Err(why) = stream_cipher.decrypt_in_place(&mut self.data) {
@@ -318,6 +334,11 @@ impl AWProtocol {
             ProtocolMessage::Encrypt(should) => {
                 self.encrypt_data(should);
             }
+            ProtocolMessage::CipherKind(kind) => {
+                if self.set_cipher_kind(kind).is_err() {
+                    self.kill();
+                }
+            }
             ProtocolMessage::Disconnect => {
                 self.kill();
             }
@@ -373,12 +394,51 @@ impl AWProtocol {
     }
 }
 
+/// Serialize packets to be sent, compressing them if requested.
+pub(crate) fn serialize_outgoing(
+    packets: &mut [AWPacket],
+    compression: bool,
+) -> Result<Vec<u8>, ReasonCode> {
+    for packet in packets.iter_mut() {
+        let PacketTypeResult::PacketType(packet_type) = packet.get_type() else {
+            continue;
+        };
+        match packet_type {
+            PacketType::PublicKeyResponse
+            | PacketType::StreamKeyResponse
+            | PacketType::Attributes
+            // When you are the server, this header should be 1, but
+            // when you are the client, this header should be 2, or else
+            // a normal universe server will return RC_MUST_UPGRADE
+            // | PacketType::Login
+            | PacketType::Tunnel => {
+                packet.set_header_1(1);
+            }
+            _ => {}
+        }
+    }
+
+    // Serialize one or more packets
+    let mut serialized_bytes = Vec::<u8>::new();
+    for packet in packets.iter() {
+        serialized_bytes.extend(packet.serialize().map_err(|_| ReasonCode::SendFailed)?);
+    }
+
+    // Try to compress the serialized packet
+    if compression {
+        AWPacket::compress_if_needed(&serialized_bytes).map_err(|_| ReasonCode::SendFailed)
+    } else {
+        Ok(serialized_bytes)
+    }
+}
+
 #[derive(Debug)]
 pub enum ProtocolMessage {
     Packet(AWPacket),
     PacketGroup(Vec<AWPacket>),
     Disconnect,
     StreamKey(Vec<u8>),
+    CipherKind(StreamCipherKind),
     Encrypt(bool),
 }
 
@@ -393,7 +453,8 @@ mod tests {
 
     #[test]
     pub fn test1() {
-        let listener = TcpListener::bind("0.0.0.0:1234").unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
 
         let (tx, rx) = channel::<AWPacket>();
 
@@ -413,7 +474,7 @@ mod tests {
             drop(listener);
         });
 
-        let stream = TcpStream::connect("127.0.0.1:1234").unwrap();
+        let stream = TcpStream::connect(addr).unwrap();
         let mut proto = AWProtocol::new(stream).unwrap();
 
         // Construct a test packet.
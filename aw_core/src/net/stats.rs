@@ -0,0 +1,85 @@
+//! Counting the traffic of connections, so servers can report how busy they are.
+use crate::{AWPacket, Direction};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+/// Bytes and packets which went through any number of connections. One instance is usually
+/// shared by every connection of a server.
+#[derive(Debug, Default)]
+pub struct ProtocolStats {
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    /// Number of packets by direction and opcode. Opcodes are kept as numbers so packet types
+    /// this crate doesn't know are counted as well.
+    packets: Mutex<HashMap<(Direction, i16), u64>>,
+}
+
+impl ProtocolStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count bytes as they are written to or read from the socket, after compression and
+    /// encryption.
+    pub fn record_bytes(&self, direction: Direction, bytes: usize) {
+        let counter = match direction {
+            Direction::Sent => &self.bytes_sent,
+            Direction::Received => &self.bytes_received,
+        };
+        counter.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_packet(&self, direction: Direction, packet: &AWPacket) {
+        let opcode = i16::from(packet.get_type());
+        let mut packets = self.packets.lock().unwrap_or_else(PoisonError::into_inner);
+        *packets.entry((direction, opcode)).or_default() += 1;
+    }
+
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes_received.load(Ordering::Relaxed)
+    }
+
+    /// Number of packets counted so far by direction and opcode, in no particular order.
+    pub fn packets(&self) -> Vec<(Direction, i16, u64)> {
+        self.packets
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|(&(direction, opcode), &count)| (direction, opcode, count))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PacketType;
+
+    #[test]
+    fn packets_are_counted_by_direction_and_type() {
+        let stats = ProtocolStats::new();
+        let heartbeat = AWPacket::new(PacketType::Heartbeat);
+        stats.record_packet(Direction::Sent, &heartbeat);
+        stats.record_packet(Direction::Sent, &heartbeat);
+        stats.record_packet(Direction::Received, &heartbeat);
+        stats.record_bytes(Direction::Received, 12);
+
+        let mut packets = stats.packets();
+        packets.sort_by_key(|&(direction, _, _)| direction as u8);
+        let opcode = PacketType::Heartbeat as i16;
+        assert_eq!(
+            packets,
+            vec![
+                (Direction::Sent, opcode, 2),
+                (Direction::Received, opcode, 1)
+            ]
+        );
+        assert_eq!(stats.bytes_received(), 12);
+        assert_eq!(stats.bytes_sent(), 0);
+    }
+}
@@ -0,0 +1,43 @@
+//! Differences between versions of the protocol
+use crate::{AWPacket, StreamCipherKind};
+use serde::{Deserialize, Serialize};
+
+/// Version of the protocol spoken by a client, which decides the stream cipher and how the
+/// client logs in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ProtocolVersion {
+    /// AW 4 and 5, which use RC4 and send plaintext passwords
+    #[default]
+    V4,
+    /// AW 6, which uses AES and sends password digests
+    V6,
+}
+
+impl ProtocolVersion {
+    /// Guess the version of a client from the first packet it sends.
+    /// AW 6 is expected to give its packets different header values than earlier versions. This
+    /// has not been checked against real browsers, so servers which meet a browser it guesses
+    /// wrong for should be told which version their clients speak instead.
+    pub fn from_handshake(packet: &AWPacket) -> Self {
+        if packet.get_header_0() >= 2 {
+            ProtocolVersion::V6
+        } else {
+            ProtocolVersion::V4
+        }
+    }
+
+    /// Set the headers of a packet sent by a client of this version.
+    pub fn set_client_headers(self, packet: &mut AWPacket) {
+        if self == ProtocolVersion::V6 {
+            packet.set_header_0(2);
+            packet.set_header_1(3);
+        }
+    }
+
+    pub fn cipher_kind(self) -> StreamCipherKind {
+        match self {
+            ProtocolVersion::V4 => StreamCipherKind::A4,
+            ProtocolVersion::V6 => StreamCipherKind::AES,
+        }
+    }
+}
@@ -1,4 +1,6 @@
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+use num_derive::FromPrimitive;
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, FromPrimitive)]
 pub enum ReasonCode {
     Success = 0,
     CitizenshipExpired = 1,
@@ -1,11 +1,12 @@
 use serde::{Deserialize, Serialize};
 
 /// Internal (sqlite, local, on-disk, self contained) database or external (server) database
-#[derive(Deserialize, Serialize, Debug, Default)]
+#[derive(Deserialize, Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum DatabaseType {
     External,
     #[default]
     Internal,
+    Postgres,
 }
 
 /// Config for sqlite, the local database solution
@@ -24,10 +25,35 @@ pub struct MysqlConfig {
     pub database: String,
 }
 
+/// Configuation section for the postgres connection
+#[derive(Deserialize, Serialize, Debug)]
+pub struct PostgresConfig {
+    pub hostname: String,
+    pub port: u16,
+    pub username: String,
+    pub password: String,
+    pub database: String,
+}
+
+impl Default for PostgresConfig {
+    fn default() -> Self {
+        Self {
+            hostname: "127.0.0.1".to_string(),
+            port: 5432,
+            username: "postgres".to_string(),
+            password: "password".to_string(),
+            database: "aworld_universe".to_string(),
+        }
+    }
+}
+
 /// Configuration for the database, whether external or internal
 #[derive(Deserialize, Serialize, Debug)]
 pub struct DatabaseConfig {
     pub database_type: DatabaseType,
     pub mysql_config: MysqlConfig,
     pub sqlite_config: SqliteConfig,
+    /// Config files written before Postgres was supported don't have this section
+    #[serde(default)]
+    pub postgres_config: PostgresConfig,
 }
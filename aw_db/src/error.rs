@@ -4,6 +4,8 @@ pub enum DatabaseOpenError {
     ConnectionPoolFailure(#[from] mysql::Error),
     #[error("Couldn't open the Sqlite database: {0}")]
     SqliteOpenFailure(#[from] rusqlite::Error),
+    #[error("Couldn't connect to the Postgres server: {0}")]
+    PostgresConnectFailure(#[from] postgres::Error),
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -12,6 +14,24 @@ pub enum DatabaseExecError {
     MysqlError(#[from] mysql::Error),
     #[error("Encountered sqlite error {0}")]
     SqliteError(#[from] rusqlite::Error),
+    #[error("Encountered postgres error {0}")]
+    PostgresError(#[from] postgres::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum RowError {
+    #[error("Column {0} does not exist")]
+    Missing(String),
+    #[error("Column {0} is NULL")]
+    Null(String),
+    #[error("Column {column} holds {found}, which can't be read as {expected}")]
+    Type {
+        column: String,
+        found: &'static str,
+        expected: &'static str,
+    },
+    #[error("Column {0} has a type which is not supported")]
+    Unsupported(String),
 }
 
 #[derive(Debug, Copy, Clone)]
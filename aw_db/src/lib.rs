@@ -1,23 +1,38 @@
 mod config;
 mod error;
 mod mysql_wrap;
+mod postgres_wrap;
 mod row;
 mod sqlite_wrap;
+mod stats;
+mod transaction;
+
+use std::sync::{Mutex, MutexGuard, TryLockError};
+use std::time::Instant;
 
 use mysql_wrap::mysql_exec;
+use postgres_wrap::postgres_exec;
+
+pub use config::{DatabaseConfig, DatabaseType, MysqlConfig, PostgresConfig, SqliteConfig};
+pub use error::{DatabaseExecError, DatabaseOpenError, DatabaseResult, RowError};
+pub use row::{ColumnValue, FromColumn, FromRow, Row};
+pub use stats::{DatabaseStats, LATENCY_BUCKETS};
+pub use transaction::{PostgresTransaction, Transaction};
 
-pub use config::{DatabaseConfig, DatabaseType, MysqlConfig, SqliteConfig};
-pub use error::{DatabaseExecError, DatabaseOpenError, DatabaseResult};
-pub use row::Row;
+pub struct Database {
+    backend: Backend,
+    stats: DatabaseStats,
+}
 
-pub enum Database {
+enum Backend {
     External { pool: mysql::Pool },
     Internal { conn: rusqlite::Connection },
+    Postgres { client: Mutex<postgres::Client> },
 }
 
 impl Database {
     pub fn new(config: DatabaseConfig) -> Result<Self, DatabaseOpenError> {
-        let db = match config.database_type {
+        let backend = match config.database_type {
             DatabaseType::External => {
                 let username = &config.mysql_config.username;
                 let password = &config.mysql_config.password;
@@ -27,16 +42,32 @@ impl Database {
                 let uri =
                     format!("mysql://{username}:{password}@{hostname}:{port}/{database_name}");
 
-                Self::External {
+                Backend::External {
                     pool: mysql::Pool::new(uri.as_str())?,
                 }
             }
-            DatabaseType::Internal => Self::Internal {
+            DatabaseType::Internal => Backend::Internal {
                 conn: rusqlite::Connection::open(config.sqlite_config.path)?,
             },
+            DatabaseType::Postgres => {
+                let client = postgres::Config::new()
+                    .host(&config.postgres_config.hostname)
+                    .port(config.postgres_config.port)
+                    .user(&config.postgres_config.username)
+                    .password(&config.postgres_config.password)
+                    .dbname(&config.postgres_config.database)
+                    .connect(postgres::NoTls)?;
+
+                Backend::Postgres {
+                    client: Mutex::new(client),
+                }
+            }
         };
 
-        Ok(db)
+        Ok(Self {
+            backend,
+            stats: DatabaseStats::default(),
+        })
     }
 
     #[must_use]
@@ -51,36 +82,148 @@ impl Database {
             &parameters
         );
 
-        let res = match &self {
-            Database::External { pool } => mysql_exec(pool, statement.as_ref(), parameters.clone()),
-            Database::Internal { conn } => {
+        let start = Instant::now();
+        let res = match &self.backend {
+            Backend::External { pool } => mysql_exec(pool, statement.as_ref(), parameters.clone()),
+            Backend::Internal { conn } => {
                 sqlite_wrap::sqlite_exec(conn, statement.as_ref(), parameters.clone())
             }
+            Backend::Postgres { client } => match lock(client) {
+                Some(mut client) => {
+                    postgres_exec(&mut client, statement.as_ref(), parameters.clone())
+                }
+                None => return DatabaseResult::DatabaseError,
+            },
         };
+        self.stats.record(start.elapsed(), res.is_err());
 
-        match res {
-            Ok(rows) => DatabaseResult::Ok(rows),
-            Err(why) => {
-                log::error!("A database execution failed: {why:?}");
-                log::error!("Statement: {:?}", statement.as_ref());
-                log::error!("Parameters: {parameters:?}");
+        exec_result(res, statement.as_ref(), &parameters)
+    }
+
+    /// Number, duration and failures of the statements executed so far, including those executed in
+    /// transactions.
+    pub fn stats(&self) -> &DatabaseStats {
+        &self.stats
+    }
+
+    /// Runs `f` inside a transaction. The transaction is committed if `f` returns
+    /// `DatabaseResult::Ok` and rolled back otherwise, including when `f` panics.
+    ///
+    /// Every statement in `f` must be executed through the given [`Transaction`].
+    /// Statements executed through the `Database` itself are not part of the transaction, and on
+    /// Postgres, whose only connection is held by the transaction, they fail instead of running.
+    pub fn transaction<T>(
+        &self,
+        f: impl FnOnce(&mut Transaction) -> DatabaseResult<T>,
+    ) -> DatabaseResult<T> {
+        let begin: Result<Transaction, DatabaseExecError> = match &self.backend {
+            Backend::External { pool } => pool
+                .start_transaction(mysql::TxOpts::default())
+                .map(|tx| Transaction::External {
+                    tx,
+                    stats: &self.stats,
+                })
+                .map_err(DatabaseExecError::from),
+            Backend::Internal { conn } => conn
+                .unchecked_transaction()
+                .map(|tx| Transaction::Internal {
+                    tx,
+                    stats: &self.stats,
+                })
+                .map_err(DatabaseExecError::from),
+            Backend::Postgres { client } => {
+                // The client is held for the whole transaction so nothing else can run on it
+                let Some(client) = lock(client) else {
+                    return DatabaseResult::DatabaseError;
+                };
+                PostgresTransaction::begin(client).map(|client| Transaction::Postgres {
+                    client,
+                    stats: &self.stats,
+                })
+            }
+        };
 
-                DatabaseResult::DatabaseError
+        let mut tx = match begin {
+            Ok(tx) => tx,
+            Err(why) => {
+                log::error!("Could not begin a database transaction: {why:?}");
+                return DatabaseResult::DatabaseError;
             }
+        };
+
+        let result = f(&mut tx);
+
+        let finished = match &result {
+            DatabaseResult::Ok(_) => tx.commit(),
+            DatabaseResult::DatabaseError => tx.rollback(),
+        };
+
+        if let Err(why) = finished {
+            log::error!("Could not finish a database transaction: {why:?}");
+            return DatabaseResult::DatabaseError;
         }
+
+        result
     }
 
     pub fn auto_increment_not_null(&self) -> &'static str {
-        match &self {
-            Database::External { .. } => "NOT NULL AUTO_INCREMENT",
-            Database::Internal { .. } => "AUTOINCREMENT NOT NULL",
+        match &self.backend {
+            Backend::External { .. } => "NOT NULL AUTO_INCREMENT",
+            Backend::Internal { .. } => "AUTOINCREMENT NOT NULL",
+            Backend::Postgres { .. } => "GENERATED BY DEFAULT AS IDENTITY",
         }
     }
 
+    /// Postgres has no unsigned types, but its statements are translated to use a wider type
+    /// wherever this is written.
     pub fn unsigned_str(&self) -> &'static str {
-        match &self {
-            Database::External { .. } => "unsigned",
-            Database::Internal { .. } => "",
+        match &self.backend {
+            Backend::External { .. } | Backend::Postgres { .. } => "unsigned",
+            Backend::Internal { .. } => "",
+        }
+    }
+
+    /// Column type for an integer which must hold any u32.
+    pub fn unsigned_int_str(&self) -> &'static str {
+        match &self.backend {
+            Backend::External { .. } => "INTEGER unsigned",
+            Backend::Internal { .. } => "INTEGER",
+            Backend::Postgres { .. } => "BIGINT",
+        }
+    }
+}
+
+/// Locks the Postgres client. A panic while it was held does not leave the client itself unusable.
+///
+/// A `Database` can't be shared between threads, so the client can only be held already by a
+/// transaction which is open further up the same thread. Waiting for it would never end.
+fn lock(client: &Mutex<postgres::Client>) -> Option<MutexGuard<'_, postgres::Client>> {
+    match client.try_lock() {
+        Ok(client) => Some(client),
+        Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+        Err(TryLockError::WouldBlock) => {
+            log::error!(
+                "The database can't be used outside of the transaction which is open on it"
+            );
+            None
+        }
+    }
+}
+
+/// Converts the result of executing a statement, logging any failure.
+fn exec_result(
+    res: Result<Vec<Row>, DatabaseExecError>,
+    statement: &str,
+    parameters: &[String],
+) -> DatabaseResult<Vec<Row>> {
+    match res {
+        Ok(rows) => DatabaseResult::Ok(rows),
+        Err(why) => {
+            log::error!("A database execution failed: {why:?}");
+            log::error!("Statement: {statement:?}");
+            log::error!("Parameters: {parameters:?}");
+
+            DatabaseResult::DatabaseError
         }
     }
 }
@@ -97,3 +240,128 @@ macro_rules! aw_params {
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory_database() -> Database {
+        let db = Database::new(DatabaseConfig {
+            database_type: DatabaseType::Internal,
+            mysql_config: MysqlConfig {
+                hostname: String::new(),
+                port: 0,
+                username: String::new(),
+                password: String::new(),
+                database: String::new(),
+            },
+            sqlite_config: SqliteConfig {
+                path: ":memory:".to_string(),
+            },
+            postgres_config: PostgresConfig::default(),
+        })
+        .unwrap();
+
+        assert!(!db.exec("CREATE TABLE t (x INTEGER);", vec![]).is_err());
+        db
+    }
+
+    fn count(db: &Database) -> usize {
+        match db.exec("SELECT * FROM t;", vec![]) {
+            DatabaseResult::Ok(rows) => rows.len(),
+            DatabaseResult::DatabaseError => panic!("Could not count rows"),
+        }
+    }
+
+    #[test]
+    fn transaction_commits() {
+        let db = memory_database();
+        let r = db.transaction(|tx| {
+            if tx
+                .exec("INSERT INTO t (x) VALUES(?);", aw_params!(1))
+                .is_err()
+            {
+                return DatabaseResult::DatabaseError;
+            }
+            tx.exec("INSERT INTO t (x) VALUES(?);", aw_params!(2))
+        });
+
+        assert!(!r.is_err());
+        assert_eq!(count(&db), 2);
+    }
+
+    #[test]
+    fn transaction_rolls_back() {
+        let db = memory_database();
+        let r = db.transaction(|tx| {
+            if tx
+                .exec("INSERT INTO t (x) VALUES(?);", aw_params!(1))
+                .is_err()
+            {
+                return DatabaseResult::DatabaseError;
+            }
+            tx.exec("INSERT INTO nonexistent (x) VALUES(?);", aw_params!(2))
+        });
+
+        assert!(r.is_err());
+        assert_eq!(count(&db), 0);
+    }
+
+    #[test]
+    fn transaction_rolls_back_on_panic() {
+        let db = memory_database();
+        let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            db.transaction::<()>(|tx| {
+                assert!(!tx
+                    .exec("INSERT INTO t (x) VALUES(?);", aw_params!(1))
+                    .is_err());
+                panic!("The transaction was interrupted");
+            })
+        }));
+
+        assert!(r.is_err());
+        assert_eq!(count(&db), 0);
+    }
+
+    #[test]
+    fn inserted_ids_are_read_back() {
+        let db = memory_database();
+        assert!(!db
+            .exec(
+                "CREATE TABLE ids (ID INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL, x INTEGER);",
+                vec![]
+            )
+            .is_err());
+        assert!(!db
+            .exec("INSERT INTO ids (ID, x) VALUES(?, ?);", aw_params!(5, 0))
+            .is_err());
+
+        let r = db.transaction(|tx| {
+            if tx
+                .exec("INSERT INTO ids (x) VALUES(?);", aw_params!(1))
+                .is_err()
+            {
+                return DatabaseResult::DatabaseError;
+            }
+            tx.last_insert_id("ids", "ID")
+        });
+
+        assert!(matches!(r, DatabaseResult::Ok(6)));
+    }
+
+    #[test]
+    fn statements_are_counted() {
+        let db = memory_database();
+        assert!(db.exec("SELECT * FROM nonexistent;", vec![]).is_err());
+
+        let stats = db.stats();
+        assert_eq!(stats.statements(), 2);
+        assert_eq!(stats.errors(), 1);
+        assert_eq!(stats.cumulative_buckets().last(), Some(&2));
+
+        let r = db.transaction(|tx| tx.exec("SELECT * FROM nonexistent;", vec![]));
+        assert!(r.is_err());
+        assert_eq!(stats.statements(), 3);
+        assert_eq!(stats.errors(), 2);
+    }
+}
@@ -8,6 +8,15 @@ pub(crate) fn mysql_exec(
     parameters: Vec<String>,
 ) -> Result<Vec<Row>, DatabaseExecError> {
     let mut conn = pool.get_conn()?;
+    mysql_exec_on(&mut conn, statement, parameters)
+}
+
+/// Executes a statement on a specific connection or transaction rather than one from the pool.
+pub(crate) fn mysql_exec_on(
+    conn: &mut impl Queryable,
+    statement: impl AsRef<str>,
+    parameters: Vec<String>,
+) -> Result<Vec<Row>, DatabaseExecError> {
     let r: mysql::Result<Vec<mysql::Row>> = conn.exec(statement.as_ref(), parameters.clone());
 
     Ok(r?
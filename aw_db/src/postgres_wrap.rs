@@ -0,0 +1,153 @@
+use std::error::Error;
+
+use bytes::BytesMut;
+use postgres::types::{to_sql_checked, Format, IsNull, ToSql, Type};
+
+use crate::{error::DatabaseExecError, Row};
+
+/// A parameter which is sent to the server as text. The server converts it to whatever type the
+/// statement expects, the same way MySQL and SQLite treat our string parameters.
+#[derive(Debug)]
+struct TextParameter<'a>(&'a str);
+
+impl ToSql for TextParameter<'_> {
+    fn to_sql(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        out.extend_from_slice(self.0.as_bytes());
+        Ok(IsNull::No)
+    }
+
+    fn accepts(_ty: &Type) -> bool {
+        true
+    }
+
+    fn encode_format(&self, _ty: &Type) -> Format {
+        Format::Text
+    }
+
+    to_sql_checked!();
+}
+
+pub(crate) fn postgres_exec(
+    client: &mut postgres::Client,
+    statement: impl AsRef<str>,
+    parameters: Vec<String>,
+) -> Result<Vec<Row>, DatabaseExecError> {
+    let statement = translate_statement(statement.as_ref());
+
+    let parameters = parameters
+        .iter()
+        .map(|p| TextParameter(p))
+        .collect::<Vec<TextParameter>>();
+    let parameter_refs = parameters
+        .iter()
+        .map(|p| p as &(dyn ToSql + Sync))
+        .collect::<Vec<&(dyn ToSql + Sync)>>();
+
+    Ok(client
+        .query(statement.as_str(), &parameter_refs)?
+        .into_iter()
+        .map(Row::PostgresRow)
+        .collect::<Vec<Row>>())
+}
+
+/// Rewrites a statement written for MySQL and SQLite into the Postgres dialect.
+/// `?` placeholders become numbered `$n` placeholders, and backtick quoted identifiers become
+/// double quoted. Quoted identifiers are lowercased so that they still match the same identifier
+/// written without quotes, which Postgres folds to lowercase. Tables are created with the column
+/// types of [`translate_column_types`].
+pub(crate) fn translate_statement(statement: &str) -> String {
+    let is_schema_change = ["CREATE TABLE", "ALTER TABLE"].iter().any(|prefix| {
+        statement
+            .trim_start()
+            .get(..prefix.len())
+            .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+    });
+    let statement = match is_schema_change {
+        true => translate_column_types(statement),
+        false => statement.to_string(),
+    };
+
+    let mut result = String::with_capacity(statement.len());
+    let mut placeholder = 0;
+    let mut in_string = false;
+    let mut in_identifier = false;
+
+    for c in statement.chars() {
+        match c {
+            '\'' if !in_identifier => {
+                in_string = !in_string;
+                result.push(c);
+            }
+            '?' if !in_string && !in_identifier => {
+                placeholder += 1;
+                result.push_str(&format!("${placeholder}"));
+            }
+            '`' if !in_string => {
+                in_identifier = !in_identifier;
+                result.push('"');
+            }
+            _ if in_identifier => result.extend(c.to_lowercase()),
+            _ => result.push(c),
+        }
+    }
+
+    result
+}
+
+/// Rewrites the column types of the MySQL schema which Postgres lacks. Every tinyint becomes a
+/// SMALLINT, and unsigned integers become BIGINT so that they can still hold any u32.
+fn translate_column_types(statement: &str) -> String {
+    let mut result = statement.replace("INTEGER unsigned", "BIGINT");
+
+    while let Some(start) = result.find("tinyint(") {
+        let end = result[start..]
+            .find(')')
+            .map_or(result.len(), |end| start + end + 1);
+        result.replace_range(start..end, "SMALLINT");
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_are_numbered() {
+        assert_eq!(
+            translate_statement("UPDATE t SET a=?, b=? WHERE c=?;"),
+            "UPDATE t SET a=$1, b=$2 WHERE c=$3;"
+        );
+        assert_eq!(
+            translate_statement("SELECT * FROM t WHERE a='?' AND b=?"),
+            "SELECT * FROM t WHERE a='?' AND b=$1"
+        );
+    }
+
+    #[test]
+    fn identifiers_are_quoted() {
+        assert_eq!(
+            translate_statement("INSERT INTO t (`From`, Timestamp) VALUES(?, '`')"),
+            "INSERT INTO t (\"from\", Timestamp) VALUES($1, '`')"
+        );
+    }
+
+    #[test]
+    fn column_types_are_translated() {
+        assert_eq!(
+            translate_statement(
+                "CREATE TABLE t (A INTEGER unsigned NOT NULL, B tinyint(4), C tinyint(1), D INTEGER);"
+            ),
+            "CREATE TABLE t (A BIGINT NOT NULL, B SMALLINT, C SMALLINT, D INTEGER);"
+        );
+        assert_eq!(
+            translate_statement("SELECT 'tinyint(1)' FROM t;"),
+            "SELECT 'tinyint(1)' FROM t;"
+        );
+    }
+}
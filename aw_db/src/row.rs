@@ -1,6 +1,8 @@
 use aw_core::encoding::latin1_to_string;
 
-use crate::sqlite_wrap::SqliteValue;
+use postgres::types::Type;
+
+use crate::{error::RowError, sqlite_wrap::SqliteValue, DatabaseResult};
 
 #[derive(Debug)]
 pub enum Row {
@@ -9,42 +11,311 @@ pub enum Row {
         column_names: Vec<String>,
         row: Vec<SqliteValue>,
     },
+    PostgresRow(postgres::Row),
+}
+
+/// A single value of a row, in the same form for every kind of database.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnValue {
+    Null,
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl ColumnValue {
+    fn kind(&self) -> &'static str {
+        match self {
+            ColumnValue::Null => "NULL",
+            ColumnValue::Int(_) | ColumnValue::UInt(_) => "an integer",
+            ColumnValue::Float(_) => "a float",
+            ColumnValue::Text(_) => "text",
+            ColumnValue::Bytes(_) => "bytes",
+        }
+    }
+
+    /// Text of a value. MySQL returns text as latin1 bytes.
+    fn text(&self) -> Option<String> {
+        match self {
+            ColumnValue::Text(x) => Some(x.clone()),
+            ColumnValue::Bytes(x) => Some(latin1_to_string(x)),
+            _ => None,
+        }
+    }
+}
+
+/// A type which can be read from a single column.
+pub trait FromColumn: Sized {
+    fn from_column(value: &ColumnValue) -> Option<Self>;
+}
+
+/// A type which can be read from a whole row. Usually implemented with [`crate::from_row`].
+pub trait FromRow: Sized {
+    fn from_row(row: &Row) -> Result<Self, RowError>;
+}
+
+macro_rules! from_column_int {
+    ($($t:ty),*) => {
+        $(
+            impl FromColumn for $t {
+                fn from_column(value: &ColumnValue) -> Option<Self> {
+                    match value {
+                        ColumnValue::Int(x) => <$t>::try_from(*x).ok(),
+                        ColumnValue::UInt(x) => <$t>::try_from(*x).ok(),
+                        // MySQL returns numbers as text for statements without parameters
+                        _ => value.text()?.trim().parse().ok(),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+from_column_int!(i8, i16, i32, i64, u8, u16, u32, u64);
+
+macro_rules! from_column_float {
+    ($($t:ty),*) => {
+        $(
+            impl FromColumn for $t {
+                fn from_column(value: &ColumnValue) -> Option<Self> {
+                    match value {
+                        ColumnValue::Float(x) => Some(*x as $t),
+                        // A whole number in a float column may be stored as an integer
+                        ColumnValue::Int(x) => Some(*x as $t),
+                        ColumnValue::UInt(x) => Some(*x as $t),
+                        _ => value.text()?.trim().parse().ok(),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+from_column_float!(f32, f64);
+
+impl FromColumn for bool {
+    fn from_column(value: &ColumnValue) -> Option<Self> {
+        i64::from_column(value).map(|x| x != 0)
+    }
+}
+
+impl FromColumn for String {
+    fn from_column(value: &ColumnValue) -> Option<Self> {
+        value.text()
+    }
+}
+
+impl FromColumn for Vec<u8> {
+    fn from_column(value: &ColumnValue) -> Option<Self> {
+        match value {
+            ColumnValue::Bytes(x) => Some(x.clone()),
+            ColumnValue::Text(x) => Some(x.as_bytes().to_vec()),
+            _ => None,
+        }
+    }
+}
+
+impl<T: FromColumn> FromColumn for Option<T> {
+    fn from_column(value: &ColumnValue) -> Option<Self> {
+        match value {
+            ColumnValue::Null => Some(None),
+            _ => T::from_column(value).map(Some),
+        }
+    }
 }
 
 impl Row {
-    pub fn fetch_int(&self, name: &str) -> Option<i64> {
+    /// Reads a column as any type implementing [`FromColumn`].
+    pub fn get<T: FromColumn>(&self, name: &str) -> Result<T, RowError> {
+        let value = self.value(name)?;
+
+        match T::from_column(&value) {
+            Some(x) => Ok(x),
+            None if value == ColumnValue::Null => Err(RowError::Null(name.to_string())),
+            None => Err(RowError::Type {
+                column: name.to_string(),
+                found: value.kind(),
+                expected: std::any::type_name::<T>(),
+            }),
+        }
+    }
+
+    /// Reads the whole row as any type implementing [`FromRow`], logging any failure.
+    pub fn decode<T: FromRow>(&self) -> DatabaseResult<T> {
+        match T::from_row(self) {
+            Ok(x) => DatabaseResult::Ok(x),
+            Err(why) => {
+                log::error!(
+                    "Couldn't read a {} from the database: {why}",
+                    std::any::type_name::<T>()
+                );
+                DatabaseResult::DatabaseError
+            }
+        }
+    }
+
+    pub fn value(&self, name: &str) -> Result<ColumnValue, RowError> {
+        let missing = || RowError::Missing(name.to_string());
+
         match &self {
-            Row::MysqlRow(row) => match row.get::<mysql::Value, _>(name) {
-                Some(mysql::Value::Int(x)) => Some(x),
-                _ => None,
-            },
-            Row::SqliteRow { column_names, row } => match column_names
-                .iter()
-                .enumerate()
-                .find(|(_, cname)| *cname == name)
-                .and_then(|(i, _)| row.get(i))
-            {
-                Some(SqliteValue::Int(x)) => Some(*x),
-                _ => None,
-            },
+            Row::MysqlRow(row) => {
+                let value = row.get::<mysql::Value, _>(name).ok_or_else(missing)?;
+                match value {
+                    mysql::Value::NULL => Ok(ColumnValue::Null),
+                    mysql::Value::Int(x) => Ok(ColumnValue::Int(x)),
+                    mysql::Value::UInt(x) => Ok(ColumnValue::UInt(x)),
+                    mysql::Value::Float(x) => Ok(ColumnValue::Float(f64::from(x))),
+                    mysql::Value::Double(x) => Ok(ColumnValue::Float(x)),
+                    mysql::Value::Bytes(x) => Ok(ColumnValue::Bytes(x)),
+                    mysql::Value::Date(..) | mysql::Value::Time(..) => {
+                        Err(RowError::Unsupported(name.to_string()))
+                    }
+                }
+            }
+            Row::SqliteRow { column_names, row } => {
+                let value = column_names
+                    .iter()
+                    .position(|cname| cname == name)
+                    .and_then(|i| row.get(i))
+                    .ok_or_else(missing)?;
+                Ok(match value {
+                    SqliteValue::None => ColumnValue::Null,
+                    SqliteValue::Int(x) => ColumnValue::Int(*x),
+                    SqliteValue::Float(x) => ColumnValue::Float(*x),
+                    SqliteValue::String(x) => ColumnValue::Text(x.clone()),
+                    SqliteValue::Blob(x) => ColumnValue::Bytes(x.clone()),
+                })
+            }
+            Row::PostgresRow(row) => {
+                // Postgres folds unquoted names to lowercase, so the case is ignored.
+                let i = row
+                    .columns()
+                    .iter()
+                    .position(|column| column.name().eq_ignore_ascii_case(name))
+                    .ok_or_else(missing)?;
+                postgres_value(row, i).ok_or_else(|| RowError::Unsupported(name.to_string()))
+            }
         }
     }
 
+    pub fn fetch_int(&self, name: &str) -> Option<i64> {
+        self.get::<Option<i64>>(name).ok().flatten()
+    }
+
+    pub fn fetch_float(&self, name: &str) -> Option<f64> {
+        self.get::<Option<f64>>(name).ok().flatten()
+    }
+
     pub fn fetch_string(&self, name: &str) -> Option<String> {
-        match &self {
-            Row::MysqlRow(row) => match row.get::<mysql::Value, _>(name) {
-                Some(mysql::Value::Bytes(x)) => Some(latin1_to_string(&x)),
-                _ => None,
-            },
-            Row::SqliteRow { column_names, row } => match column_names
-                .iter()
-                .enumerate()
-                .find(|(_, cname)| *cname == name)
-                .and_then(|(i, _)| row.get(i))
-            {
-                Some(SqliteValue::String(x)) => Some(x.clone()),
-                _ => None,
-            },
+        self.get::<Option<String>>(name).ok().flatten()
+    }
+}
+
+fn postgres_value(row: &postgres::Row, i: usize) -> Option<ColumnValue> {
+    let value = match *row.columns()[i].type_() {
+        Type::BOOL => row
+            .try_get::<_, Option<bool>>(i)
+            .ok()?
+            .map(|x| ColumnValue::Int(i64::from(x))),
+        Type::INT2 => row
+            .try_get::<_, Option<i16>>(i)
+            .ok()?
+            .map(|x| ColumnValue::Int(i64::from(x))),
+        Type::INT4 => row
+            .try_get::<_, Option<i32>>(i)
+            .ok()?
+            .map(|x| ColumnValue::Int(i64::from(x))),
+        Type::INT8 => row.try_get::<_, Option<i64>>(i).ok()?.map(ColumnValue::Int),
+        Type::FLOAT4 => row
+            .try_get::<_, Option<f32>>(i)
+            .ok()?
+            .map(|x| ColumnValue::Float(f64::from(x))),
+        Type::FLOAT8 => row
+            .try_get::<_, Option<f64>>(i)
+            .ok()?
+            .map(ColumnValue::Float),
+        Type::TEXT | Type::VARCHAR | Type::BPCHAR | Type::NAME => row
+            .try_get::<_, Option<String>>(i)
+            .ok()?
+            .map(ColumnValue::Text),
+        Type::BYTEA => row
+            .try_get::<_, Option<Vec<u8>>>(i)
+            .ok()?
+            .map(ColumnValue::Bytes),
+        _ => return None,
+    };
+
+    Some(value.unwrap_or(ColumnValue::Null))
+}
+
+/// Implements [`FromRow`] for a struct by reading each field from a column.
+/// A field may be followed by a function which converts the column to the field's type.
+///
+/// ```ignore
+/// from_row!(ContactQuery {
+///     citizen: "Citizen",
+///     contact: "Contact",
+///     options: "Options" => ContactOptions::from_bits_truncate,
+/// });
+/// ```
+#[macro_export]
+macro_rules! from_row {
+    (@column $row:ident, $column:literal) => {
+        $row.get($column)?
+    };
+    (@column $row:ident, $column:literal, $convert:expr) => {
+        ($convert)($row.get($column)?)
+    };
+    ($type:ty { $($field:ident: $column:literal $(=> $convert:expr)?),* $(,)? }) => {
+        impl $crate::FromRow for $type {
+            fn from_row(row: &$crate::Row) -> Result<Self, $crate::RowError> {
+                Ok(Self {
+                    $($field: $crate::from_row!(@column row, $column $(, $convert)?),)*
+                })
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sqlite_row() -> Row {
+        Row::SqliteRow {
+            column_names: ["Int", "Float", "Text", "Null", "Blob"]
+                .map(String::from)
+                .to_vec(),
+            row: vec![
+                SqliteValue::Int(300),
+                SqliteValue::Float(1.5),
+                SqliteValue::String("42".to_string()),
+                SqliteValue::None,
+                SqliteValue::Blob(vec![1, 2]),
+            ],
         }
     }
+
+    #[test]
+    fn columns_convert() {
+        let row = sqlite_row();
+
+        assert_eq!(row.get::<u32>("Int").unwrap(), 300);
+        assert_eq!(row.get::<f32>("Float").unwrap(), 1.5);
+        assert_eq!(row.get::<f64>("Int").unwrap(), 300.0);
+        assert_eq!(row.get::<u16>("Text").unwrap(), 42);
+        assert_eq!(row.get::<Option<u32>>("Null").unwrap(), None);
+        assert_eq!(row.get::<Vec<u8>>("Blob").unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn columns_report_errors() {
+        let row = sqlite_row();
+
+        assert!(matches!(row.get::<u8>("Int"), Err(RowError::Type { .. })));
+        assert!(matches!(row.get::<u32>("Null"), Err(RowError::Null(_))));
+        assert!(matches!(row.get::<u32>("Nope"), Err(RowError::Missing(_))));
+    }
 }
@@ -1,4 +1,4 @@
-use rusqlite::params_from_iter;
+use rusqlite::{params_from_iter, types::ValueRef};
 
 use crate::{error::DatabaseExecError, Row};
 
@@ -6,7 +6,9 @@ use crate::{error::DatabaseExecError, Row};
 pub enum SqliteValue {
     None,
     Int(i64),
+    Float(f64),
     String(String),
+    Blob(Vec<u8>),
 }
 
 pub(crate) fn sqlite_exec(
@@ -28,13 +30,13 @@ pub(crate) fn sqlite_exec(
     while let Ok(Some(row)) = rows.next() {
         let mut row_vec = Vec::<SqliteValue>::new();
         for i in 0..column_names.len() {
-            if let Ok(num) = row.get::<usize, i64>(i) {
-                row_vec.push(SqliteValue::Int(num))
-            } else if let Ok(b) = row.get::<usize, String>(i) {
-                row_vec.push(SqliteValue::String(b))
-            } else {
-                row_vec.push(SqliteValue::None)
-            }
+            row_vec.push(match row.get_ref(i)? {
+                ValueRef::Null => SqliteValue::None,
+                ValueRef::Integer(num) => SqliteValue::Int(num),
+                ValueRef::Real(num) => SqliteValue::Float(num),
+                ValueRef::Text(text) => SqliteValue::String(String::from_utf8_lossy(text).into()),
+                ValueRef::Blob(blob) => SqliteValue::Blob(blob.to_vec()),
+            })
         }
         rows_vec.push(Row::SqliteRow {
             column_names: column_names.clone(),
@@ -0,0 +1,61 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Upper bounds of the buckets statement durations are sorted into, in seconds.
+pub const LATENCY_BUCKETS: [f64; 10] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.5, 1.0,
+];
+
+/// How many statements were executed, how long they took, and how many of them failed.
+#[derive(Debug, Default)]
+pub struct DatabaseStats {
+    statements: AtomicU64,
+    errors: AtomicU64,
+    total_micros: AtomicU64,
+    /// Number of statements which took at most as long as the bucket's bound, but longer than the
+    /// bound of the bucket before it.
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+}
+
+impl DatabaseStats {
+    pub(crate) fn record(&self, elapsed: Duration, failed: bool) {
+        self.statements.fetch_add(1, Ordering::Relaxed);
+        if failed {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        self.total_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+
+        let seconds = elapsed.as_secs_f64();
+        if let Some(bucket) = LATENCY_BUCKETS
+            .iter()
+            .zip(&self.buckets)
+            .find_map(|(&bound, bucket)| (seconds <= bound).then_some(bucket))
+        {
+            bucket.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn statements(&self) -> u64 {
+        self.statements.load(Ordering::Relaxed)
+    }
+
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    /// Time spent executing all statements together.
+    pub fn total_time(&self) -> Duration {
+        Duration::from_micros(self.total_micros.load(Ordering::Relaxed))
+    }
+
+    /// Number of statements which took at most as long as each of [`LATENCY_BUCKETS`], so each
+    /// count includes those of the buckets before it.
+    pub fn cumulative_buckets(&self) -> [u64; LATENCY_BUCKETS.len()] {
+        let mut total = 0;
+        std::array::from_fn(|i| {
+            total += self.buckets[i].load(Ordering::Relaxed);
+            total
+        })
+    }
+}
@@ -0,0 +1,212 @@
+use std::sync::MutexGuard;
+use std::time::Instant;
+
+use crate::{
+    error::DatabaseExecError, exec_result, mysql_wrap::mysql_exec_on, postgres_wrap::postgres_exec,
+    sqlite_wrap::sqlite_exec, DatabaseResult, DatabaseStats, Row,
+};
+
+/// A set of statements which are committed together or not at all.
+/// Created by [`crate::Database::transaction`].
+pub enum Transaction<'a> {
+    External {
+        tx: mysql::Transaction<'static>,
+        stats: &'a DatabaseStats,
+    },
+    Internal {
+        tx: rusqlite::Transaction<'a>,
+        stats: &'a DatabaseStats,
+    },
+    Postgres {
+        client: PostgresTransaction<'a>,
+        stats: &'a DatabaseStats,
+    },
+}
+
+/// The Postgres client of a database, held while a transaction is open on it. Like the
+/// transactions of the other backends, it is rolled back if dropped before being finished, as
+/// when the closure running in it panics.
+pub struct PostgresTransaction<'a> {
+    client: MutexGuard<'a, postgres::Client>,
+    finished: bool,
+}
+
+impl<'a> PostgresTransaction<'a> {
+    pub(crate) fn begin(
+        mut client: MutexGuard<'a, postgres::Client>,
+    ) -> Result<Self, DatabaseExecError> {
+        client.batch_execute("BEGIN")?;
+        Ok(Self {
+            client,
+            finished: false,
+        })
+    }
+
+    fn finish(mut self, statement: &str) -> Result<(), DatabaseExecError> {
+        self.finished = true;
+        self.client.batch_execute(statement)?;
+        Ok(())
+    }
+}
+
+impl Drop for PostgresTransaction<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            if let Err(why) = self.client.batch_execute("ROLLBACK") {
+                log::error!("Could not roll back an unfinished database transaction: {why:?}");
+            }
+        }
+    }
+}
+
+impl Transaction<'_> {
+    /// Executes a statement as part of this transaction.
+    #[must_use]
+    pub fn exec(
+        &mut self,
+        statement: impl AsRef<str>,
+        parameters: Vec<String>,
+    ) -> DatabaseResult<Vec<Row>> {
+        log::trace!(
+            "Executing statement {:?} with parameters {:?} in a transaction",
+            statement.as_ref(),
+            &parameters
+        );
+
+        let start = Instant::now();
+        let (res, stats) = match self {
+            Transaction::External { tx, stats } => (
+                mysql_exec_on(tx, statement.as_ref(), parameters.clone()),
+                stats,
+            ),
+            Transaction::Internal { tx, stats } => (
+                sqlite_exec(tx, statement.as_ref(), parameters.clone()),
+                stats,
+            ),
+            Transaction::Postgres { client, stats } => (
+                postgres_exec(&mut client.client, statement.as_ref(), parameters.clone()),
+                stats,
+            ),
+        };
+        stats.record(start.elapsed(), res.is_err());
+
+        exec_result(res, statement.as_ref(), &parameters)
+    }
+
+    pub fn auto_increment_not_null(&self) -> &'static str {
+        match &self {
+            Transaction::External { .. } => "NOT NULL AUTO_INCREMENT",
+            Transaction::Internal { .. } => "AUTOINCREMENT NOT NULL",
+            Transaction::Postgres { .. } => "GENERATED BY DEFAULT AS IDENTITY",
+        }
+    }
+
+    pub fn unsigned_str(&self) -> &'static str {
+        match &self {
+            Transaction::External { .. } | Transaction::Postgres { .. } => "unsigned",
+            Transaction::Internal { .. } => "",
+        }
+    }
+
+    pub fn unsigned_int_str(&self) -> &'static str {
+        match &self {
+            Transaction::External { .. } => "INTEGER unsigned",
+            Transaction::Internal { .. } => "INTEGER",
+            Transaction::Postgres { .. } => "BIGINT",
+        }
+    }
+
+    /// Changes the type of an existing column, keeping the `constraints` it was created with.
+    /// SQLite does not enforce column types, so its tables are left as they are.
+    pub fn change_column_type(
+        &mut self,
+        table: &str,
+        column: &str,
+        column_type: &str,
+        constraints: &str,
+    ) -> DatabaseResult<()> {
+        let statement = match self {
+            Transaction::External { .. } => {
+                format!("ALTER TABLE {table} MODIFY {column} {column_type} {constraints};")
+            }
+            Transaction::Internal { .. } => return DatabaseResult::Ok(()),
+            // Postgres keeps the constraints of a column when only its type changes
+            Transaction::Postgres { .. } => {
+                format!("ALTER TABLE {table} ALTER COLUMN {column} TYPE {column_type};")
+            }
+        };
+
+        match self.exec(statement, vec![]) {
+            DatabaseResult::Ok(_) => DatabaseResult::Ok(()),
+            DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
+        }
+    }
+
+    /// Makes sure the next row inserted into `table` without an explicit `column` value gets an
+    /// unused one. Only needed after inserting explicit values, which Postgres does not account for.
+    pub fn auto_increment_reset(&mut self, table: &str, column: &str) -> DatabaseResult<()> {
+        if !matches!(self, Transaction::Postgres { .. }) {
+            return DatabaseResult::Ok(());
+        }
+
+        // Postgres only folds the table name to lowercase, not the column name
+        let sequence_column = column.to_lowercase();
+        let r = self.exec(
+            format!(
+                r"SELECT setval(pg_get_serial_sequence('{table}', '{sequence_column}'),
+                    COALESCE(MAX({column}), 0) + 1, false) FROM {table};"
+            ),
+            vec![],
+        );
+
+        match r {
+            DatabaseResult::Ok(_) => DatabaseResult::Ok(()),
+            DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
+        }
+    }
+
+    /// The ID which `column` of `table` was given by the last row this transaction inserted without
+    /// one. Unlike reading the highest ID back, this can't return a row inserted by someone else.
+    pub fn last_insert_id(&mut self, table: &str, column: &str) -> DatabaseResult<u32> {
+        let statement = match self {
+            Transaction::External { .. } => "SELECT LAST_INSERT_ID() AS ID;".to_string(),
+            Transaction::Internal { .. } => "SELECT last_insert_rowid() AS ID;".to_string(),
+            Transaction::Postgres { .. } => {
+                let sequence_column = column.to_lowercase();
+                format!(
+                    "SELECT currval(pg_get_serial_sequence('{table}', '{sequence_column}')) AS ID;"
+                )
+            }
+        };
+
+        let rows = match self.exec(statement, vec![]) {
+            DatabaseResult::Ok(rows) => rows,
+            DatabaseResult::DatabaseError => return DatabaseResult::DatabaseError,
+        };
+
+        match rows.first().map(|row| row.get::<u32>("ID")) {
+            Some(Ok(id)) => DatabaseResult::Ok(id),
+            _ => DatabaseResult::DatabaseError,
+        }
+    }
+
+    pub(crate) fn commit(self) -> Result<(), DatabaseExecError> {
+        match self {
+            Transaction::External { tx, .. } => tx.commit()?,
+            Transaction::Internal { tx, .. } => tx.commit()?,
+            Transaction::Postgres { client, .. } => client.finish("COMMIT")?,
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn rollback(self) -> Result<(), DatabaseExecError> {
+        match self {
+            Transaction::External { tx, .. } => tx.rollback()?,
+            Transaction::Internal { tx, .. } => tx.rollback()?,
+            Transaction::Postgres { client, .. } => client.finish("ROLLBACK")?,
+        }
+
+        Ok(())
+    }
+}
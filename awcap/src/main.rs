@@ -0,0 +1,274 @@
+use aw_core::{
+    AWClient, AWPacket, CaptureReader, CapturedPacket, Direction, PacketType, PacketTypeResult,
+    ProtocolVersion, VarID,
+};
+use clap::{Parser, Subcommand};
+use num_traits::FromPrimitive;
+use std::net::SocketAddr;
+use std::thread::sleep;
+use std::time::{Duration, SystemTime};
+
+#[derive(Parser)]
+/// Inspect and replay packet captures recorded by the universe
+struct Args {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the packets of a capture
+    Show {
+        capture_file: String,
+
+        #[clap(long = "type")]
+        /// Only show packets of this type, by name or opcode. May be repeated
+        packet_types: Vec<String>,
+
+        #[clap(long = "var")]
+        /// Only show packets containing this variable, by name or ID. May be repeated
+        vars: Vec<String>,
+
+        #[clap(long)]
+        /// Only show packets going this way: <sent | received>
+        direction: Option<String>,
+    },
+    /// Send the packets a client sent in a capture to a universe, printing what it answers
+    Replay {
+        capture_file: String,
+        address: SocketAddr,
+
+        #[clap(long)]
+        /// Protocol version to speak: <4 | 6>. Detected from the capture by default
+        protocol: Option<u32>,
+
+        #[clap(long)]
+        /// Wait between packets as long as the client did
+        realtime: bool,
+
+        #[clap(long, default_value_t = 500)]
+        /// Milliseconds to wait for answers after each packet
+        wait: u64,
+    },
+}
+
+fn main() {
+    let args = Args::parse();
+
+    match args.command {
+        Command::Show {
+            capture_file,
+            packet_types,
+            vars,
+            direction,
+        } => {
+            let packet_types = packet_types
+                .iter()
+                .map(|name| parse_packet_type(name))
+                .collect::<Vec<i16>>();
+            let vars = vars
+                .iter()
+                .map(|name| parse_var(name))
+                .collect::<Vec<u16>>();
+            let direction = direction.map(|name| parse_direction(&name));
+
+            show(&capture_file, &packet_types, &vars, direction);
+        }
+        Command::Replay {
+            capture_file,
+            address,
+            protocol,
+            realtime,
+            wait,
+        } => {
+            let protocol = protocol.map(|version| match version {
+                4 => ProtocolVersion::V4,
+                6 => ProtocolVersion::V6,
+                _ => {
+                    println!("Unsupported protocol version {version}.");
+                    std::process::exit(1);
+                }
+            });
+
+            replay(
+                &capture_file,
+                address,
+                protocol,
+                realtime,
+                Duration::from_millis(wait),
+            );
+        }
+    }
+}
+
+fn read_capture(capture_file: &str) -> Vec<CapturedPacket> {
+    let reader = CaptureReader::open(capture_file).unwrap_or_else(|why| {
+        println!("Could not open capture file: {why}");
+        std::process::exit(1);
+    });
+
+    let mut packets = Vec::new();
+    for record in reader {
+        match record {
+            Ok(record) => packets.push(record),
+            Err(why) => {
+                // A capture may end in the middle of a record if the universe was killed
+                println!("Stopped reading capture early: {why}");
+                break;
+            }
+        }
+    }
+
+    packets
+}
+
+fn show(capture_file: &str, packet_types: &[i16], vars: &[u16], direction: Option<Direction>) {
+    let packets = read_capture(capture_file);
+    let Some(start) = packets.first().map(|record| record.time) else {
+        return;
+    };
+
+    for record in packets {
+        let opcode = i16::from(record.packet.get_type());
+        if !packet_types.is_empty() && !packet_types.contains(&opcode) {
+            continue;
+        }
+        if !vars.is_empty() && !vars.iter().any(|&id| record.packet.get_var(id).is_some()) {
+            continue;
+        }
+        if direction.is_some_and(|direction| direction != record.direction) {
+            continue;
+        }
+
+        print_packet(
+            elapsed(start, record.time),
+            record.direction,
+            &record.packet,
+        );
+    }
+}
+
+fn replay(
+    capture_file: &str,
+    address: SocketAddr,
+    protocol: Option<ProtocolVersion>,
+    realtime: bool,
+    wait: Duration,
+) {
+    let packets = read_capture(capture_file)
+        .into_iter()
+        .filter(|record| record.direction == Direction::Received)
+        .collect::<Vec<CapturedPacket>>();
+    let Some(first) = packets.first() else {
+        println!("The capture has no packets from a client.");
+        return;
+    };
+
+    let protocol = protocol.unwrap_or_else(|| ProtocolVersion::from_handshake(&first.packet));
+    let start = SystemTime::now();
+    let mut client = AWClient::connect(address, protocol).unwrap_or_else(|why| {
+        println!("{why}");
+        std::process::exit(1);
+    });
+    println!("Connected to {address} using protocol {protocol:?}");
+
+    let mut previous_time = first.time;
+    for record in &packets {
+        // The client has already exchanged keys on its own
+        if matches!(
+            record.packet.get_type(),
+            PacketTypeResult::PacketType(
+                PacketType::PublicKeyRequest
+                    | PacketType::PublicKeyResponse
+                    | PacketType::StreamKeyResponse
+            )
+        ) {
+            continue;
+        }
+
+        if realtime {
+            sleep(
+                record
+                    .time
+                    .duration_since(previous_time)
+                    .unwrap_or_default(),
+            );
+        }
+        previous_time = record.time;
+
+        print_packet(
+            elapsed(start, SystemTime::now()),
+            Direction::Sent,
+            &record.packet,
+        );
+        client.send(record.packet.clone());
+
+        loop {
+            match client.next_packet(wait) {
+                Ok(Some(packet)) => print_packet(
+                    elapsed(start, SystemTime::now()),
+                    Direction::Received,
+                    &packet,
+                ),
+                Ok(None) => break,
+                Err(why) => {
+                    println!("{why}");
+                    return;
+                }
+            }
+        }
+    }
+}
+
+fn elapsed(start: SystemTime, time: SystemTime) -> Duration {
+    time.duration_since(start).unwrap_or_default()
+}
+
+fn print_packet(elapsed: Duration, direction: Direction, packet: &AWPacket) {
+    let arrow = match direction {
+        Direction::Sent => "->",
+        Direction::Received => "<-",
+    };
+    println!("{:>10.3} {arrow} {packet}", elapsed.as_secs_f64());
+}
+
+fn parse_packet_type(name: &str) -> i16 {
+    if let Ok(opcode) = name.parse::<i16>() {
+        return opcode;
+    }
+
+    (i16::MIN..=i16::MAX)
+        .filter_map(PacketType::from_i16)
+        .find(|packet_type| format!("{packet_type:?}").eq_ignore_ascii_case(name))
+        .map(|packet_type| packet_type as i16)
+        .unwrap_or_else(|| {
+            println!("Unknown packet type {name}.");
+            std::process::exit(1);
+        })
+}
+
+fn parse_var(name: &str) -> u16 {
+    if let Ok(id) = name.parse::<u16>() {
+        return id;
+    }
+
+    (0..=u16::MAX)
+        .filter_map(VarID::from_u16)
+        .find(|var_id| format!("{var_id:?}").eq_ignore_ascii_case(name))
+        .map(u16::from)
+        .unwrap_or_else(|| {
+            println!("Unknown variable {name}.");
+            std::process::exit(1);
+        })
+}
+
+fn parse_direction(name: &str) -> Direction {
+    match name.to_ascii_lowercase().as_str() {
+        "sent" => Direction::Sent,
+        "received" => Direction::Received,
+        _ => {
+            println!("Direction must be sent or received.");
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,365 @@
+use aw_core::{
+    AWClient, AWConnection, AWCryptRSA, AWPacket, AWProtocol, LicenseGenerator, PacketCapture,
+    PacketType, PacketTypeResult, ProtocolMessage, ProtocolVersion, VarID,
+};
+use clap::Parser;
+use env_logger::Builder;
+use std::net::{SocketAddr, SocketAddrV4, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// How long to wait for packets from one side before checking the other
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Parser)]
+/// Sit between AW clients and a universe, logging every packet they exchange
+struct Args {
+    #[clap(long, value_parser, default_value_t = log::LevelFilter::Info)]
+    /// Verbosity of logging: <off | error | warn | info | debug | trace>
+    log_level: log::LevelFilter,
+
+    #[clap(long, default_value = "0.0.0.0:6670")]
+    /// Address to accept clients on
+    listen: SocketAddrV4,
+
+    #[clap(long)]
+    /// Address of the universe to forward clients to
+    upstream: String,
+
+    #[clap(long)]
+    /// Address clients connect to, which their license is made for. Defaults to the listen address
+    public_addr: Option<SocketAddrV4>,
+
+    #[clap(long)]
+    /// Directory to write a packet capture of each client to
+    capture_dir: Option<PathBuf>,
+}
+
+struct Proxy {
+    upstream: String,
+    license_generator: LicenseGenerator,
+    capture_dir: Option<PathBuf>,
+}
+
+fn init_logging(level: log::LevelFilter) {
+    let mut builder = Builder::new();
+    builder.filter_level(level);
+    builder.init();
+}
+
+fn main() {
+    let args = Args::parse();
+    init_logging(args.log_level);
+
+    let public_addr = args.public_addr.unwrap_or(args.listen);
+    if public_addr.ip().is_unspecified() {
+        log::warn!("Clients will reject a license for {public_addr}, set --public-addr");
+    }
+
+    let listener = TcpListener::bind(args.listen).unwrap_or_else(|why| {
+        println!("Could not listen on {}: {why}", args.listen);
+        std::process::exit(1);
+    });
+    log::info!("Forwarding clients on {} to {}", args.listen, args.upstream);
+
+    let proxy = Arc::new(Proxy {
+        upstream: args.upstream,
+        license_generator: LicenseGenerator::new(&public_addr),
+        capture_dir: args.capture_dir,
+    });
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let proxy = proxy.clone();
+                thread::spawn(move || proxy_client(&proxy, stream));
+            }
+            Err(why) => log::warn!("Failed to accept a client: {why}"),
+        }
+    }
+}
+
+fn proxy_client(proxy: &Proxy, stream: TcpStream) {
+    let Ok(peer) = stream.peer_addr() else {
+        return;
+    };
+    log::info!("{peer} connected");
+
+    let mut protocol = match AWProtocol::new(stream) {
+        Ok(protocol) => protocol,
+        Err(why) => {
+            log::warn!("Could not set up a connection for {peer}: {why:?}");
+            return;
+        }
+    };
+    if let Some(capture_dir) = &proxy.capture_dir {
+        match PacketCapture::create_in(capture_dir, peer) {
+            Ok(capture) => protocol.set_capture(capture),
+            Err(why) => log::warn!("Could not create a capture for {peer}: {why}"),
+        }
+    }
+
+    let mut session = Session {
+        peer,
+        client: AWConnection::new(protocol),
+        upstream: None,
+        rsa: AWCryptRSA::new(),
+        browser_build: 0,
+    };
+    session.run(proxy);
+    log::info!("{peer} disconnected");
+}
+
+/// Replace the universe's license in a packet with one for the address clients connect to.
+fn replace_license(
+    packet: &mut AWPacket,
+    license_generator: &LicenseGenerator,
+    browser_build: i32,
+) {
+    if packet.get_var(VarID::UniverseLicense).is_some() {
+        packet.remove_var(VarID::UniverseLicense);
+        packet.add_data(
+            VarID::UniverseLicense,
+            license_generator.create_license_data(browser_build),
+        );
+    }
+}
+
+/// A client connected to the proxy, and the proxy's own connection to the universe for it.
+///
+/// Both connections are encrypted with keys of their own, so the proxy answers the client's key
+/// exchange itself instead of forwarding it. Everything after that is passed along as it is,
+/// except for the license sent on login, which has to name the proxy's address to be accepted, and
+/// the client's heartbeats, since the universe's are answered by the proxy's own connection.
+struct Session {
+    peer: SocketAddr,
+    client: AWConnection,
+    upstream: Option<AWClient>,
+    rsa: AWCryptRSA,
+    browser_build: i32,
+}
+
+impl Session {
+    fn run(&mut self, proxy: &Proxy) {
+        loop {
+            match self.client.recv_timeout(POLL_INTERVAL) {
+                Some(ProtocolMessage::Packet(packet)) => {
+                    if !self.client_packet(proxy, packet) {
+                        return;
+                    }
+                }
+                Some(ProtocolMessage::PacketGroup(packets)) => {
+                    for packet in packets {
+                        if !self.client_packet(proxy, packet) {
+                            return;
+                        }
+                    }
+                }
+                Some(ProtocolMessage::Disconnect) => return,
+                Some(_) | None => {}
+            }
+
+            if !self.forward_upstream(proxy) {
+                return;
+            }
+        }
+    }
+
+    /// Handle a packet from the client. Returns false once the session is over.
+    fn client_packet(&mut self, proxy: &Proxy, packet: AWPacket) -> bool {
+        log::info!("{} -> {packet}", self.peer);
+
+        match packet.get_type() {
+            PacketTypeResult::PacketType(PacketType::PublicKeyRequest) => {
+                self.public_key_request(proxy, &packet)
+            }
+            PacketTypeResult::PacketType(PacketType::StreamKeyResponse) => {
+                self.stream_key_response(&packet)
+            }
+            PacketTypeResult::PacketType(PacketType::PublicKeyResponse) => {
+                self.public_key_response(&packet)
+            }
+            // The universe's heartbeats are already answered by our own connection to it
+            PacketTypeResult::PacketType(PacketType::Heartbeat) => true,
+            _ => {
+                if let Some(build) = packet.get_int(VarID::BrowserBuild) {
+                    self.browser_build = build;
+                }
+
+                match &self.upstream {
+                    Some(upstream) => {
+                        upstream.send(packet);
+                        true
+                    }
+                    None => {
+                        log::warn!("{} sent a packet before exchanging keys", self.peer);
+                        false
+                    }
+                }
+            }
+        }
+    }
+
+    /// Forward everything the universe has sent to the client. Returns false once the session is
+    /// over.
+    fn forward_upstream(&mut self, proxy: &Proxy) -> bool {
+        let Some(upstream) = &mut self.upstream else {
+            return true;
+        };
+
+        loop {
+            match upstream.next_packet(Duration::ZERO) {
+                Ok(Some(mut packet)) => {
+                    log::info!("{} <- {packet}", self.peer);
+                    replace_license(&mut packet, &proxy.license_generator, self.browser_build);
+                    self.client.send(packet);
+                }
+                Ok(None) => return true,
+                Err(why) => {
+                    log::info!("Universe connection for {} closed: {why}", self.peer);
+                    return false;
+                }
+            }
+        }
+    }
+
+    /// The client starts the key exchange, so open the session with the universe in the same
+    /// version of the protocol and answer with our own public key.
+    fn public_key_request(&mut self, proxy: &Proxy, packet: &AWPacket) -> bool {
+        let version = ProtocolVersion::from_handshake(packet);
+        log::info!("{} is using protocol {version:?}", self.peer);
+        self.client.set_cipher_kind(version.cipher_kind());
+
+        match AWClient::connect(proxy.upstream.as_str(), version) {
+            Ok(upstream) => self.upstream = Some(upstream),
+            Err(why) => {
+                log::warn!("Could not connect to {}: {why}", proxy.upstream);
+                return false;
+            }
+        }
+
+        let Some(key) = self.rsa.encode_public_key() else {
+            log::warn!("Failed to encode public key for {}", self.peer);
+            return false;
+        };
+        let mut response = AWPacket::new(PacketType::PublicKeyResponse);
+        response.add_data(VarID::EncryptionKey, key);
+        self.client.send(response);
+
+        true
+    }
+
+    /// The client sent its stream key, so we can read what it sends from now on. The universe
+    /// has already given us its attributes, which the client expects next.
+    fn stream_key_response(&mut self, packet: &AWPacket) -> bool {
+        let Some(upstream) = &self.upstream else {
+            return false;
+        };
+        let Some(encrypted_key) = packet.get_data(VarID::EncryptionKey) else {
+            return false;
+        };
+
+        match self.rsa.decrypt_private(&encrypted_key) {
+            Ok(key) => self.client.set_recv_key(&key),
+            Err(why) => {
+                log::warn!("Could not decrypt stream key of {}: {why:?}", self.peer);
+                return false;
+            }
+        }
+
+        let attributes = upstream.attributes().clone();
+        log::info!("{} <- {attributes}", self.peer);
+        self.client.send(attributes);
+
+        true
+    }
+
+    /// The client sent its public key, so send it our stream key and encrypt everything after.
+    fn public_key_response(&mut self, packet: &AWPacket) -> bool {
+        let Some(key) = packet.get_data(VarID::EncryptionKey) else {
+            return false;
+        };
+
+        let mut client_rsa = AWCryptRSA::default();
+        client_rsa.randomize();
+        if client_rsa.decode_public_key(&key).is_err() {
+            log::warn!("Could not decode public key of {}", self.peer);
+            return false;
+        }
+
+        match client_rsa.encrypt_public(&self.client.get_send_key()) {
+            Ok(encrypted_key) => {
+                let mut response = AWPacket::new(PacketType::StreamKeyResponse);
+                response.add_data(VarID::EncryptionKey, encrypted_key);
+                self.client.send(response);
+                self.client.encrypt_data(true);
+                true
+            }
+            Err(why) => {
+                log::warn!("Could not encrypt stream key for {}: {why:?}", self.peer);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn licenses_are_replaced() {
+        let generator = LicenseGenerator::new(&SocketAddrV4::new(Ipv4Addr::LOCALHOST, 6670));
+
+        let mut packet = AWPacket::new(PacketType::Login);
+        packet.add_data(VarID::UniverseLicense, vec![1, 2, 3]);
+        packet.add_int(VarID::ReasonCode, 0);
+        replace_license(&mut packet, &generator, 1217);
+
+        assert_eq!(
+            packet.get_data(VarID::UniverseLicense),
+            Some(generator.create_license_data(1217))
+        );
+        assert_eq!(packet.get_int(VarID::ReasonCode), Some(0));
+        assert_eq!(packet.get_vars().len(), 2);
+    }
+
+    #[test]
+    fn packets_without_a_license_are_unchanged() {
+        let generator = LicenseGenerator::new(&SocketAddrV4::new(Ipv4Addr::LOCALHOST, 6670));
+
+        let mut packet = AWPacket::new(PacketType::Heartbeat);
+        packet.add_int(VarID::ReasonCode, 0);
+        let original = packet.clone();
+        replace_license(&mut packet, &generator, 0);
+
+        assert!(packet == original);
+    }
+
+    #[test]
+    fn client_heartbeats_are_not_forwarded() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let _peer = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, peer) = listener.accept().unwrap();
+
+        let proxy = Proxy {
+            upstream: String::new(),
+            license_generator: LicenseGenerator::new(&SocketAddrV4::new(Ipv4Addr::LOCALHOST, 6670)),
+            capture_dir: None,
+        };
+        let mut session = Session {
+            peer,
+            client: AWConnection::new(AWProtocol::new(stream).unwrap()),
+            upstream: None,
+            rsa: AWCryptRSA::new(),
+            browser_build: 0,
+        };
+
+        // Without a universe connection, anything which would be forwarded ends the session
+        assert!(session.client_packet(&proxy, AWPacket::new(PacketType::Heartbeat)));
+        assert!(!session.client_packet(&proxy, AWPacket::new(PacketType::Login)));
+    }
+}
@@ -0,0 +1,471 @@
+//! What staff can do to the universe, shared by the admin API and the console.
+use std::{fmt, net::Ipv4Addr};
+
+use aw_db::DatabaseResult;
+use serde::Deserialize;
+
+use crate::{
+    client::ClientInfo,
+    database::{
+        citizen::CitizenQuery, eject::EjectionQuery, license::LicenseQuery, CitizenDB, EjectDB,
+        LicenseDB,
+    },
+    ejection::{disconnect_ejected_connections, EjectionTarget},
+    packet_handler::{check_valid_name, check_valid_world_name},
+    password,
+    player::Player,
+    telegram::send_telegram_to_everyone,
+    timestamp::unix_epoch_timestamp_u32,
+    universe_connection::UniverseConnectionID,
+    UniverseServer,
+};
+
+/// Why an action could not be carried out.
+#[derive(Debug)]
+pub enum AdminError {
+    Invalid(String),
+    NotFound(&'static str),
+    Conflict(&'static str),
+    Internal(&'static str),
+}
+
+impl fmt::Display for AdminError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdminError::Invalid(message) => write!(f, "{message}"),
+            AdminError::NotFound(what) => write!(f, "No such {what}"),
+            AdminError::Conflict(message) | AdminError::Internal(message) => {
+                write!(f, "{message}")
+            }
+        }
+    }
+}
+
+pub type AdminResult<T> = Result<T, AdminError>;
+
+/// Fields of a citizen which staff can set. Fields which are left out are not changed.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct CitizenFields {
+    pub number: Option<u32>,
+    pub name: Option<String>,
+    pub password: Option<String>,
+    pub privilege_password: Option<String>,
+    pub email: Option<String>,
+    pub comment: Option<String>,
+    pub url: Option<String>,
+    pub expiration: Option<u32>,
+    pub bot_limit: Option<u32>,
+    pub beta: Option<bool>,
+    pub enabled: Option<bool>,
+    pub trial: Option<bool>,
+    pub cav_enabled: Option<bool>,
+    pub cav_template: Option<u32>,
+    pub privacy: Option<u32>,
+}
+
+impl CitizenFields {
+    fn apply(self, citizen: &mut CitizenQuery) -> AdminResult<()> {
+        if let Some(name) = self.name {
+            check_valid_name(&name, false)
+                .map_err(|rc| AdminError::Invalid(format!("Invalid name: {rc:?}")))?;
+            citizen.name = name;
+        }
+        if let Some(password) = self.password {
+            citizen.password = hash_password(&password)?;
+        }
+        if let Some(privilege_password) = self.privilege_password {
+            citizen.priv_pass = hash_password(&privilege_password)?;
+        }
+        if let Some(email) = self.email {
+            citizen.email = email;
+        }
+        if let Some(comment) = self.comment {
+            citizen.comment = comment;
+        }
+        if let Some(url) = self.url {
+            citizen.url = url;
+        }
+        if let Some(expiration) = self.expiration {
+            citizen.expiration = expiration;
+        }
+        if let Some(bot_limit) = self.bot_limit {
+            citizen.bot_limit = bot_limit;
+        }
+        if let Some(beta) = self.beta {
+            citizen.beta = beta.into();
+        }
+        if let Some(enabled) = self.enabled {
+            citizen.enabled = enabled.into();
+        }
+        if let Some(trial) = self.trial {
+            citizen.trial = trial.into();
+        }
+        if let Some(cav_enabled) = self.cav_enabled {
+            citizen.cav_enabled = cav_enabled.into();
+        }
+        if let Some(cav_template) = self.cav_template {
+            citizen.cav_template = cav_template;
+        }
+        if let Some(privacy) = self.privacy {
+            citizen.privacy = privacy;
+        }
+
+        Ok(())
+    }
+}
+
+/// Fields of a license which staff can set. Fields which are left out are not changed.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct LicenseFields {
+    pub name: Option<String>,
+    pub password: Option<String>,
+    pub email: Option<String>,
+    pub comment: Option<String>,
+    pub expiration: Option<u32>,
+    pub users: Option<u32>,
+    pub world_size: Option<u32>,
+    pub hidden: Option<bool>,
+    pub tourists: Option<bool>,
+    pub voip: Option<bool>,
+    pub plugins: Option<bool>,
+}
+
+impl LicenseFields {
+    fn apply(self, license: &mut LicenseQuery) {
+        if let Some(password) = self.password {
+            license.password = password;
+        }
+        if let Some(email) = self.email {
+            license.email = email;
+        }
+        if let Some(comment) = self.comment {
+            license.comment = comment;
+        }
+        if let Some(expiration) = self.expiration {
+            license.expiration = expiration;
+        }
+        if let Some(users) = self.users {
+            license.users = users;
+        }
+        if let Some(world_size) = self.world_size {
+            license.world_size = world_size;
+        }
+        if let Some(hidden) = self.hidden {
+            license.hidden = hidden.into();
+        }
+        if let Some(tourists) = self.tourists {
+            license.tourists = tourists.into();
+        }
+        if let Some(voip) = self.voip {
+            license.voip = voip.into();
+        }
+        if let Some(plugins) = self.plugins {
+            license.plugins = plugins.into();
+        }
+    }
+}
+
+pub fn player_by_session_id(
+    server: &UniverseServer,
+    session_id: u16,
+) -> AdminResult<UniverseConnectionID> {
+    server
+        .connections
+        .get_by_session_id(session_id)
+        .ok_or(AdminError::NotFound("player"))
+}
+
+/// Find a player by name. Tourists may be named with or without their quotes.
+pub fn player_by_name(server: &UniverseServer, name: &str) -> AdminResult<UniverseConnectionID> {
+    server
+        .connections
+        .iter()
+        .find(|(_id, conn)| {
+            conn.client
+                .as_ref()
+                .and_then(ClientInfo::player)
+                .map(Player::username)
+                .is_some_and(|username| {
+                    username.eq_ignore_ascii_case(name)
+                        || username.trim_matches('"').eq_ignore_ascii_case(name)
+                })
+        })
+        .map(|(&id, _conn)| id)
+        .ok_or(AdminError::NotFound("player"))
+}
+
+pub fn kick(server: &mut UniverseServer, cid: UniverseConnectionID) {
+    if let Some(conn) = server.connections.get_connection_mut(cid) {
+        log::info!("Kicking {} by staff request", conn.addr().ip());
+        conn.disconnect();
+    }
+}
+
+/// Send a telegram to every citizen who is logged in, returning how many were sent.
+pub fn broadcast(server: &UniverseServer, message: &str) -> AdminResult<usize> {
+    if message.is_empty() {
+        return Err(AdminError::Invalid("The message is empty".to_string()));
+    }
+
+    database(send_telegram_to_everyone(server, message))
+}
+
+pub fn citizen_by_name(server: &UniverseServer, name: &str) -> AdminResult<CitizenQuery> {
+    database(server.database.citizen_by_name(name))?.ok_or(AdminError::NotFound("citizen"))
+}
+
+pub fn citizen_by_number(server: &UniverseServer, number: u32) -> AdminResult<CitizenQuery> {
+    database(server.database.citizen_by_number(number))?.ok_or(AdminError::NotFound("citizen"))
+}
+
+/// Add a citizen, who is given the next free number unless the fields pick one.
+pub fn add_citizen(server: &UniverseServer, fields: CitizenFields) -> AdminResult<CitizenQuery> {
+    let number = fields.number.unwrap_or(0);
+
+    let Some(name) = fields.name.clone() else {
+        return Err(AdminError::Invalid("A citizen needs a name".to_string()));
+    };
+    if fields.password.as_deref().unwrap_or_default().is_empty() {
+        return Err(AdminError::Invalid(
+            "A citizen needs a password".to_string(),
+        ));
+    }
+    if number > i32::MAX as u32 {
+        return Err(AdminError::Invalid(
+            "Citizen number is too large".to_string(),
+        ));
+    }
+
+    if database(server.database.citizen_by_name(&name))?.is_some() {
+        return Err(AdminError::Conflict("Name is already used"));
+    }
+    if number != 0 && database(server.database.citizen_by_number(number))?.is_some() {
+        return Err(AdminError::Conflict("Citizen number is already used"));
+    }
+
+    let mut citizen = CitizenQuery {
+        id: number,
+        changed: 0,
+        name: String::default(),
+        password: String::default(),
+        email: String::default(),
+        priv_pass: String::default(),
+        comment: String::default(),
+        url: String::default(),
+        immigration: unix_epoch_timestamp_u32(),
+        expiration: 0,
+        last_login: 0,
+        last_address: 0,
+        total_time: 0,
+        bot_limit: 0,
+        beta: 0,
+        cav_enabled: 0,
+        cav_template: 0,
+        enabled: 1,
+        privacy: 0,
+        trial: 0,
+    };
+    fields.apply(&mut citizen)?;
+
+    database(match number {
+        0 => server.database.citizen_add_next(citizen),
+        1.. => server.database.citizen_add(&citizen),
+    })?;
+
+    let citizen = citizen_by_name(server, &name)?;
+    log::info!("Added citizen #{} {:?}", citizen.id, citizen.name);
+
+    Ok(citizen)
+}
+
+pub fn change_citizen(
+    server: &UniverseServer,
+    number: u32,
+    fields: CitizenFields,
+) -> AdminResult<CitizenQuery> {
+    if fields.number.is_some_and(|changed| changed != number) {
+        return Err(AdminError::Invalid(
+            "Citizen numbers can't be changed".to_string(),
+        ));
+    }
+
+    let mut citizen = citizen_by_number(server, number)?;
+
+    if let Some(name) = &fields.name {
+        if let Some(other) = database(server.database.citizen_by_name(name))? {
+            if other.id != citizen.id {
+                return Err(AdminError::Conflict("Name is already used"));
+            }
+        }
+    }
+
+    let password_changed = fields.password.is_some();
+    fields.apply(&mut citizen)?;
+    database(server.database.citizen_change(&citizen))?;
+
+    // Staff choosing a password settles a change which was required
+    if password_changed {
+        database(
+            server
+                .database
+                .citizen_set_password_change_required(citizen.id, false),
+        )?;
+    }
+
+    Ok(citizen)
+}
+
+pub fn delete_citizen(server: &UniverseServer, number: u32) -> AdminResult<()> {
+    if number == 1 {
+        return Err(AdminError::Invalid(
+            "The Administrator can't be deleted".to_string(),
+        ));
+    }
+    citizen_by_number(server, number)?;
+
+    database(server.database.citizen_delete(number))?;
+    log::info!("Deleted citizen #{number}");
+
+    Ok(())
+}
+
+pub fn licenses(server: &UniverseServer) -> AdminResult<Vec<LicenseQuery>> {
+    let mut licenses = Vec::new();
+    let mut name = String::new();
+    while let Some(license) = database(server.database.license_next(&name))? {
+        name = license.name.clone();
+        licenses.push(license);
+    }
+
+    Ok(licenses)
+}
+
+pub fn license_by_name(server: &UniverseServer, name: &str) -> AdminResult<LicenseQuery> {
+    database(server.database.license_by_name(name))?.ok_or(AdminError::NotFound("license"))
+}
+
+pub fn add_license(server: &UniverseServer, fields: LicenseFields) -> AdminResult<LicenseQuery> {
+    let Some(name) = fields.name.clone() else {
+        return Err(AdminError::Invalid(
+            "A license needs a world name".to_string(),
+        ));
+    };
+    check_valid_world_name(&name)
+        .map_err(|rc| AdminError::Invalid(format!("Invalid world name: {rc:?}")))?;
+    if database(server.database.license_by_name(&name))?.is_some() {
+        return Err(AdminError::Conflict("World already exists"));
+    }
+
+    let mut license = LicenseQuery {
+        id: 0,
+        name: name.clone(),
+        password: String::default(),
+        email: String::default(),
+        comment: String::default(),
+        creation: 0,
+        expiration: 0,
+        last_start: 0,
+        last_address: 0,
+        users: 0,
+        world_size: 0,
+        hidden: 0,
+        changed: 0,
+        tourists: 0,
+        voip: 0,
+        plugins: 0,
+    };
+    fields.apply(&mut license);
+
+    database(server.database.license_add(&license))?;
+    log::info!("Added license for {name:?}");
+
+    license_by_name(server, &name)
+}
+
+pub fn change_license(
+    server: &UniverseServer,
+    name: &str,
+    fields: LicenseFields,
+) -> AdminResult<LicenseQuery> {
+    if fields
+        .name
+        .as_ref()
+        .is_some_and(|changed| !changed.eq_ignore_ascii_case(name))
+    {
+        return Err(AdminError::Invalid("Licenses can't be renamed".to_string()));
+    }
+
+    let mut license = license_by_name(server, name)?;
+    fields.apply(&mut license);
+    database(server.database.license_change(&license))?;
+
+    license_by_name(server, name)
+}
+
+pub fn delete_license(server: &UniverseServer, name: &str) -> AdminResult<()> {
+    license_by_name(server, name)?;
+
+    database(server.database.license_delete(name))?;
+    log::info!("Deleted license for {name:?}");
+
+    Ok(())
+}
+
+pub fn ejections(server: &UniverseServer) -> AdminResult<Vec<EjectionQuery>> {
+    database(server.database.ejection_list())
+}
+
+pub fn ejection_by_id(server: &UniverseServer, id: u32) -> AdminResult<EjectionQuery> {
+    database(server.database.ejection_by_id(id))?.ok_or(AdminError::NotFound("ejection"))
+}
+
+/// Eject an address, network, serial number or citizen until `expiration`, disconnecting anyone
+/// who is now ejected.
+pub fn add_ejection(
+    server: &mut UniverseServer,
+    target: EjectionTarget,
+    expiration: u32,
+    reason: &str,
+) -> AdminResult<EjectionQuery> {
+    let now = unix_epoch_timestamp_u32();
+    let ejection = EjectionQuery::new(target, reason, 0, now, expiration);
+    let id = database(server.database.ejection_set(&ejection))?;
+    log::info!("Ejected {target} until {expiration}");
+
+    disconnect_ejected_connections(server);
+
+    ejection_by_id(server, id)
+}
+
+pub fn delete_ejection(server: &UniverseServer, id: u32) -> AdminResult<()> {
+    let ejection = ejection_by_id(server, id)?;
+
+    database(server.database.ejection_delete(id))?;
+    match ejection.target() {
+        Some(target) => log::info!("Removed the ejection of {target}"),
+        None => log::info!("Removed ejection {id}"),
+    }
+
+    Ok(())
+}
+
+/// Addresses of licenses are stored the way clients send them.
+pub fn ip_from_u32(address: u32) -> Ipv4Addr {
+    Ipv4Addr::from(address.to_le_bytes())
+}
+
+fn hash_password(password: &str) -> AdminResult<String> {
+    password::hash_password(password).map_err(|why| {
+        log::error!("Could not hash password: {why}");
+        AdminError::Internal("Could not hash password")
+    })
+}
+
+fn database<T>(result: DatabaseResult<T>) -> AdminResult<T> {
+    match result {
+        DatabaseResult::Ok(value) => Ok(value),
+        DatabaseResult::DatabaseError => Err(AdminError::Internal("Database error")),
+    }
+}
@@ -0,0 +1,263 @@
+//! HTTP management API, answering JSON requests authenticated with a token.
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    io::Read,
+    net::SocketAddr,
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::configuration::AdminApiConfig;
+
+/// How long a request may wait for the main loop to answer it.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest request body which is accepted, in bytes.
+const MAX_BODY_SIZE: u64 = 64 * 1024;
+
+#[derive(thiserror::Error, Debug)]
+pub enum AdminApiStartError {
+    #[error("The admin API needs a token to authenticate requests with")]
+    EmptyToken,
+    #[error("Could not listen on {0}: {1}")]
+    Bind(SocketAddr, Box<dyn std::error::Error + Send + Sync>),
+}
+
+pub struct AdminApi {
+    server: Arc<Server>,
+    requests: Receiver<AdminRequest>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// An authenticated request, waiting for the main loop to answer it.
+#[derive(Debug)]
+pub struct AdminRequest {
+    pub method: Method,
+    /// Segments of the path, with percent-encoding removed.
+    pub path: Vec<String>,
+    pub query: HashMap<String, String>,
+    /// JSON body of the request, or null if it had none.
+    pub body: Value,
+    respond: Sender<AdminResponse>,
+}
+
+#[derive(Debug)]
+pub struct AdminResponse {
+    pub status: u16,
+    pub body: Value,
+}
+
+impl AdminApi {
+    pub fn start(config: &AdminApiConfig) -> Result<Self, AdminApiStartError> {
+        if config.token.is_empty() {
+            return Err(AdminApiStartError::EmptyToken);
+        }
+
+        let server =
+            Server::http(config.bind).map_err(|why| AdminApiStartError::Bind(config.bind, why))?;
+        let server = Arc::new(server);
+        let (requests_tx, requests) = channel();
+
+        let token = config.token.clone();
+        let thread = {
+            let server = server.clone();
+            thread::spawn(move || serve(&server, &token, &requests_tx))
+        };
+
+        Ok(Self {
+            server,
+            requests,
+            thread: Some(thread),
+        })
+    }
+
+    /// Address the API is listening on, which tells which port was picked when binding to port 0.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    /// Take the requests which arrived since the last call.
+    pub fn pending_requests(&self) -> Vec<AdminRequest> {
+        self.requests.try_iter().collect()
+    }
+}
+
+impl Drop for AdminApi {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+impl AdminRequest {
+    pub fn respond(self, response: AdminResponse) {
+        // The HTTP thread has given up on the request if this fails
+        self.respond.send(response).ok();
+    }
+}
+
+impl AdminResponse {
+    pub fn ok(body: Value) -> Self {
+        Self { status: 200, body }
+    }
+
+    pub fn error(status: u16, message: impl Display) -> Self {
+        Self {
+            status,
+            body: json!({ "error": message.to_string() }),
+        }
+    }
+}
+
+fn serve(server: &Server, token: &str, requests: &Sender<AdminRequest>) {
+    for mut request in server.incoming_requests() {
+        let response = forward_request(&mut request, token, requests);
+        let content_type = Header::from_bytes("Content-Type", "application/json")
+            .expect("Content-Type header should be valid");
+
+        let result = request.respond(
+            Response::from_string(response.body.to_string())
+                .with_status_code(response.status)
+                .with_header(content_type),
+        );
+        if let Err(why) = result {
+            log::debug!("Could not answer admin API request: {why}");
+        }
+    }
+}
+
+/// Check and parse a request, then wait for the main loop to answer it.
+fn forward_request(
+    request: &mut Request,
+    token: &str,
+    requests: &Sender<AdminRequest>,
+) -> AdminResponse {
+    if !is_authorized(request, token) {
+        log::info!(
+            "Refused admin API request from {:?} without a valid token",
+            request.remote_addr()
+        );
+        return AdminResponse::error(401, "Missing or invalid token");
+    }
+
+    log::info!("Admin API request: {} {}", request.method(), request.url());
+
+    let mut body = String::new();
+    if let Err(why) = request
+        .as_reader()
+        .take(MAX_BODY_SIZE + 1)
+        .read_to_string(&mut body)
+    {
+        return AdminResponse::error(400, format!("Could not read body: {why}"));
+    }
+    if body.len() as u64 > MAX_BODY_SIZE {
+        return AdminResponse::error(413, "Body is too large");
+    }
+
+    let body = if body.trim().is_empty() {
+        Value::Null
+    } else {
+        match serde_json::from_str(&body) {
+            Ok(body) => body,
+            Err(why) => return AdminResponse::error(400, format!("Body is not valid JSON: {why}")),
+        }
+    };
+
+    let (path, query) = split_url(request.url());
+    let (respond, response) = channel();
+    let admin_request = AdminRequest {
+        method: request.method().clone(),
+        path,
+        query,
+        body,
+        respond,
+    };
+
+    if requests.send(admin_request).is_err() {
+        return AdminResponse::error(503, "The universe is shutting down");
+    }
+
+    response
+        .recv_timeout(RESPONSE_TIMEOUT)
+        .unwrap_or_else(|_| AdminResponse::error(503, "The universe did not answer in time"))
+}
+
+fn is_authorized(request: &Request, token: &str) -> bool {
+    request
+        .headers()
+        .iter()
+        .filter(|header| header.field.equiv("Authorization"))
+        .filter_map(|header| header.value.as_str().strip_prefix("Bearer "))
+        .any(|given| tokens_match(given.as_bytes(), token.as_bytes()))
+}
+
+/// Compare tokens in constant time, so the token can't be guessed by timing requests.
+fn tokens_match(given: &[u8], token: &[u8]) -> bool {
+    given.len() == token.len()
+        && given
+            .iter()
+            .zip(token)
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+fn split_url(url: &str) -> (Vec<String>, HashMap<String, String>) {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+
+    let path = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(percent_decode)
+        .collect();
+
+    let query = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (
+                percent_decode(&key.replace('+', " ")),
+                percent_decode(&value.replace('+', " ")),
+            )
+        })
+        .collect();
+
+    (path, query)
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,504 @@
+//! Operator console, taking commands a line at a time from standard input or a Unix socket.
+use std::{
+    io::{self, BufRead},
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+use super::actions::{self, AdminError, AdminResult, CitizenFields, LicenseFields};
+use crate::{
+    configuration::ConsoleConfig, ejection::EjectionTarget, player::Player,
+    timestamp::unix_epoch_timestamp_u32, UniverseServer,
+};
+
+const HELP: &str = "\
+Commands:
+  who                                       List players
+  worlds                                    List running worlds
+  kick <name>                               Disconnect a player
+  eject <target> <duration> [reason]        Eject an address, network, serial:<hex> or
+                                            citizen:<number>, such as for 30m, 12h or 7d
+  broadcast <message>                       Send a telegram to every citizen online
+  citizen add <name> <password> [email]     Add a citizen
+  citizen disable <name>                    Stop a citizen from logging in
+  citizen enable <name>                     Let a disabled citizen log in again
+  license add <world> <password> [duration] Add a world license, which may expire
+  license extend <world> <duration>         Make a world license last longer
+  reload                                    Apply changes to the configuration file
+  shutdown [seconds]                        Stop the universe, now or after a delay
+Names with spaces can be quoted, like \"Bob Smith\".";
+
+pub struct Console {
+    commands: Receiver<ConsoleCommand>,
+    socket: Option<PathBuf>,
+}
+
+/// A line typed into the console, waiting for the main loop to run it.
+pub struct ConsoleCommand {
+    pub line: String,
+    respond: Sender<String>,
+}
+
+impl Console {
+    pub fn start(config: &ConsoleConfig) -> io::Result<Self> {
+        let (commands_tx, commands) = channel();
+
+        if let Some(path) = &config.socket {
+            listen(path, commands_tx.clone())?;
+        }
+
+        if config.stdin {
+            thread::spawn(move || read_stdin(&commands_tx));
+        }
+
+        Ok(Self {
+            commands,
+            socket: config.socket.clone(),
+        })
+    }
+
+    /// Take the commands which arrived since the last call.
+    pub fn pending_commands(&self) -> Vec<ConsoleCommand> {
+        self.commands.try_iter().collect()
+    }
+}
+
+impl Drop for Console {
+    fn drop(&mut self) {
+        if let Some(path) = &self.socket {
+            std::fs::remove_file(path).ok();
+        }
+    }
+}
+
+impl ConsoleCommand {
+    pub fn respond(self, output: String) {
+        self.respond.send(output).ok();
+    }
+}
+
+fn read_stdin(commands: &Sender<ConsoleCommand>) {
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match send_command(commands, line) {
+            Some(output) => println!("{output}"),
+            None => return,
+        }
+    }
+}
+
+/// Pass a command to the main loop and wait for its output, which is `None` once the universe has
+/// stopped.
+fn send_command(commands: &Sender<ConsoleCommand>, line: String) -> Option<String> {
+    let (respond, output) = channel();
+    commands.send(ConsoleCommand { line, respond }).ok()?;
+    output.recv().ok()
+}
+
+#[cfg(unix)]
+fn listen(path: &Path, commands: Sender<ConsoleCommand>) -> io::Result<()> {
+    use std::fs::{self, Permissions};
+    use std::os::unix::{
+        fs::{FileTypeExt, PermissionsExt},
+        net::UnixListener,
+    };
+
+    // A universe which was killed leaves its socket behind, which would keep us from binding
+    if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+    // Only the user running the universe may send it commands
+    fs::set_permissions(path, Permissions::from_mode(0o600))?;
+    log::info!("Console listening on {}", path.display());
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let commands = commands.clone();
+                    thread::spawn(move || serve_socket(stream, &commands));
+                }
+                Err(why) => log::warn!("Failed to accept a console connection: {why}"),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn listen(_path: &Path, _commands: Sender<ConsoleCommand>) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Console sockets are only supported on Unix",
+    ))
+}
+
+#[cfg(unix)]
+fn serve_socket(stream: std::os::unix::net::UnixStream, commands: &Sender<ConsoleCommand>) {
+    use std::io::{BufReader, Write};
+
+    let Ok(mut writer) = stream.try_clone() else {
+        return;
+    };
+
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let Some(output) = send_command(commands, line) else {
+            return;
+        };
+        if writeln!(writer, "{output}").is_err() {
+            return;
+        }
+    }
+}
+
+/// Run a line typed into the console, returning what to show the operator.
+pub fn run_command(server: &mut UniverseServer, line: &str) -> String {
+    let words = split_words(line);
+    let words = words.iter().map(String::as_str).collect::<Vec<_>>();
+
+    // Passwords may follow, so only the command itself is logged
+    log::info!("Console command: {}", words.first().unwrap_or(&""));
+
+    let result = match words.as_slice() {
+        ["help"] => Ok(HELP.to_string()),
+        ["who"] => Ok(who(server)),
+        ["worlds"] => Ok(worlds(server)),
+        ["kick", name @ ..] if !name.is_empty() => kick(server, &name.join(" ")),
+        ["eject", target, duration, reason @ ..] => {
+            eject(server, target, duration, &reason.join(" "))
+        }
+        // The message is sent just as it was typed, quotes and all
+        ["broadcast", _, ..] => broadcast(server, rest_of_line(line)),
+        ["citizen", "add", name, password] => citizen_add(server, name, password, None),
+        ["citizen", "add", name, password, email] => {
+            citizen_add(server, name, password, Some(email))
+        }
+        ["citizen", "disable", name @ ..] if !name.is_empty() => {
+            citizen_enable(server, &name.join(" "), false)
+        }
+        ["citizen", "enable", name @ ..] if !name.is_empty() => {
+            citizen_enable(server, &name.join(" "), true)
+        }
+        ["license", "add", world, password] => license_add(server, world, password, None),
+        ["license", "add", world, password, duration] => {
+            license_add(server, world, password, Some(duration))
+        }
+        ["license", "extend", world, duration] => license_extend(server, world, duration),
+        ["reload"] => server
+            .reload_config()
+            .map(|()| "Reloaded the configuration".to_string())
+            .map_err(AdminError::Invalid),
+        ["shutdown"] => shutdown(server, "0"),
+        ["shutdown", seconds] => shutdown(server, seconds),
+        _ => Err(AdminError::Invalid(
+            "Unknown command, see \"help\"".to_string(),
+        )),
+    };
+
+    result.unwrap_or_else(|why| format!("Error: {why}"))
+}
+
+fn who(server: &UniverseServer) -> String {
+    let mut lines = vec![format!(
+        "{:>7}  {:<8} {:<24} {:<10} {}",
+        "Session", "Type", "Name", "World", "Address"
+    )];
+
+    for (_id, conn) in server.connections.iter() {
+        let Some(player) = conn.client.as_ref().and_then(|client| client.player()) else {
+            continue;
+        };
+        let info = player.base_player();
+        let kind = match player {
+            Player::Citizen(_) => "citizen",
+            Player::Tourist(_) => "tourist",
+            Player::Bot(_) => "bot",
+        };
+
+        lines.push(format!(
+            "{:>7}  {:<8} {:<24} {:<10} {}",
+            info.session_id,
+            kind,
+            info.username,
+            info.world.as_deref().unwrap_or("-"),
+            conn.addr().ip()
+        ));
+    }
+
+    lines.push(format!("{} players", lines.len() - 1));
+    lines.join("\n")
+}
+
+fn worlds(server: &UniverseServer) -> String {
+    let mut lines = vec![format!(
+        "{:<10} {:>9} {:<6} {:<8} {}",
+        "World", "Users", "Rating", "Status", "Address"
+    )];
+
+    let worlds = server.connections.get_all_world_entries();
+    for world in &worlds {
+        lines.push(format!(
+            "{:<10} {:>9} {:<6} {:<8} {}:{}",
+            world.name,
+            format!("{}/{}", world.user_count, world.max_users),
+            format!("{:?}", world.rating),
+            format!("{:?}", world.status),
+            world.ip,
+            world.port
+        ));
+    }
+
+    lines.push(format!("{} worlds", worlds.len()));
+    lines.join("\n")
+}
+
+fn kick(server: &mut UniverseServer, name: &str) -> AdminResult<String> {
+    let cid = actions::player_by_name(server, name)?;
+    actions::kick(server, cid);
+
+    Ok(format!("Kicked {name}"))
+}
+
+fn eject(
+    server: &mut UniverseServer,
+    target: &str,
+    duration: &str,
+    reason: &str,
+) -> AdminResult<String> {
+    let target = target
+        .parse::<EjectionTarget>()
+        .map_err(AdminError::Invalid)?;
+    let expiration = expiration_after(parse_duration(duration)?);
+
+    let ejection = actions::add_ejection(server, target, expiration, reason)?;
+
+    Ok(format!(
+        "Ejected {target} for {duration} (ejection {})",
+        ejection.id
+    ))
+}
+
+fn broadcast(server: &UniverseServer, message: &str) -> AdminResult<String> {
+    let recipients = actions::broadcast(server, message)?;
+
+    Ok(format!("Sent the message to {recipients} citizens"))
+}
+
+fn citizen_add(
+    server: &UniverseServer,
+    name: &str,
+    password: &str,
+    email: Option<&str>,
+) -> AdminResult<String> {
+    let citizen = actions::add_citizen(
+        server,
+        CitizenFields {
+            name: Some(name.to_string()),
+            password: Some(password.to_string()),
+            email: email.map(str::to_string),
+            ..Default::default()
+        },
+    )?;
+
+    Ok(format!("Added citizen #{} {}", citizen.id, citizen.name))
+}
+
+/// Enable or disable a citizen, who is kicked if they are disabled while logged in.
+fn citizen_enable(server: &mut UniverseServer, name: &str, enabled: bool) -> AdminResult<String> {
+    let citizen = actions::citizen_by_name(server, name)?;
+    if citizen.id == 1 && !enabled {
+        return Err(AdminError::Invalid(
+            "The Administrator can't be disabled".to_string(),
+        ));
+    }
+
+    actions::change_citizen(
+        server,
+        citizen.id,
+        CitizenFields {
+            enabled: Some(enabled),
+            ..Default::default()
+        },
+    )?;
+
+    if enabled {
+        return Ok(format!("Enabled citizen #{} {}", citizen.id, citizen.name));
+    }
+
+    if let Some(cid) = server.connections.get_by_citizen_id(citizen.id) {
+        actions::kick(server, cid);
+    }
+    Ok(format!("Disabled citizen #{} {}", citizen.id, citizen.name))
+}
+
+fn license_add(
+    server: &UniverseServer,
+    world: &str,
+    password: &str,
+    duration: Option<&str>,
+) -> AdminResult<String> {
+    let expiration = duration
+        .map(parse_duration)
+        .transpose()?
+        .map(expiration_after);
+
+    let license = actions::add_license(
+        server,
+        LicenseFields {
+            name: Some(world.to_string()),
+            password: Some(password.to_string()),
+            expiration,
+            ..Default::default()
+        },
+    )?;
+
+    Ok(format!("Added license for {}", license.name))
+}
+
+/// Extend a license from when it expires, or from now if it already has.
+fn license_extend(server: &UniverseServer, world: &str, duration: &str) -> AdminResult<String> {
+    let duration = parse_duration(duration)?;
+    let license = actions::license_by_name(server, world)?;
+    let expiration = license
+        .expiration
+        .max(unix_epoch_timestamp_u32())
+        .saturating_add(duration);
+
+    actions::change_license(
+        server,
+        world,
+        LicenseFields {
+            expiration: Some(expiration),
+            ..Default::default()
+        },
+    )?;
+
+    Ok(format!(
+        "License for {} now expires at {expiration}",
+        license.name
+    ))
+}
+
+fn shutdown(server: &mut UniverseServer, seconds: &str) -> AdminResult<String> {
+    let seconds = seconds
+        .parse::<u64>()
+        .map_err(|_| AdminError::Invalid(format!("{seconds} is not a number of seconds")))?;
+
+    server.shutdown_after(Duration::from_secs(seconds));
+
+    Ok(format!("Shutting down in {seconds} seconds"))
+}
+
+fn expiration_after(duration: u32) -> u32 {
+    unix_epoch_timestamp_u32().saturating_add(duration)
+}
+
+/// Parse a duration such as `90`, `30m`, `12h`, `7d` or `2w` into seconds.
+fn parse_duration(duration: &str) -> AdminResult<u32> {
+    let invalid = || AdminError::Invalid(format!("{duration} is not a duration like 30m or 7d"));
+
+    let (amount, unit) = match duration.find(|c: char| !c.is_ascii_digit()) {
+        Some(split) => duration.split_at(split),
+        None => (duration, "s"),
+    };
+    let unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+
+    amount
+        .parse::<u32>()
+        .ok()
+        .and_then(|amount| amount.checked_mul(unit))
+        .ok_or_else(invalid)
+}
+
+/// Split a line into words, keeping quoted text together.
+fn split_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    let mut in_word = false;
+
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_word = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+
+    words
+}
+
+/// Everything after the first word of a line.
+fn rest_of_line(line: &str) -> &str {
+    line.trim()
+        .split_once(char::is_whitespace)
+        .map(|(_, rest)| rest.trim_start())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_are_parsed() {
+        assert_eq!(parse_duration("90").ok(), Some(90));
+        assert_eq!(parse_duration("30m").ok(), Some(30 * 60));
+        assert_eq!(parse_duration("7d").ok(), Some(7 * 24 * 60 * 60));
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("3y").is_err());
+        assert!(parse_duration("99999999w").is_err());
+    }
+
+    #[test]
+    fn quoted_words_stay_together() {
+        assert_eq!(
+            split_words(r#"citizen add "Bob Smith"  secret"#),
+            vec!["citizen", "add", "Bob Smith", "secret"]
+        );
+        assert_eq!(split_words(r#"kick """#), vec!["kick", ""]);
+        assert_eq!(
+            rest_of_line("  broadcast  Hello \"there\""),
+            "Hello \"there\""
+        );
+    }
+}
@@ -0,0 +1,14 @@
+//! Ways for staff to administer the universe without an AW browser: an HTTP API and an operator
+//! console.
+//!
+//! Both are served on threads of their own and pass what they receive to the main loop, which
+//! carries it out between servicing clients since that is the only place the server's state can be
+//! used from.
+mod actions;
+mod api;
+mod console;
+mod routes;
+
+pub use api::{AdminApi, AdminApiStartError, AdminRequest, AdminResponse};
+pub use console::{run_command, Console};
+pub use routes::handle_request;
@@ -0,0 +1,269 @@
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+use tiny_http::Method;
+
+use super::{
+    actions::{self, ip_from_u32, AdminError, AdminResult},
+    AdminRequest, AdminResponse,
+};
+use crate::{
+    database::{citizen::CitizenQuery, eject::EjectionQuery, license::LicenseQuery},
+    ejection::EjectionTarget,
+    player::Player,
+    timestamp::unix_epoch_timestamp_u32,
+    UniverseServer,
+};
+
+/// Answer a request to the admin API.
+pub fn handle_request(server: &mut UniverseServer, request: AdminRequest) {
+    let path = request.path.iter().map(String::as_str).collect::<Vec<_>>();
+    let body = &request.body;
+
+    let result = match (&request.method, path.as_slice()) {
+        (Method::Get, ["players"]) => Ok(players(server)),
+        (Method::Post, ["players", session_id, "kick"]) => kick(server, session_id),
+        (Method::Get, ["worlds"]) => Ok(worlds(server)),
+        (Method::Post, ["broadcast"]) => broadcast(server, body),
+
+        (Method::Get, ["citizens"]) => match request.query.get("name") {
+            Some(name) => actions::citizen_by_name(server, name).map(|c| citizen_json(&c)),
+            None => Err(AdminError::Invalid(
+                "Look up citizens by number or ?name=".to_string(),
+            )),
+        },
+        (Method::Post, ["citizens"]) => parse_body(body)
+            .and_then(|fields| actions::add_citizen(server, fields))
+            .map(|citizen| citizen_json(&citizen)),
+        (Method::Get, ["citizens", number]) => parse_citizen_number(number)
+            .and_then(|number| actions::citizen_by_number(server, number))
+            .map(|citizen| citizen_json(&citizen)),
+        (Method::Put, ["citizens", number]) => parse_citizen_number(number)
+            .and_then(|number| actions::change_citizen(server, number, parse_body(body)?))
+            .map(|citizen| citizen_json(&citizen)),
+        (Method::Delete, ["citizens", number]) => parse_citizen_number(number)
+            .and_then(|number| actions::delete_citizen(server, number))
+            .map(|()| json!({})),
+
+        (Method::Get, ["licenses"]) => {
+            actions::licenses(server).map(|licenses| licenses.iter().map(license_json).collect())
+        }
+        (Method::Post, ["licenses"]) => parse_body(body)
+            .and_then(|fields| actions::add_license(server, fields))
+            .map(|license| license_json(&license)),
+        (Method::Get, ["licenses", name]) => {
+            actions::license_by_name(server, name).map(|license| license_json(&license))
+        }
+        (Method::Put, ["licenses", name]) => parse_body(body)
+            .and_then(|fields| actions::change_license(server, name, fields))
+            .map(|license| license_json(&license)),
+        (Method::Delete, ["licenses", name]) => {
+            actions::delete_license(server, name).map(|()| json!({}))
+        }
+
+        (Method::Get, ["ejections"]) => actions::ejections(server)
+            .map(|ejections| ejections.iter().map(ejection_json).collect()),
+        (Method::Post, ["ejections"]) => ejection_add(server, body),
+        (Method::Get, ["ejections", id]) => parse_ejection_id(id)
+            .and_then(|id| actions::ejection_by_id(server, id))
+            .map(|ejection| ejection_json(&ejection)),
+        (Method::Delete, ["ejections", id]) => parse_ejection_id(id)
+            .and_then(|id| actions::delete_ejection(server, id))
+            .map(|()| json!({})),
+
+        _ => Err(AdminError::NotFound("endpoint")),
+    };
+
+    let response = match result {
+        Ok(body) => AdminResponse::ok(body),
+        Err(why) => AdminResponse::error(status(&why), why),
+    };
+    request.respond(response);
+}
+
+fn status(error: &AdminError) -> u16 {
+    match error {
+        AdminError::Invalid(_) => 400,
+        AdminError::NotFound(_) => 404,
+        AdminError::Conflict(_) => 409,
+        AdminError::Internal(_) => 500,
+    }
+}
+
+fn players(server: &UniverseServer) -> Value {
+    let players = server
+        .connections
+        .iter()
+        .filter_map(|(_id, conn)| {
+            let player = conn.client.as_ref()?.player()?;
+            let info = player.base_player();
+            let kind = match player {
+                Player::Citizen(_) => "citizen",
+                Player::Tourist(_) => "tourist",
+                Player::Bot(_) => "bot",
+            };
+
+            Some(json!({
+                "session_id": info.session_id,
+                "name": info.username,
+                "type": kind,
+                "citizen_number": player.citizen_id(),
+                "world": info.world,
+                "address": conn.addr().ip().to_string(),
+                "build": info.build,
+                "afk": info.afk,
+            }))
+        })
+        .collect();
+
+    Value::Array(players)
+}
+
+fn kick(server: &mut UniverseServer, session_id: &str) -> AdminResult<Value> {
+    let session_id = session_id
+        .parse::<u16>()
+        .map_err(|_| AdminError::Invalid("Session IDs are numbers".to_string()))?;
+    let cid = actions::player_by_session_id(server, session_id)?;
+    actions::kick(server, cid);
+
+    Ok(json!({}))
+}
+
+fn worlds(server: &UniverseServer) -> Value {
+    let worlds = server
+        .connections
+        .get_all_world_entries()
+        .into_iter()
+        .map(|world| {
+            json!({
+                "name": world.name,
+                "status": format!("{:?}", world.status),
+                "rating": format!("{:?}", world.rating),
+                "address": world.ip.to_string(),
+                "port": world.port,
+                "users": world.user_count,
+                "max_users": world.max_users,
+                "world_size": world.world_size,
+            })
+        })
+        .collect();
+
+    Value::Array(worlds)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Broadcast {
+    message: String,
+}
+
+fn broadcast(server: &UniverseServer, body: &Value) -> AdminResult<Value> {
+    let Broadcast { message } = parse_body(body)?;
+    let recipients = actions::broadcast(server, &message)?;
+
+    Ok(json!({ "recipients": recipients }))
+}
+
+/// Everything about a citizen except their passwords.
+fn citizen_json(citizen: &CitizenQuery) -> Value {
+    json!({
+        "number": citizen.id,
+        "name": citizen.name,
+        "email": citizen.email,
+        "comment": citizen.comment,
+        "url": citizen.url,
+        "immigration": citizen.immigration,
+        "expiration": citizen.expiration,
+        "last_login": citizen.last_login,
+        "last_address": ip_from_u32(citizen.last_address).to_string(),
+        "total_time": citizen.total_time,
+        "bot_limit": citizen.bot_limit,
+        "beta": citizen.beta != 0,
+        "enabled": citizen.enabled != 0,
+        "trial": citizen.trial != 0,
+        "cav_enabled": citizen.cav_enabled != 0,
+        "cav_template": citizen.cav_template,
+        "privacy": citizen.privacy,
+    })
+}
+
+fn license_json(license: &LicenseQuery) -> Value {
+    json!({
+        "id": license.id,
+        "name": license.name,
+        "password": license.password,
+        "email": license.email,
+        "comment": license.comment,
+        "creation": license.creation,
+        "expiration": license.expiration,
+        "last_start": license.last_start,
+        "last_address": ip_from_u32(license.last_address).to_string(),
+        "users": license.users,
+        "world_size": license.world_size,
+        "hidden": license.hidden != 0,
+        "tourists": license.tourists != 0,
+        "voip": license.voip != 0,
+        "plugins": license.plugins != 0,
+    })
+}
+
+fn ejection_json(ejection: &EjectionQuery) -> Value {
+    json!({
+        "id": ejection.id,
+        "target": ejection.target().map(|target| target.to_string()),
+        "reason": ejection.reason,
+        "admin": ejection.admin,
+        "creation": ejection.creation,
+        "expiration": ejection.expiration,
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NewEjection {
+    /// Address, network, `serial:<hex>` or `citizen:<number>` to eject
+    #[serde(alias = "address")]
+    target: String,
+    /// Time the ejection ends at
+    expiration: Option<u32>,
+    /// Seconds from now until the ejection ends, instead of an expiration
+    duration: Option<u32>,
+    #[serde(default, alias = "comment")]
+    reason: String,
+}
+
+fn ejection_add(server: &mut UniverseServer, body: &Value) -> AdminResult<Value> {
+    let ejection: NewEjection = parse_body(body)?;
+
+    let expiration = match (ejection.expiration, ejection.duration) {
+        (Some(expiration), None) => expiration,
+        (None, Some(duration)) => unix_epoch_timestamp_u32().saturating_add(duration),
+        _ => {
+            return Err(AdminError::Invalid(
+                "An ejection needs either an expiration or a duration".to_string(),
+            ))
+        }
+    };
+
+    let target = ejection
+        .target
+        .parse::<EjectionTarget>()
+        .map_err(AdminError::Invalid)?;
+
+    actions::add_ejection(server, target, expiration, &ejection.reason)
+        .map(|ejection| ejection_json(&ejection))
+}
+
+fn parse_body<T: DeserializeOwned>(body: &Value) -> AdminResult<T> {
+    T::deserialize(body).map_err(|why| AdminError::Invalid(format!("Invalid body: {why}")))
+}
+
+fn parse_citizen_number(number: &str) -> AdminResult<u32> {
+    number
+        .parse()
+        .map_err(|_| AdminError::Invalid("Citizen numbers are numbers".to_string()))
+}
+
+fn parse_ejection_id(id: &str) -> AdminResult<u32> {
+    id.parse()
+        .map_err(|_| AdminError::Invalid("Ejection IDs are numbers".to_string()))
+}
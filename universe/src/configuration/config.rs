@@ -1,7 +1,14 @@
-use std::{env, net::Ipv4Addr, path::PathBuf};
+use std::{
+    env,
+    fs::OpenOptions,
+    io::Write,
+    net::{Ipv4Addr, SocketAddr},
+    path::PathBuf,
+};
 
-use super::configurator::run_configurator;
-use aw_db::{DatabaseConfig, DatabaseType, MysqlConfig, SqliteConfig};
+use super::{configurator::run_configurator, ConfigOverrides};
+use aw_core::ProtocolVersion;
+use aw_db::{DatabaseConfig, DatabaseType, MysqlConfig, PostgresConfig, SqliteConfig};
 use serde::{Deserialize, Serialize};
 use std::path::Path;
 
@@ -23,37 +30,194 @@ pub struct UniverseConfig {
     pub allow_immigration: bool,
     pub connection_limit: u16,
     pub player_limit: u16,
+    /// Protocol version every client speaks, which is otherwise guessed from their first packet
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<ProtocolVersion>,
+    /// Directory to record the packets of every connection in, one file each. Passwords are left
+    /// out, but everything else players send is kept, including their messages and email addresses.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capture_dir: Option<PathBuf>,
+    /// HTTP management API, which is disabled if this section is left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin_api: Option<AdminApiConfig>,
+    /// Operator console, which is disabled if this section is left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub console: Option<ConsoleConfig>,
+    /// Metrics endpoint, which is disabled if this section is left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<MetricsConfig>,
+    /// Config files written before graceful shutdowns don't have this section
+    #[serde(default)]
+    pub shutdown: ShutdownConfig,
+}
+
+/// Configuration section for the admin API
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct AdminApiConfig {
+    /// Address to accept HTTP requests on
+    pub bind: SocketAddr,
+    /// Secret which requests must send as `Authorization: Bearer <token>`
+    pub token: String,
+}
+
+/// Configuration section for the operator console
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct ConsoleConfig {
+    /// Read commands from standard input
+    #[serde(default)]
+    pub stdin: bool,
+    /// Path of a Unix domain socket to accept commands on, one per line
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub socket: Option<PathBuf>,
+}
+
+/// Configuration section for the metrics endpoint
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct MetricsConfig {
+    /// Address to serve metrics on, which should not be reachable from outside
+    pub bind: SocketAddr,
+}
+
+/// Configuration section for shutting down
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ShutdownConfig {
+    /// Seconds to count down for when a signal asks the universe to stop
+    pub delay: u64,
+    /// Message shown to every player while counting down, with `{seconds}` replaced by the time left
+    pub warning: String,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            delay: 60,
+            warning: "The universe is shutting down in {seconds} seconds.".to_string(),
+        }
+    }
 }
 
 impl Config {
-    /// Read and (if necessary) generate configuation file.
-    pub fn get_interactive(config_path: impl AsRef<Path>) -> Result<Self, String> {
-        // Check if config file exists. If not, run configurator.
-        // If it does exist, parse it.
+    /// Read the configuration file, or start from the defaults if there is none, and replace the
+    /// settings which `overrides` gives. Nothing is written to the file.
+    pub fn get(config_path: impl AsRef<Path>, overrides: ConfigOverrides) -> Result<Self, String> {
         let config_path = config_path.as_ref();
 
-        let config = if !config_path.exists() {
-            println!(
-                "No config file was found at {}. Running configurator.",
+        let mut config = if config_path.exists() {
+            Self::load(config_path)?
+        } else {
+            log::info!(
+                "No config file was found at {}, so the defaults are used.",
                 config_path.display()
             );
-            run_configurator()
-        } else {
-            match std::fs::read_to_string(config_path) {
-                Ok(contents) => toml::from_str(&contents).map_err(|e| e.to_string())?,
-                Err(why) => Err(why.to_string())?,
-            }
+            Self::default()
         };
-
-        config.save(config_path);
+        overrides.apply(&mut config)?;
 
         Ok(config)
     }
 
-    /// Write configuation to disk.
-    pub fn save(&self, config_path: impl AsRef<Path>) {
-        let contents = toml::to_string(&self).unwrap_or_default();
-        std::fs::write(config_path, contents).ok();
+    /// Ask for the basic settings on the terminal and write them to a new configuration file.
+    pub fn configure(config_path: impl AsRef<Path>) -> Result<(), String> {
+        let config_path = config_path.as_ref();
+        println!(
+            "No config file was found at {}. Running configurator.",
+            config_path.display()
+        );
+        run_configurator().create(config_path)
+    }
+
+    /// Read an existing configuration file.
+    pub fn load(config_path: impl AsRef<Path>) -> Result<Self, String> {
+        match std::fs::read_to_string(config_path) {
+            Ok(contents) => toml::from_str(&contents).map_err(|e| e.to_string()),
+            Err(why) => Err(why.to_string()),
+        }
+    }
+
+    /// Write configuration to a new file. An existing file is never replaced.
+    pub fn create(&self, config_path: impl AsRef<Path>) -> Result<(), String> {
+        let contents = toml::to_string(&self).map_err(|e| e.to_string())?;
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(config_path)
+            .map_err(|e| e.to_string())?;
+        file.write_all(contents.as_bytes())
+            .map_err(|e| e.to_string())
+    }
+
+    /// Problems which would keep the universe from starting or from working as intended.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = self.universe.validate();
+
+        let sql = &self.sql;
+        let (server, hostname, database) = match sql.database_type {
+            DatabaseType::Internal => {
+                if sql.sqlite_config.path.is_empty() {
+                    problems.push("sqlite_config.path must not be empty".to_string());
+                }
+                return problems;
+            }
+            DatabaseType::External => (
+                "mysql_config",
+                &sql.mysql_config.hostname,
+                &sql.mysql_config.database,
+            ),
+            DatabaseType::Postgres => (
+                "postgres_config",
+                &sql.postgres_config.hostname,
+                &sql.postgres_config.database,
+            ),
+        };
+        if hostname.is_empty() {
+            problems.push(format!("{server}.hostname must not be empty"));
+        }
+        if database.is_empty() {
+            problems.push(format!("{server}.database must not be empty"));
+        }
+
+        problems
+    }
+}
+
+impl UniverseConfig {
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if self.license_ip.is_unspecified() {
+            problems
+                .push("license_ip must be the address clients connect to, not 0.0.0.0".to_string());
+        }
+        if self.connection_limit == 0 {
+            problems.push("connection_limit must be at least 1".to_string());
+        }
+        if self.player_limit > self.connection_limit {
+            problems.push("player_limit must not be more than connection_limit".to_string());
+        }
+        if let Some(capture_dir) = &self.capture_dir {
+            if capture_dir.exists() && !capture_dir.is_dir() {
+                problems.push(format!(
+                    "capture_dir {} is not a directory",
+                    capture_dir.display()
+                ));
+            }
+        }
+        if let Some(admin_api) = &self.admin_api {
+            if admin_api.token.is_empty() {
+                problems.push("admin_api.token must not be empty".to_string());
+            }
+        }
+        if let Some(console) = &self.console {
+            if !console.stdin && console.socket.is_none() {
+                problems.push("console must enable stdin or a socket".to_string());
+            }
+            if cfg!(not(unix)) && console.socket.is_some() {
+                problems.push("console.socket needs Unix domain sockets".to_string());
+            }
+        }
+
+        problems
     }
 }
 
@@ -69,6 +233,12 @@ impl Default for Config {
                 allow_immigration: true,
                 connection_limit: 200,
                 player_limit: 100,
+                protocol: None,
+                capture_dir: None,
+                admin_api: None,
+                console: None,
+                metrics: None,
+                shutdown: ShutdownConfig::default(),
             },
             sql: DatabaseConfig {
                 database_type: DatabaseType::Internal,
@@ -88,7 +258,45 @@ impl Default for Config {
                         path_str
                     },
                 },
+                postgres_config: PostgresConfig::default(),
             },
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_replace_settings_and_problems_are_found() {
+        let mut config = Config::default();
+        let overrides = ConfigOverrides {
+            port: Some(5670),
+            player_limit: Some(500),
+            protocol: Some(ProtocolVersion::V6),
+            metrics_bind: Some("127.0.0.1:9100".parse().unwrap()),
+            database_type: Some(DatabaseType::Postgres),
+            postgres_database: Some(String::new()),
+            ..Default::default()
+        };
+        overrides.apply(&mut config).unwrap();
+
+        assert_eq!(config.universe.port, 5670);
+        assert_eq!(config.universe.protocol, Some(ProtocolVersion::V6));
+        assert!(config.universe.metrics.is_some());
+        assert_eq!(
+            config.validate(),
+            vec![
+                "player_limit must not be more than connection_limit",
+                "postgres_config.database must not be empty",
+            ]
+        );
+
+        let admin_api_without_token = ConfigOverrides {
+            admin_api_bind: Some("127.0.0.1:8080".parse().unwrap()),
+            ..Default::default()
+        };
+        assert!(admin_api_without_token.apply(&mut config).is_err());
+    }
+}
@@ -1,6 +1,6 @@
 use std::net::Ipv4Addr;
 
-use aw_db::{DatabaseType, MysqlConfig, SqliteConfig};
+use aw_db::{DatabaseType, MysqlConfig, PostgresConfig, SqliteConfig};
 
 use super::Config;
 
@@ -38,13 +38,14 @@ fn config_universe(config: &mut Config) {
 /// Configure the database settings, internal or external
 fn config_database(config: &mut Config) {
     get_db_type(
-        "Enter \"internal\" or \"external\" database.",
+        "Enter \"internal\", \"external\" (MySQL) or \"postgres\" database.",
         &mut config.sql.database_type,
     );
 
     match config.sql.database_type {
         DatabaseType::External => config_mysql(&mut config.sql.mysql_config),
         DatabaseType::Internal => config_sqlite(&mut config.sql.sqlite_config),
+        DatabaseType::Postgres => config_postgres(&mut config.sql.postgres_config),
     }
 }
 
@@ -78,6 +79,36 @@ fn config_mysql(config: &mut MysqlConfig) {
     );
 }
 
+/// Configure the Postgres database
+fn config_postgres(config: &mut PostgresConfig) {
+    // Get hostname
+    get_string(
+        "Enter the hostname of the Postgres server.",
+        &mut config.hostname,
+    );
+
+    // Get port
+    get_port("Enter the port of the Postgres server.", &mut config.port);
+
+    // Get username
+    get_string(
+        "Enter the username for the Postgres server.",
+        &mut config.username,
+    );
+
+    // Get password
+    get_string(
+        "Enter the password for the Postgres server.",
+        &mut config.password,
+    );
+
+    // Get database
+    get_string(
+        "Enter the database name to use on the Postgres server.",
+        &mut config.database,
+    );
+}
+
 /// Configure the internal database
 fn config_sqlite(config: &mut SqliteConfig) {
     // Get path
@@ -160,6 +191,7 @@ fn get_db_type(message: &str, default_value: &mut DatabaseType) {
         let default_str = match default_value {
             DatabaseType::External => "External",
             DatabaseType::Internal => "Internal",
+            DatabaseType::Postgres => "Postgres",
         };
         println!("{} Default: {}", message, default_str);
         let mut input = String::new();
@@ -177,8 +209,9 @@ fn get_db_type(message: &str, default_value: &mut DatabaseType) {
         match input.trim().to_lowercase().as_str() {
             "internal" => *default_value = DatabaseType::Internal,
             "external" => *default_value = DatabaseType::External,
+            "postgres" => *default_value = DatabaseType::Postgres,
             _ => {
-                println!("Invalid database type. Choose internal or external.");
+                println!("Invalid database type. Choose internal, external or postgres.");
                 continue;
             }
         }
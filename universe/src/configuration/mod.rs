@@ -1,4 +1,6 @@
 mod config;
-pub use config::{Config, UniverseConfig};
+pub use config::{AdminApiConfig, Config, ConsoleConfig, MetricsConfig, UniverseConfig};
 
 mod configurator;
+mod overrides;
+pub use overrides::ConfigOverrides;
@@ -0,0 +1,242 @@
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    path::PathBuf,
+};
+
+use aw_core::ProtocolVersion;
+use aw_db::DatabaseType;
+
+use super::{AdminApiConfig, Config, ConsoleConfig, MetricsConfig};
+
+/// Settings given on the command line or in `AWU_*` environment variables, which take precedence
+/// over the configuration file. Flags take precedence over environment variables.
+#[derive(clap::Args, Debug, Default, Clone)]
+pub struct ConfigOverrides {
+    #[clap(long, env = "AWU_LICENSE_IP", value_parser)]
+    /// IP address clients connect to, which licenses are made out to
+    pub license_ip: Option<Ipv4Addr>,
+
+    #[clap(long, env = "AWU_BIND_IP", value_parser)]
+    /// IP address to accept connections on
+    pub bind_ip: Option<Ipv4Addr>,
+
+    #[clap(long, env = "AWU_PORT", value_parser)]
+    /// Port to accept connections on
+    pub port: Option<u16>,
+
+    #[clap(long, env = "AWU_USER_LIST", value_parser)]
+    /// Whether players can see who is online: <true | false>
+    pub user_list: Option<bool>,
+
+    #[clap(long, env = "AWU_ALLOW_CITIZEN_CHANGES", value_parser)]
+    /// Whether citizens can change their own details: <true | false>
+    pub allow_citizen_changes: Option<bool>,
+
+    #[clap(long, env = "AWU_ALLOW_IMMIGRATION", value_parser)]
+    /// Whether tourists can become citizens: <true | false>
+    pub allow_immigration: Option<bool>,
+
+    #[clap(long, env = "AWU_CONNECTION_LIMIT", value_parser)]
+    /// Most clients which can be connected at once
+    pub connection_limit: Option<u16>,
+
+    #[clap(long, env = "AWU_PLAYER_LIMIT", value_parser)]
+    /// Most players which can be logged in at once
+    pub player_limit: Option<u16>,
+
+    #[clap(long, env = "AWU_PROTOCOL", value_parser = parse_protocol)]
+    /// Protocol version every client speaks, instead of guessing it: <v4 | v6>
+    pub protocol: Option<ProtocolVersion>,
+
+    #[clap(long, env = "AWU_CAPTURE_DIR", value_parser)]
+    /// Directory to record the packets of every connection in
+    pub capture_dir: Option<PathBuf>,
+
+    #[clap(long, env = "AWU_ADMIN_API_BIND", value_parser)]
+    /// Address to serve the admin API on
+    pub admin_api_bind: Option<SocketAddr>,
+
+    #[clap(
+        long,
+        env = "AWU_ADMIN_API_TOKEN",
+        value_parser,
+        hide_env_values = true
+    )]
+    /// Secret which admin API requests must send
+    pub admin_api_token: Option<String>,
+
+    #[clap(long, env = "AWU_CONSOLE_STDIN", value_parser)]
+    /// Whether to read console commands from standard input: <true | false>
+    pub console_stdin: Option<bool>,
+
+    #[clap(long, env = "AWU_CONSOLE_SOCKET", value_parser)]
+    /// Unix domain socket to accept console commands on
+    pub console_socket: Option<PathBuf>,
+
+    #[clap(long, env = "AWU_METRICS_BIND", value_parser)]
+    /// Address to serve metrics on
+    pub metrics_bind: Option<SocketAddr>,
+
+    #[clap(long, env = "AWU_SHUTDOWN_DELAY", value_parser)]
+    /// Seconds to count down for when asked to stop
+    pub shutdown_delay: Option<u64>,
+
+    #[clap(long, env = "AWU_SHUTDOWN_WARNING", value_parser)]
+    /// Telegram sent while shutting down, with {seconds} replaced by the time left
+    pub shutdown_warning: Option<String>,
+
+    #[clap(long, env = "AWU_DATABASE_TYPE", value_parser = parse_database_type)]
+    /// Database to use: <internal | external | postgres>
+    pub database_type: Option<DatabaseType>,
+
+    #[clap(long, env = "AWU_SQLITE_PATH", value_parser)]
+    /// File of the internal database
+    pub sqlite_path: Option<String>,
+
+    #[clap(long, env = "AWU_MYSQL_HOSTNAME", value_parser)]
+    /// Hostname of the MySQL server
+    pub mysql_hostname: Option<String>,
+
+    #[clap(long, env = "AWU_MYSQL_PORT", value_parser)]
+    /// Port of the MySQL server
+    pub mysql_port: Option<u16>,
+
+    #[clap(long, env = "AWU_MYSQL_USERNAME", value_parser)]
+    /// User to log in to the MySQL server as
+    pub mysql_username: Option<String>,
+
+    #[clap(long, env = "AWU_MYSQL_PASSWORD", value_parser, hide_env_values = true)]
+    /// Password of the MySQL user
+    pub mysql_password: Option<String>,
+
+    #[clap(long, env = "AWU_MYSQL_DATABASE", value_parser)]
+    /// Database to use on the MySQL server
+    pub mysql_database: Option<String>,
+
+    #[clap(long, env = "AWU_POSTGRES_HOSTNAME", value_parser)]
+    /// Hostname of the Postgres server
+    pub postgres_hostname: Option<String>,
+
+    #[clap(long, env = "AWU_POSTGRES_PORT", value_parser)]
+    /// Port of the Postgres server
+    pub postgres_port: Option<u16>,
+
+    #[clap(long, env = "AWU_POSTGRES_USERNAME", value_parser)]
+    /// User to log in to the Postgres server as
+    pub postgres_username: Option<String>,
+
+    #[clap(
+        long,
+        env = "AWU_POSTGRES_PASSWORD",
+        value_parser,
+        hide_env_values = true
+    )]
+    /// Password of the Postgres user
+    pub postgres_password: Option<String>,
+
+    #[clap(long, env = "AWU_POSTGRES_DATABASE", value_parser)]
+    /// Database to use on the Postgres server
+    pub postgres_database: Option<String>,
+}
+
+impl ConfigOverrides {
+    /// Replace the settings of `config` which were given. Sections which are left out of the
+    /// file, like the admin API's, are added when everything they need was given.
+    pub fn apply(self, config: &mut Config) -> Result<(), String> {
+        let universe = &mut config.universe;
+        set(&mut universe.license_ip, self.license_ip);
+        set(&mut universe.bind_ip, self.bind_ip);
+        set(&mut universe.port, self.port);
+        set(&mut universe.user_list, self.user_list);
+        set(
+            &mut universe.allow_citizen_changes,
+            self.allow_citizen_changes,
+        );
+        set(&mut universe.allow_immigration, self.allow_immigration);
+        set(&mut universe.connection_limit, self.connection_limit);
+        set(&mut universe.player_limit, self.player_limit);
+        if self.protocol.is_some() {
+            universe.protocol = self.protocol;
+        }
+        if self.capture_dir.is_some() {
+            universe.capture_dir = self.capture_dir;
+        }
+
+        match (
+            &mut universe.admin_api,
+            self.admin_api_bind,
+            self.admin_api_token,
+        ) {
+            (_, None, None) => {}
+            (Some(admin_api), bind, token) => {
+                set(&mut admin_api.bind, bind);
+                set(&mut admin_api.token, token);
+            }
+            (None, Some(bind), Some(token)) => {
+                universe.admin_api = Some(AdminApiConfig { bind, token });
+            }
+            (None, _, _) => {
+                return Err(
+                    "Enabling the admin API needs both its address and its token".to_string(),
+                )
+            }
+        }
+
+        if self.console_stdin.is_some() || self.console_socket.is_some() {
+            let console = universe.console.get_or_insert(ConsoleConfig {
+                stdin: false,
+                socket: None,
+            });
+            set(&mut console.stdin, self.console_stdin);
+            if self.console_socket.is_some() {
+                console.socket = self.console_socket;
+            }
+        }
+
+        if let Some(bind) = self.metrics_bind {
+            universe.metrics = Some(MetricsConfig { bind });
+        }
+
+        set(&mut universe.shutdown.delay, self.shutdown_delay);
+        set(&mut universe.shutdown.warning, self.shutdown_warning);
+
+        let sql = &mut config.sql;
+        set(&mut sql.database_type, self.database_type);
+        set(&mut sql.sqlite_config.path, self.sqlite_path);
+        set(&mut sql.mysql_config.hostname, self.mysql_hostname);
+        set(&mut sql.mysql_config.port, self.mysql_port);
+        set(&mut sql.mysql_config.username, self.mysql_username);
+        set(&mut sql.mysql_config.password, self.mysql_password);
+        set(&mut sql.mysql_config.database, self.mysql_database);
+        set(&mut sql.postgres_config.hostname, self.postgres_hostname);
+        set(&mut sql.postgres_config.port, self.postgres_port);
+        set(&mut sql.postgres_config.username, self.postgres_username);
+        set(&mut sql.postgres_config.password, self.postgres_password);
+        set(&mut sql.postgres_config.database, self.postgres_database);
+
+        Ok(())
+    }
+}
+
+fn set<T>(setting: &mut T, value: Option<T>) {
+    if let Some(value) = value {
+        *setting = value;
+    }
+}
+
+fn parse_protocol(value: &str) -> Result<ProtocolVersion, String> {
+    match value.to_lowercase().as_str() {
+        "v4" => Ok(ProtocolVersion::V4),
+        "v6" => Ok(ProtocolVersion::V6),
+        _ => Err("Choose v4 or v6".to_string()),
+    }
+}
+
+fn parse_database_type(value: &str) -> Result<DatabaseType, String> {
+    match value.to_lowercase().as_str() {
+        "internal" => Ok(DatabaseType::Internal),
+        "external" => Ok(DatabaseType::External),
+        "postgres" => Ok(DatabaseType::Postgres),
+        _ => Err("Choose internal, external or postgres".to_string()),
+    }
+}
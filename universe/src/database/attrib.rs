@@ -49,13 +49,15 @@ impl From<Attribute> for u16 {
 }
 
 pub trait AttribDB {
-    fn init_attrib(&self, universe_config: &UniverseConfig) -> DatabaseResult<()>;
+    fn init_attrib(&self) -> DatabaseResult<()>;
+    /// Store the attributes which come from the universe configuration.
+    fn attrib_set_config(&self, universe_config: &UniverseConfig) -> DatabaseResult<()>;
     fn attrib_set(&self, attribute_id: Attribute, value: &str) -> DatabaseResult<()>;
     fn attrib_get(&self) -> DatabaseResult<HashMap<Attribute, String>>;
 }
 
 impl AttribDB for UniverseDatabase {
-    fn init_attrib(&self, universe_config: &UniverseConfig) -> DatabaseResult<()> {
+    fn init_attrib(&self) -> DatabaseResult<()> {
         let r = self.db.exec(
             r"CREATE TABLE IF NOT EXISTS awu_attrib ( 
             ID INTEGER PRIMARY KEY NOT NULL default '0', 
@@ -65,10 +67,13 @@ impl AttribDB for UniverseDatabase {
             vec![],
         );
 
-        if r.is_err() {
-            return DatabaseResult::DatabaseError;
+        match r {
+            DatabaseResult::Ok(_) => DatabaseResult::Ok(()),
+            DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
         }
+    }
 
+    fn attrib_set_config(&self, universe_config: &UniverseConfig) -> DatabaseResult<()> {
         // Unimplemented: mail template
         // Unimplemented: mail file
         // Unimplemented: mail command
@@ -94,46 +99,48 @@ impl AttribDB for UniverseDatabase {
     }
 
     fn attrib_set(&self, attribute_id: Attribute, value: &str) -> DatabaseResult<()> {
-        // Check if attribute is already in the database
-        let rows = match self.db.exec(
-            r"SELECT * FROM awu_attrib WHERE ID=?",
-            aw_params!(attribute_id as u32),
-        ) {
-            DatabaseResult::Ok(rows) => rows,
-            DatabaseResult::DatabaseError => return DatabaseResult::DatabaseError,
-        };
-
-        if rows.is_empty() {
-            // Add the attribute if it is not already existent
-            let r = self.db.exec(
-                r"INSERT INTO awu_attrib (ID, Value) VALUES(?, ?);",
-                aw_params!(attribute_id as u32, value),
-            );
-
-            match r {
-                DatabaseResult::Ok(_) => {}
+        self.db.transaction(|tx| {
+            // Check if attribute is already in the database
+            let rows = match tx.exec(
+                r"SELECT * FROM awu_attrib WHERE ID=?",
+                aw_params!(attribute_id as u32),
+            ) {
+                DatabaseResult::Ok(rows) => rows,
                 DatabaseResult::DatabaseError => return DatabaseResult::DatabaseError,
-            }
-            log::debug!("Set attribute {attribute_id:?} to {value}");
-        } else {
-            // Try to update the attribute if it is already present
-            let r = self.db.exec(
-                r"UPDATE awu_attrib SET Value=?, Changed=NOT Changed WHERE ID=?;",
-                aw_params! {
-                    value,
-                    attribute_id as u32
-                },
-            );
-
-            match r {
-                DatabaseResult::Ok(_) => {}
-                DatabaseResult::DatabaseError => return DatabaseResult::DatabaseError,
-            }
+            };
 
-            log::debug!("Updated attribute {attribute_id:?} to {value}");
-        }
+            if rows.is_empty() {
+                // Add the attribute if it is not already existent
+                let r = tx.exec(
+                    r"INSERT INTO awu_attrib (ID, Value) VALUES(?, ?);",
+                    aw_params!(attribute_id as u32, value),
+                );
+
+                match r {
+                    DatabaseResult::Ok(_) => {}
+                    DatabaseResult::DatabaseError => return DatabaseResult::DatabaseError,
+                }
+                log::debug!("Set attribute {attribute_id:?} to {value}");
+            } else {
+                // Try to update the attribute if it is already present
+                let r = tx.exec(
+                    r"UPDATE awu_attrib SET Value=?, Changed=1-Changed WHERE ID=?;",
+                    aw_params! {
+                        value,
+                        attribute_id as u32
+                    },
+                );
+
+                match r {
+                    DatabaseResult::Ok(_) => {}
+                    DatabaseResult::DatabaseError => return DatabaseResult::DatabaseError,
+                }
+
+                log::debug!("Updated attribute {attribute_id:?} to {value}");
+            }
 
-        DatabaseResult::Ok(())
+            DatabaseResult::Ok(())
+        })
     }
 
     fn attrib_get(&self) -> DatabaseResult<HashMap<Attribute, String>> {
@@ -1,9 +1,58 @@
-use aw_db::DatabaseResult;
+use aw_db::{aw_params, from_row, DatabaseResult, FromRow, Row};
 
 use super::UniverseDatabase;
 
+/// Custom avatar of a citizen for one template.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CavQuery {
+    pub citizen: u32,
+    pub template: u32,
+    pub keyframe1_scale: f32,
+    pub keyframe2_scale: f32,
+    pub height: f32,
+    pub skin_color: u32,
+    pub hair_color: u32,
+}
+
+from_row!(CavQuery {
+    citizen: "Citizen",
+    template: "Template",
+    keyframe1_scale: "Keyframe1Scale",
+    keyframe2_scale: "Keyframe2Scale",
+    height: "Height",
+    skin_color: "SkinColor",
+    hair_color: "HairColor",
+});
+
+/// Avatar model which citizens customize.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CavTemplateQuery {
+    pub id: u32,
+    pub template_type: u32,
+    pub rating: u32,
+    pub name: String,
+    pub model: String,
+}
+
+from_row!(CavTemplateQuery {
+    id: "ID",
+    template_type: "Type",
+    rating: "Rating",
+    name: "Name" => |name: Option<String>| name.unwrap_or_default(),
+    model: "Model",
+});
+
 pub trait CavDB {
     fn init_cav(&self) -> DatabaseResult<()>;
+    fn cav_get(&self, citizen: u32, template: u32) -> DatabaseResult<Option<CavQuery>>;
+    fn cav_set(&self, cav: &CavQuery) -> DatabaseResult<()>;
+    fn cav_delete(&self, citizen: u32, template: u32) -> DatabaseResult<()>;
+    fn cav_template_by_number(&self, id: u32) -> DatabaseResult<Option<CavTemplateQuery>>;
+    fn cav_template_next(&self, id: u32) -> DatabaseResult<Option<CavTemplateQuery>>;
+    /// Add a template if its ID is 0, or change the template with its ID. Returns the ID.
+    fn cav_template_set(&self, template: &CavTemplateQuery) -> DatabaseResult<u32>;
+    /// Delete a template along with every citizen's avatar for it.
+    fn cav_template_delete(&self, id: u32) -> DatabaseResult<()>;
 }
 
 impl CavDB for UniverseDatabase {
@@ -52,4 +101,223 @@ impl CavDB for UniverseDatabase {
 
         DatabaseResult::Ok(())
     }
+
+    fn cav_get(&self, citizen: u32, template: u32) -> DatabaseResult<Option<CavQuery>> {
+        let r = self.db.exec(
+            r"SELECT * FROM awu_cav WHERE Citizen=? AND Template=?;",
+            aw_params! {
+                citizen,
+                template
+            },
+        );
+
+        match r {
+            DatabaseResult::Ok(rows) => decode_first(&rows),
+            DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
+        }
+    }
+
+    fn cav_set(&self, cav: &CavQuery) -> DatabaseResult<()> {
+        self.db.transaction(|tx| {
+            let r = tx.exec(
+                r"DELETE FROM awu_cav WHERE Citizen=? AND Template=?;",
+                aw_params! {
+                    cav.citizen,
+                    cav.template
+                },
+            );
+
+            if r.is_err() {
+                return DatabaseResult::DatabaseError;
+            }
+
+            let r = tx.exec(
+                r"INSERT INTO awu_cav (Citizen, Template, Keyframe1Scale, Keyframe2Scale, 
+                Height, SkinColor, HairColor) 
+                VALUES(?, ?, ?, ?, ?, ?, ?);",
+                aw_params! {
+                    cav.citizen,
+                    cav.template,
+                    cav.keyframe1_scale,
+                    cav.keyframe2_scale,
+                    cav.height,
+                    cav.skin_color,
+                    cav.hair_color
+                },
+            );
+
+            match r {
+                DatabaseResult::Ok(_) => DatabaseResult::Ok(()),
+                DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
+            }
+        })
+    }
+
+    fn cav_delete(&self, citizen: u32, template: u32) -> DatabaseResult<()> {
+        let r = self.db.exec(
+            r"DELETE FROM awu_cav WHERE Citizen=? AND Template=?;",
+            aw_params! {
+                citizen,
+                template
+            },
+        );
+
+        match r {
+            DatabaseResult::Ok(_) => DatabaseResult::Ok(()),
+            DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
+        }
+    }
+
+    fn cav_template_by_number(&self, id: u32) -> DatabaseResult<Option<CavTemplateQuery>> {
+        let r = self.db.exec(
+            r"SELECT * FROM awu_cav_template WHERE ID=?;",
+            aw_params! {
+                id
+            },
+        );
+
+        match r {
+            DatabaseResult::Ok(rows) => decode_first(&rows),
+            DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
+        }
+    }
+
+    fn cav_template_next(&self, id: u32) -> DatabaseResult<Option<CavTemplateQuery>> {
+        let r = self.db.exec(
+            r"SELECT * FROM awu_cav_template WHERE ID>? ORDER BY ID LIMIT 1;",
+            aw_params! {
+                id
+            },
+        );
+
+        match r {
+            DatabaseResult::Ok(rows) => decode_first(&rows),
+            DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
+        }
+    }
+
+    fn cav_template_set(&self, template: &CavTemplateQuery) -> DatabaseResult<u32> {
+        self.db.transaction(|tx| {
+            if template.id != 0 {
+                let r = tx.exec(
+                    r"UPDATE awu_cav_template SET Type=?, Rating=?, Name=?, Model=? 
+                    WHERE ID=?;",
+                    aw_params! {
+                        template.template_type,
+                        template.rating,
+                        template.name,
+                        template.model,
+                        template.id
+                    },
+                );
+
+                return match r {
+                    DatabaseResult::Ok(_) => DatabaseResult::Ok(template.id),
+                    DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
+                };
+            }
+
+            let r = tx.exec(
+                r"INSERT INTO awu_cav_template (Type, Rating, Name, Model) 
+                VALUES(?, ?, ?, ?);",
+                aw_params! {
+                    template.template_type,
+                    template.rating,
+                    template.name,
+                    template.model
+                },
+            );
+
+            if r.is_err() {
+                return DatabaseResult::DatabaseError;
+            }
+
+            tx.last_insert_id("awu_cav_template", "ID")
+        })
+    }
+
+    fn cav_template_delete(&self, id: u32) -> DatabaseResult<()> {
+        self.db.transaction(|tx| {
+            let r = tx.exec(
+                r"DELETE FROM awu_cav WHERE Template=?;",
+                aw_params! {
+                    id
+                },
+            );
+
+            if r.is_err() {
+                return DatabaseResult::DatabaseError;
+            }
+
+            let r = tx.exec(
+                r"DELETE FROM awu_cav_template WHERE ID=?;",
+                aw_params! {
+                    id
+                },
+            );
+
+            match r {
+                DatabaseResult::Ok(_) => DatabaseResult::Ok(()),
+                DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
+            }
+        })
+    }
+}
+
+fn decode_first<T: FromRow>(rows: &[Row]) -> DatabaseResult<Option<T>> {
+    let Some(row) = rows.first() else {
+        return DatabaseResult::Ok(None);
+    };
+
+    match row.decode() {
+        DatabaseResult::Ok(value) => DatabaseResult::Ok(Some(value)),
+        DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::Config;
+
+    #[test]
+    fn cavs_are_deleted_with_their_template() {
+        let mut config = Config::default();
+        config.sql.sqlite_config.path = ":memory:".to_string();
+        let db = UniverseDatabase::new(config.sql, &config.universe).unwrap();
+
+        let mut template = CavTemplateQuery {
+            id: 0,
+            template_type: 0,
+            rating: 0,
+            name: "Dummy".to_string(),
+            model: "dummy.rwx".to_string(),
+        };
+        let DatabaseResult::Ok(id) = db.cav_template_set(&template) else {
+            panic!("Could not add a template");
+        };
+        template.id = id;
+        assert!(
+            matches!(db.cav_template_by_number(id), DatabaseResult::Ok(Some(t)) if t == template)
+        );
+
+        let cav = CavQuery {
+            citizen: 1,
+            template: id,
+            keyframe1_scale: 1.0,
+            keyframe2_scale: 1.0,
+            height: 1.5,
+            skin_color: 0xC08060,
+            hair_color: 0x202020,
+        };
+        assert!(!db.cav_set(&cav).is_err());
+        assert!(matches!(db.cav_get(1, id), DatabaseResult::Ok(Some(c)) if c == cav));
+
+        assert!(!db.cav_template_delete(id).is_err());
+        assert!(matches!(
+            db.cav_template_by_number(id),
+            DatabaseResult::Ok(None)
+        ));
+        assert!(matches!(db.cav_get(1, id), DatabaseResult::Ok(None)));
+    }
 }
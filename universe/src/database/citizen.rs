@@ -1,16 +1,21 @@
-use aw_db::{aw_params, DatabaseResult, Row};
+use aw_db::{aw_params, from_row, DatabaseResult, Transaction};
 
-use crate::timestamp::unix_epoch_timestamp_u32;
+use crate::{password, timestamp::unix_epoch_timestamp_u32};
 
 use super::UniverseDatabase;
 
+/// The password that citizen #1 used to be created with.
+const DEFAULT_ADMIN_PASSWORD: &str = "welcome";
+
 #[derive(Debug)]
 pub struct CitizenQuery {
     pub id: u32,
     pub changed: u32,
     pub name: String,
+    /// Argon2 hash of the password, or plaintext if the row predates password hashing.
     pub password: String,
     pub email: String,
+    /// Argon2 hash of the privilege password, or plaintext if the row predates password hashing.
     pub priv_pass: String,
     pub comment: String,
     pub url: String,
@@ -24,18 +29,54 @@ pub struct CitizenQuery {
     pub cav_enabled: u32,
     pub cav_template: u32,
     pub enabled: u32,
+    /// Privacy settings as the browser sent them (aw_citizen_privacy). The meaning of their bits
+    /// has no cited source, so they are stored and sent back but not enforced.
     pub privacy: u32,
     pub trial: u32,
 }
 
+from_row!(CitizenQuery {
+    id: "ID",
+    changed: "Changed",
+    name: "Name",
+    password: "Password",
+    email: "Email",
+    priv_pass: "PrivPass",
+    comment: "Comment",
+    url: "URL",
+    immigration: "Immigration",
+    expiration: "Expiration",
+    last_login: "LastLogin",
+    // It is not unexpected for LastAddress to end up in the database as a negative number.
+    last_address: "LastAddress" => |x: i64| x as u32,
+    total_time: "TotalTime",
+    bot_limit: "BotLimit",
+    beta: "Beta",
+    cav_enabled: "CAVEnabled",
+    cav_template: "CAVTemplate",
+    enabled: "Enabled",
+    privacy: "Privacy",
+    trial: "Trial",
+});
+
 pub trait CitizenDB {
     fn init_citizen(&self) -> DatabaseResult<()>;
+    /// Create citizen #1, or replace its default password, once the schema is up to date.
+    fn init_administrator(&self) -> DatabaseResult<()>;
     fn citizen_by_name(&self, name: &str) -> DatabaseResult<Option<CitizenQuery>>;
     fn citizen_by_number(&self, citizen_id: u32) -> DatabaseResult<Option<CitizenQuery>>;
     fn citizen_add(&self, citizen: &CitizenQuery) -> DatabaseResult<()>;
     fn citizen_add_next(&self, citizen: CitizenQuery) -> DatabaseResult<()>;
     fn citizen_change(&self, citizen: &CitizenQuery) -> DatabaseResult<()>;
     fn citizen_delete(&self, citizen_id: u32) -> DatabaseResult<()>;
+    /// Whether the citizen has to choose a new password before entering a world, owning bots or
+    /// being acted as.
+    fn citizen_password_change_required(&self, citizen_id: u32) -> DatabaseResult<bool>;
+    fn citizen_set_password_change_required(
+        &self,
+        citizen_id: u32,
+        required: bool,
+    ) -> DatabaseResult<()>;
 }
 
 impl CitizenDB for UniverseDatabase {
@@ -69,22 +110,46 @@ impl CitizenDB for UniverseDatabase {
             vec![],
         );
 
-        if r.is_err() {
-            return DatabaseResult::DatabaseError;
+        match r {
+            DatabaseResult::Ok(_) => DatabaseResult::Ok(()),
+            DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
         }
+    }
 
+    fn init_administrator(&self) -> DatabaseResult<()> {
         // Create default Administrator account if one doesn't exist yet
         match self.citizen_by_number(1) {
-            DatabaseResult::Ok(Some(_)) => { /* Administrator exists, no work to be done */ }
+            DatabaseResult::Ok(Some(mut admin)) => {
+                // Older universes created the Administrator with a well-known password
+                if !password::verify_password(DEFAULT_ADMIN_PASSWORD, &admin.password).is_valid() {
+                    return DatabaseResult::Ok(());
+                }
+
+                let Some((admin_password, admin_password_hash)) = generate_admin_password() else {
+                    return DatabaseResult::DatabaseError;
+                };
+                admin.password = admin_password_hash;
+                if self.citizen_change(&admin).is_err() {
+                    log::error!("Failed to change the default password of citizen #1");
+                    return DatabaseResult::DatabaseError;
+                }
+                log::warn!(
+                    "Citizen #1 was still using the default password, so it has been replaced"
+                );
+                print_admin_password(&admin.name, &admin_password);
+            }
             DatabaseResult::Ok(None) => {
                 // Administrator does not exist yet - create it
+                let Some((admin_password, admin_password_hash)) = generate_admin_password() else {
+                    return DatabaseResult::DatabaseError;
+                };
                 let now = unix_epoch_timestamp_u32();
 
                 let admin = CitizenQuery {
                     id: 1,
                     changed: 0,
                     name: "Administrator".to_string(),
-                    password: "welcome".to_string(),
+                    password: admin_password_hash,
                     //email: "support@activeworlds.com".to_string(),
                     email: Default::default(),
                     priv_pass: Default::default(),
@@ -105,15 +170,17 @@ impl CitizenDB for UniverseDatabase {
                 };
 
                 if self.citizen_add(&admin).is_err() {
-                    eprintln!("Failed to create citizen #1");
+                    log::error!("Failed to create citizen #1");
                     return DatabaseResult::DatabaseError;
                 }
-                println!("Citizen #1 created as {} / {}", admin.name, admin.password);
+                log::info!("Citizen #1 created as {}", admin.name);
+                print_admin_password(&admin.name, &admin_password);
             }
             DatabaseResult::DatabaseError => return DatabaseResult::DatabaseError,
         }
 
-        DatabaseResult::Ok(())
+        // The generated password is only good for choosing another
+        self.citizen_set_password_change_required(1, true)
     }
 
     fn citizen_by_name(&self, name: &str) -> DatabaseResult<Option<CitizenQuery>> {
@@ -136,7 +203,7 @@ impl CitizenDB for UniverseDatabase {
             return DatabaseResult::Ok(None);
         };
 
-        match fetch_citizen(user) {
+        match user.decode() {
             DatabaseResult::Ok(user) => DatabaseResult::Ok(Some(user)),
             DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
         }
@@ -162,7 +229,7 @@ impl CitizenDB for UniverseDatabase {
             return DatabaseResult::Ok(None);
         };
 
-        match fetch_citizen(user) {
+        match user.decode() {
             DatabaseResult::Ok(user) => DatabaseResult::Ok(Some(user)),
             DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
         }
@@ -206,67 +273,54 @@ impl CitizenDB for UniverseDatabase {
         }
     }
 
-    fn citizen_add_next(&self, mut citizen: CitizenQuery) -> DatabaseResult<()> {
-        // Get the next unused ID from the database
-        let rows = match self
-            .db
-            .exec("SELECT MAX(ID) + 1 AS ID FROM awu_citizen", vec![])
-        {
-            DatabaseResult::Ok(rows) => rows,
-            DatabaseResult::DatabaseError => return DatabaseResult::DatabaseError,
-        };
-        let next_id = rows.first();
-
-        let id: u32 = match next_id {
-            Some(row) => match row.fetch_int("ID").map(u32::try_from) {
-                Some(Ok(x)) => x,
-                _ => return DatabaseResult::DatabaseError,
-            },
-            None => 1,
-        };
-
-        citizen.id = id;
-
-        let r = self.db.exec(
-            r"INSERT INTO awu_citizen(
-                ID, Immigration, Expiration, LastLogin, LastAddress, TotalTime, 
-                BotLimit, Beta, Enabled, Trial, Privacy, CAVEnabled, CAVTemplate, 
-                Name, Password, Email, PrivPass, Comment, URL) 
-            VALUES(?, ?, ?, ?, ?, ?, 
-                ?, ?, ?, ?, ?, ?, ?, 
-                ?, ?, ?, ?, ?, ?)",
-            aw_params! {
-                citizen.id,
-                citizen.immigration,
-                citizen.expiration,
-                citizen.last_login,
-                citizen.last_address,
-                citizen.total_time,
-                citizen.bot_limit,
-                citizen.beta,
-                citizen.enabled,
-                citizen.trial,
-                citizen.privacy,
-                citizen.cav_enabled,
-                citizen.cav_template,
-                &citizen.name,
-                &citizen.password,
-                &citizen.email,
-                &citizen.priv_pass,
-                &citizen.comment,
-                &citizen.url
-            },
-        );
+    fn citizen_add_next(&self, citizen: CitizenQuery) -> DatabaseResult<()> {
+        self.db.transaction(|tx| {
+            // Citizen #1 is inserted with its ID, which Postgres doesn't count towards the next one
+            if tx.auto_increment_reset("awu_citizen", "ID").is_err() {
+                return DatabaseResult::DatabaseError;
+            }
 
-        match r {
-            DatabaseResult::Ok(_) => DatabaseResult::Ok(()),
-            DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
-        }
+            // The database picks the next ID as the row is inserted, so it can't be given out twice
+            let r = tx.exec(
+                r"INSERT INTO awu_citizen(
+                    Immigration, Expiration, LastLogin, LastAddress, TotalTime, 
+                    BotLimit, Beta, Enabled, Trial, Privacy, CAVEnabled, CAVTemplate, 
+                    Name, Password, Email, PrivPass, Comment, URL) 
+                VALUES(?, ?, ?, ?, ?, 
+                    ?, ?, ?, ?, ?, ?, ?, 
+                    ?, ?, ?, ?, ?, ?)",
+                aw_params! {
+                    citizen.immigration,
+                    citizen.expiration,
+                    citizen.last_login,
+                    citizen.last_address,
+                    citizen.total_time,
+                    citizen.bot_limit,
+                    citizen.beta,
+                    citizen.enabled,
+                    citizen.trial,
+                    citizen.privacy,
+                    citizen.cav_enabled,
+                    citizen.cav_template,
+                    &citizen.name,
+                    &citizen.password,
+                    &citizen.email,
+                    &citizen.priv_pass,
+                    &citizen.comment,
+                    &citizen.url
+                },
+            );
+
+            match r {
+                DatabaseResult::Ok(_) => DatabaseResult::Ok(()),
+                DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
+            }
+        })
     }
 
     fn citizen_change(&self, citizen: &CitizenQuery) -> DatabaseResult<()> {
         let r = self.db.exec(
-            r"UPDATE awu_citizen SET Changed=NOT Changed,
+            r"UPDATE awu_citizen SET Changed=1-Changed,
                 Immigration=?, Expiration=?, LastLogin=?, 
                 LastAddress=?, TotalTime=?, BotLimit=?, 
                 Beta=?, Enabled=?, Trial=?, Privacy=?, 
@@ -319,130 +373,125 @@ impl CitizenDB for UniverseDatabase {
             DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
         }
     }
+
+    fn citizen_password_change_required(&self, citizen_id: u32) -> DatabaseResult<bool> {
+        let rows = match self.db.exec(
+            r"SELECT PasswordChange FROM awu_citizen WHERE ID=?;",
+            aw_params!(citizen_id),
+        ) {
+            DatabaseResult::Ok(rows) => rows,
+            DatabaseResult::DatabaseError => return DatabaseResult::DatabaseError,
+        };
+
+        let required = rows
+            .first()
+            .and_then(|row| row.fetch_int("PasswordChange"))
+            .unwrap_or(0);
+
+        DatabaseResult::Ok(required != 0)
+    }
+
+    fn citizen_set_password_change_required(
+        &self,
+        citizen_id: u32,
+        required: bool,
+    ) -> DatabaseResult<()> {
+        let r = self.db.exec(
+            r"UPDATE awu_citizen SET PasswordChange=? WHERE ID=?;",
+            aw_params!(required as u32, citizen_id),
+        );
+
+        match r {
+            DatabaseResult::Ok(_) => DatabaseResult::Ok(()),
+            DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
+        }
+    }
 }
 
-fn fetch_citizen(row: &Row) -> DatabaseResult<CitizenQuery> {
-    let id = match row.fetch_int("ID").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    let changed = match row.fetch_int("Changed").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    let name = match row.fetch_string("Name") {
-        Some(x) => x,
-        None => return DatabaseResult::DatabaseError,
-    };
-
-    let password = match row.fetch_string("Password") {
-        Some(x) => x,
-        None => return DatabaseResult::DatabaseError,
-    };
-
-    let email = match row.fetch_string("Email") {
-        Some(x) => x,
-        None => return DatabaseResult::DatabaseError,
-    };
-
-    let priv_pass = match row.fetch_string("PrivPass") {
-        Some(x) => x,
-        None => return DatabaseResult::DatabaseError,
-    };
-
-    let comment = match row.fetch_string("Comment") {
-        Some(x) => x,
-        None => return DatabaseResult::DatabaseError,
-    };
-
-    let url = match row.fetch_string("URL") {
-        Some(x) => x,
-        None => return DatabaseResult::DatabaseError,
-    };
-
-    let immigration = match row.fetch_int("Immigration").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    let expiration = match row.fetch_int("Expiration").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    let last_login = match row.fetch_int("LastLogin").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
+/// Generates a password for citizen #1, returning it along with its hash.
+fn generate_admin_password() -> Option<(String, String)> {
+    let admin_password = password::generate_password();
+    match password::hash_password(&admin_password) {
+        Ok(hash) => Some((admin_password, hash)),
+        Err(why) => {
+            log::error!("Failed to hash a password for citizen #1: {why}");
+            None
+        }
+    }
+}
 
-    // It is not unexpected for LastAddress to end up in the database as a negative number.
-    let last_address = match row.fetch_int("LastAddress").map(|x| x as u32) {
-        Some(x) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    let total_time = match row.fetch_int("TotalTime").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    let bot_limit = match row.fetch_int("BotLimit").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    let beta = match row.fetch_int("Beta").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    let cav_enabled = match row.fetch_int("CAVEnabled").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    let cav_template = match row.fetch_int("CAVTemplate").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    let enabled = match row.fetch_int("Enabled").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    let privacy = match row.fetch_int("Privacy").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    let trial = match row.fetch_int("Trial").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    DatabaseResult::Ok(CitizenQuery {
-        id,
-        changed,
-        name,
-        password,
-        email,
-        priv_pass,
-        comment,
-        url,
-        immigration,
-        expiration,
-        last_login,
-        last_address,
-        total_time,
-        bot_limit,
-        beta,
-        cav_enabled,
-        cav_template,
-        enabled,
-        privacy,
-        trial,
-    })
+/// Shows the generated password to whoever started the universe. It goes to stderr rather than
+/// the log so that it is shown once and not kept in log files.
+fn print_admin_password(name: &str, password: &str) {
+    eprintln!("Citizen #1 can log in as {name} / {password}");
+    eprintln!(
+        "This password must be changed from a browser before it can be used for anything else."
+    );
+}
+
+/// Adds the flag which keeps a citizen out of worlds until they choose a new password.
+pub fn add_password_change_column(tx: &mut Transaction) -> DatabaseResult<()> {
+    let r = tx.exec(
+        r"ALTER TABLE awu_citizen ADD PasswordChange tinyint(1) NOT NULL default '0';",
+        vec![],
+    );
+
+    match r {
+        DatabaseResult::Ok(_) => DatabaseResult::Ok(()),
+        DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::Config;
+
+    #[test]
+    fn administrator_must_change_the_generated_password() {
+        let mut config = Config::default();
+        config.sql.sqlite_config.path = ":memory:".to_string();
+        let db = UniverseDatabase::new(config.sql, &config.universe).unwrap();
+        let required = |db: &UniverseDatabase| match db.citizen_password_change_required(1) {
+            DatabaseResult::Ok(required) => required,
+            DatabaseResult::DatabaseError => panic!("Could not read the password change flag"),
+        };
+        assert!(required(&db));
+
+        // A password which has been changed is left alone
+        assert!(!db.citizen_set_password_change_required(1, false).is_err());
+        assert!(!db.init_administrator().is_err());
+        assert!(!required(&db));
+
+        // The default password of older universes is replaced
+        let DatabaseResult::Ok(Some(mut admin)) = db.citizen_by_number(1) else {
+            panic!("The universe has no Administrator");
+        };
+        admin.password = password::hash_password(DEFAULT_ADMIN_PASSWORD).unwrap();
+        assert!(!db.citizen_change(&admin).is_err());
+        assert!(!db.init_administrator().is_err());
+        assert!(required(&db));
+        let DatabaseResult::Ok(Some(admin)) = db.citizen_by_number(1) else {
+            panic!("The universe has no Administrator");
+        };
+        assert!(!password::verify_password(DEFAULT_ADMIN_PASSWORD, &admin.password).is_valid());
+    }
+
+    #[test]
+    fn privacy_settings_are_stored_unchanged() {
+        let mut config = Config::default();
+        config.sql.sqlite_config.path = ":memory:".to_string();
+        let db = UniverseDatabase::new(config.sql, &config.universe).unwrap();
+        let DatabaseResult::Ok(Some(mut admin)) = db.citizen_by_number(1) else {
+            panic!("The universe has no Administrator");
+        };
+
+        let privacy = 0x8000_0001;
+        admin.privacy = privacy;
+        assert!(!db.citizen_change(&admin).is_err());
+        let DatabaseResult::Ok(Some(admin)) = db.citizen_by_number(1) else {
+            panic!("The universe has no Administrator");
+        };
+        assert_eq!(admin.privacy, privacy);
+    }
 }
@@ -1,4 +1,4 @@
-use aw_db::{aw_params, DatabaseResult, Row};
+use aw_db::{aw_params, from_row, DatabaseResult, Transaction};
 use bitflags::bitflags;
 
 use super::UniverseDatabase;
@@ -234,9 +234,21 @@ pub struct ContactQuery {
     pub options: ContactOptions,
 }
 
+from_row!(ContactQuery {
+    citizen: "Citizen",
+    contact: "Contact",
+    options: "Options" => ContactOptions::from_bits_truncate,
+});
+
 pub trait ContactDB {
     fn init_contact(&self) -> DatabaseResult<()>;
     fn contact_set(&self, citizen_id: u32, contact_id: u32, options: u32) -> DatabaseResult<()>;
+    fn contact_set_mutual(
+        &self,
+        citizen_id: u32,
+        contact_id: u32,
+        options: u32,
+    ) -> DatabaseResult<()>;
     fn contact_get(&self, citizen_id: u32, contact_id: u32)
         -> DatabaseResult<Option<ContactQuery>>;
     fn contact_get_all(&self, citizen_id: u32) -> DatabaseResult<Vec<ContactQuery>>;
@@ -283,47 +295,24 @@ impl ContactDB for UniverseDatabase {
     }
 
     fn contact_set(&self, citizen_id: u32, contact_id: u32, options: u32) -> DatabaseResult<()> {
-        // Check if contact pair is already in the database
-        let r = self.db.exec(
-            r"SELECT * FROM awu_contact WHERE Citizen=? AND Contact=?;",
-            aw_params! {
-                citizen_id,
-                contact_id
-            },
-        );
-
-        let rows = match r {
-            DatabaseResult::Ok(rows) => rows,
-            DatabaseResult::DatabaseError => return DatabaseResult::DatabaseError,
-        };
+        self.db
+            .transaction(|tx| contact_set_in(tx, citizen_id, contact_id, options))
+    }
 
-        let r = if rows.is_empty() {
-            // Add the contact pair if it is not already existent
-            self.db.exec(
-                r"INSERT INTO awu_contact (Citizen,Contact,Options) 
-                VALUES(?, ?, ?);",
-                aw_params! {
-                    citizen_id,
-                    contact_id,
-                    options
-                },
-            )
-        } else {
-            // Try to update the contact pair if it is already present
-            self.db.exec(
-                r"UPDATE awu_contact SET Options=? WHERE Citizen=? AND Contact=?;",
-                aw_params! {
-                    options,
-                    citizen_id,
-                    contact_id
-                },
-            )
-        };
+    fn contact_set_mutual(
+        &self,
+        citizen_id: u32,
+        contact_id: u32,
+        options: u32,
+    ) -> DatabaseResult<()> {
+        // Either both citizens become contacts of each other, or neither does
+        self.db.transaction(|tx| {
+            if contact_set_in(tx, citizen_id, contact_id, options).is_err() {
+                return DatabaseResult::DatabaseError;
+            }
 
-        match r {
-            DatabaseResult::Ok(_) => DatabaseResult::Ok(()),
-            DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
-        }
+            contact_set_in(tx, contact_id, citizen_id, options)
+        })
     }
 
     fn contact_get(
@@ -349,7 +338,7 @@ impl ContactDB for UniverseDatabase {
             return DatabaseResult::Ok(None);
         };
 
-        match fetch_contact(contact) {
+        match contact.decode() {
             DatabaseResult::Ok(contact) => DatabaseResult::Ok(Some(contact)),
             DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
         }
@@ -370,7 +359,7 @@ impl ContactDB for UniverseDatabase {
 
         let mut result = Vec::<ContactQuery>::new();
         for row in rows {
-            match fetch_contact(&row) {
+            match row.decode() {
                 DatabaseResult::Ok(contact) => result.push(contact),
                 DatabaseResult::DatabaseError => return DatabaseResult::DatabaseError,
             }
@@ -571,25 +560,51 @@ impl ContactDB for UniverseDatabase {
     }
 }
 
-fn fetch_contact(row: &Row) -> DatabaseResult<ContactQuery> {
-    let citizen = match row.fetch_int("Citizen").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    let contact = match row.fetch_int("Contact").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
+fn contact_set_in(
+    tx: &mut Transaction,
+    citizen_id: u32,
+    contact_id: u32,
+    options: u32,
+) -> DatabaseResult<()> {
+    // Check if contact pair is already in the database
+    let r = tx.exec(
+        r"SELECT * FROM awu_contact WHERE Citizen=? AND Contact=?;",
+        aw_params! {
+            citizen_id,
+            contact_id
+        },
+    );
+
+    let rows = match r {
+        DatabaseResult::Ok(rows) => rows,
+        DatabaseResult::DatabaseError => return DatabaseResult::DatabaseError,
     };
 
-    let options = match row.fetch_int("Options").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
+    let r = if rows.is_empty() {
+        // Add the contact pair if it is not already existent
+        tx.exec(
+            r"INSERT INTO awu_contact (Citizen,Contact,Options) 
+            VALUES(?, ?, ?);",
+            aw_params! {
+                citizen_id,
+                contact_id,
+                options
+            },
+        )
+    } else {
+        // Try to update the contact pair if it is already present
+        tx.exec(
+            r"UPDATE awu_contact SET Options=? WHERE Citizen=? AND Contact=?;",
+            aw_params! {
+                options,
+                citizen_id,
+                contact_id
+            },
+        )
     };
 
-    DatabaseResult::Ok(ContactQuery {
-        citizen,
-        contact,
-        options: ContactOptions::from_bits_truncate(options),
-    })
+    match r {
+        DatabaseResult::Ok(_) => DatabaseResult::Ok(()),
+        DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
+    }
 }
@@ -0,0 +1,481 @@
+//! Copying a universe from one database to another, e.g. from the internal SQLite database to
+//! an external server.
+use aw_db::{DatabaseConfig, DatabaseResult, Row, Transaction};
+
+use super::{schema::latest_schema_version, SchemaDB, UniverseDatabase, UniverseDatabaseError};
+
+#[derive(Clone, Copy)]
+enum Column {
+    Int(&'static str),
+    Float(&'static str),
+    String(&'static str),
+}
+
+impl Column {
+    fn name(self) -> &'static str {
+        match self {
+            Column::Int(name) | Column::Float(name) | Column::String(name) => name,
+        }
+    }
+
+    /// Reads the column as a statement parameter.
+    fn fetch(self, row: &Row) -> Option<String> {
+        match self {
+            Column::Int(name) => row.fetch_int(name).map(|x| x.to_string()),
+            Column::Float(name) => row.fetch_float(name).map(|x| x.to_string()),
+            // Nullable text columns are copied as empty text
+            Column::String(name) => Some(row.fetch_string(name).unwrap_or_default()),
+        }
+    }
+}
+
+struct Table {
+    name: &'static str,
+    columns: &'static [Column],
+    /// Column which is generated when a row is inserted without it.
+    auto_increment: Option<&'static str>,
+}
+
+/// Every table belonging to the universe except for the schema version, which the destination
+/// manages itself.
+const TABLES: &[Table] = &[
+    Table {
+        name: "awu_attrib",
+        columns: &[
+            Column::Int("ID"),
+            Column::Int("Changed"),
+            Column::String("Value"),
+        ],
+        auto_increment: None,
+    },
+    Table {
+        name: "awu_citizen",
+        columns: &[
+            Column::Int("ID"),
+            Column::Int("Changed"),
+            Column::String("Name"),
+            Column::String("Password"),
+            Column::String("Email"),
+            Column::String("PrivPass"),
+            Column::String("Comment"),
+            Column::String("URL"),
+            Column::Int("Immigration"),
+            Column::Int("Expiration"),
+            Column::Int("LastLogin"),
+            Column::Int("LastAddress"),
+            Column::Int("TotalTime"),
+            Column::Int("BotLimit"),
+            Column::Int("Beta"),
+            Column::Int("CAVEnabled"),
+            Column::Int("CAVTemplate"),
+            Column::Int("Enabled"),
+            Column::Int("Privacy"),
+            Column::Int("Trial"),
+            Column::Int("PasswordChange"),
+        ],
+        auto_increment: Some("ID"),
+    },
+    Table {
+        name: "awu_contact",
+        columns: &[
+            Column::Int("Citizen"),
+            Column::Int("Contact"),
+            Column::Int("Options"),
+            Column::Int("Changed"),
+        ],
+        auto_increment: None,
+    },
+    Table {
+        name: "awu_license",
+        columns: &[
+            Column::Int("ID"),
+            Column::String("Name"),
+            Column::String("Password"),
+            Column::String("Email"),
+            Column::String("Comment"),
+            Column::Int("Creation"),
+            Column::Int("Expiration"),
+            Column::Int("LastStart"),
+            Column::Int("LastAddress"),
+            Column::Int("Users"),
+            Column::Int("WorldSize"),
+            Column::Int("Hidden"),
+            Column::Int("Changed"),
+            Column::Int("Tourists"),
+            Column::Int("Voip"),
+            Column::Int("Plugins"),
+        ],
+        auto_increment: Some("ID"),
+    },
+    Table {
+        name: "awu_telegram",
+        columns: &[
+            Column::Int("ID"),
+            Column::Int("Citizen"),
+            Column::Int("From"),
+            Column::Int("Timestamp"),
+            Column::String("Message"),
+            Column::Int("Delivered"),
+        ],
+        auto_increment: Some("ID"),
+    },
+    Table {
+        name: "awu_cav",
+        columns: &[
+            Column::Int("Citizen"),
+            Column::Int("Template"),
+            Column::Int("Changed"),
+            Column::Float("Keyframe1Scale"),
+            Column::Float("Keyframe2Scale"),
+            Column::Float("Height"),
+            Column::Int("SkinColor"),
+            Column::Int("HairColor"),
+        ],
+        auto_increment: None,
+    },
+    Table {
+        name: "awu_cav_template",
+        columns: &[
+            Column::Int("ID"),
+            Column::Int("Changed"),
+            Column::Int("Type"),
+            Column::Int("Rating"),
+            Column::String("Name"),
+            Column::String("Model"),
+        ],
+        auto_increment: Some("ID"),
+    },
+    Table {
+        name: "awu_eject",
+        columns: &[
+            Column::Int("ID"),
+            Column::Int("Expiration"),
+            Column::Int("Creation"),
+            Column::Int("Address"),
+            Column::String("Comment"),
+            Column::Int("Changed"),
+        ],
+        auto_increment: Some("ID"),
+    },
+    Table {
+        name: "awu_ejection",
+        columns: &[
+            Column::Int("ID"),
+            Column::Int("Kind"),
+            Column::String("RangeStart"),
+            Column::String("RangeEnd"),
+            Column::Int("Prefix"),
+            Column::Int("Number"),
+            Column::String("Reason"),
+            Column::Int("Admin"),
+            Column::Int("Creation"),
+            Column::Int("Expiration"),
+        ],
+        auto_increment: Some("ID"),
+    },
+];
+
+#[derive(thiserror::Error, Debug)]
+pub enum ConvertError {
+    #[error("Couldn't open the source database: {0}")]
+    Source(UniverseDatabaseError),
+    #[error("Couldn't open the destination database: {0}")]
+    Destination(UniverseDatabaseError),
+    #[error("The source database schema is version {found}, but version {supported} is required. Start the universe on the source database once to upgrade it")]
+    SourceOutdated { found: u32, supported: u32 },
+    #[error("Couldn't read {0} from the source database")]
+    Read(&'static str),
+    #[error("Couldn't write {0} to the destination database")]
+    Write(&'static str),
+    #[error("Couldn't commit the copy to the destination database")]
+    Commit,
+    #[error("{table} has {source_rows} rows in the source database but {destination_rows} in the destination")]
+    CountMismatch {
+        table: &'static str,
+        source_rows: usize,
+        destination_rows: usize,
+    },
+}
+
+/// Replaces every universe table in the destination database with the contents of the source
+/// database, keeping all IDs. Running it again gives the same result.
+///
+/// Every table is copied in one transaction, so a failed run leaves the destination as it was.
+/// The exception is the ID sequences of Postgres, which are not rolled back and only ever
+/// advance.
+pub fn convert_database(
+    source_config: DatabaseConfig,
+    destination_config: DatabaseConfig,
+) -> Result<(), ConvertError> {
+    // The source is only read from, so it must already be up to date
+    let source =
+        UniverseDatabase::open(source_config).map_err(|why| ConvertError::Source(why.into()))?;
+    let found = source
+        .schema_check()
+        .map_err(|why| ConvertError::Source(why.into()))?;
+    let supported = latest_schema_version();
+    if found != supported {
+        return Err(ConvertError::SourceOutdated { found, supported });
+    }
+
+    // Opening the destination creates any tables it is missing, which are left empty for the copy
+    let destination =
+        UniverseDatabase::new_empty(destination_config).map_err(ConvertError::Destination)?;
+
+    let mut failure = None;
+    let r = destination.db.transaction(|tx| {
+        for table in TABLES {
+            if let Err(why) = copy_table(&source, tx, table) {
+                failure = Some(why);
+                return DatabaseResult::DatabaseError;
+            }
+        }
+        DatabaseResult::Ok(())
+    });
+
+    match (r, failure) {
+        (_, Some(why)) => Err(why),
+        (DatabaseResult::Ok(()), None) => Ok(()),
+        (DatabaseResult::DatabaseError, None) => Err(ConvertError::Commit),
+    }
+}
+
+fn copy_table(
+    source: &UniverseDatabase,
+    tx: &mut Transaction,
+    table: &'static Table,
+) -> Result<(), ConvertError> {
+    let rows = match source.table_rows(table) {
+        DatabaseResult::Ok(rows) => rows,
+        DatabaseResult::DatabaseError => return Err(ConvertError::Read(table.name)),
+    };
+
+    log::info!("Copying {} rows of {}", rows.len(), table.name);
+    if replace_table(tx, table, &rows).is_err() {
+        return Err(ConvertError::Write(table.name));
+    }
+
+    let destination_rows = match tx.exec(format!("SELECT * FROM {};", table.name), vec![]) {
+        DatabaseResult::Ok(rows) => rows.len(),
+        DatabaseResult::DatabaseError => return Err(ConvertError::Write(table.name)),
+    };
+    if destination_rows != rows.len() {
+        return Err(ConvertError::CountMismatch {
+            table: table.name,
+            source_rows: rows.len(),
+            destination_rows,
+        });
+    }
+
+    Ok(())
+}
+
+impl UniverseDatabase {
+    fn table_rows(&self, table: &Table) -> DatabaseResult<Vec<Row>> {
+        self.db
+            .exec(format!("SELECT * FROM {};", table.name), vec![])
+    }
+}
+
+fn replace_table(tx: &mut Transaction, table: &Table, rows: &[Row]) -> DatabaseResult<()> {
+    if tx
+        .exec(format!("DELETE FROM {};", table.name), vec![])
+        .is_err()
+    {
+        return DatabaseResult::DatabaseError;
+    }
+
+    let column_names = table
+        .columns
+        .iter()
+        .map(|column| format!("`{}`", column.name()))
+        .collect::<Vec<String>>()
+        .join(", ");
+    let placeholders = vec!["?"; table.columns.len()].join(", ");
+    let statement = format!(
+        "INSERT INTO {} ({column_names}) VALUES({placeholders});",
+        table.name
+    );
+
+    for row in rows {
+        let mut parameters = Vec::<String>::with_capacity(table.columns.len());
+        for column in table.columns {
+            let Some(value) = column.fetch(row) else {
+                log::error!(
+                    "Couldn't read {}.{} from {row:?}",
+                    table.name,
+                    column.name()
+                );
+                return DatabaseResult::DatabaseError;
+            };
+            parameters.push(value);
+        }
+
+        if tx.exec(&statement, parameters).is_err() {
+            return DatabaseResult::DatabaseError;
+        }
+    }
+
+    if let Some(column) = table.auto_increment {
+        return tx.auto_increment_reset(table.name, column);
+    }
+
+    DatabaseResult::Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        configuration::Config,
+        database::{
+            cav::{CavQuery, CavTemplateQuery},
+            citizen::CitizenQuery,
+            eject::EjectionQuery,
+            license::LicenseQuery,
+            CavDB, CitizenDB, ContactDB, EjectDB, LicenseDB, TelegramDB,
+        },
+        ejection::EjectionTarget,
+    };
+    use aw_db::aw_params;
+
+    /// Every value of every row in the table, in the order the rows were inserted.
+    fn table_contents(db: &UniverseDatabase, table: &Table) -> Vec<Vec<Option<String>>> {
+        let DatabaseResult::Ok(rows) = db.table_rows(table) else {
+            panic!("Could not read {}", table.name);
+        };
+        rows.iter()
+            .map(|row| table.columns.iter().map(|c| c.fetch(row)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn sqlite_databases_are_copied() {
+        let path = |name: &str| {
+            std::env::temp_dir()
+                .join(format!("universe_convert_{name}_{}.db", std::process::id()))
+                .to_string_lossy()
+                .to_string()
+        };
+        let (source_path, destination_path) = (path("source"), path("destination"));
+        std::fs::remove_file(&source_path).ok();
+        std::fs::remove_file(&destination_path).ok();
+
+        let database_config = |path: &str| {
+            let mut config = Config::default().sql;
+            config.sqlite_config.path = path.to_string();
+            config
+        };
+
+        let source =
+            UniverseDatabase::new(database_config(&source_path), &Config::default().universe)
+                .unwrap();
+        let DatabaseResult::Ok(Some(admin)) = source.citizen_by_number(1) else {
+            panic!("The universe has no Administrator");
+        };
+        let citizen = CitizenQuery {
+            name: "Alice".to_string(),
+            ..admin
+        };
+        assert!(!source.citizen_add_next(citizen).is_err());
+        assert!(!source.telegram_add(2, 1, 1000, "Welcome").is_err());
+        let template = CavTemplateQuery {
+            id: 0,
+            template_type: 1,
+            rating: 0,
+            name: "Template".to_string(),
+            model: "model".to_string(),
+        };
+        let DatabaseResult::Ok(template) = source.cav_template_set(&template) else {
+            panic!("Could not add a template");
+        };
+        let cav = CavQuery {
+            citizen: 2,
+            template,
+            keyframe1_scale: 0.25,
+            keyframe2_scale: 1.5,
+            height: 1.75,
+            skin_color: 0xC08060,
+            hair_color: 0x302010,
+        };
+        assert!(!source.cav_set(&cav).is_err());
+        assert!(!source.contact_set(2, 1, 5).is_err());
+        let license = LicenseQuery {
+            id: 0,
+            name: "Test".to_string(),
+            password: "worldpass".to_string(),
+            email: "test@example.com".to_string(),
+            comment: "Comment".to_string(),
+            creation: 0,
+            expiration: 2000,
+            last_start: 0,
+            last_address: 0,
+            users: 10,
+            world_size: 100,
+            hidden: 0,
+            changed: 0,
+            tourists: 1,
+            voip: 0,
+            plugins: 0,
+        };
+        assert!(!source.license_add(&license).is_err());
+        let ejection = EjectionQuery::new(EjectionTarget::Serial(0x1234), "Reason", 1, 1000, 3000);
+        assert!(!source.ejection_set(&ejection).is_err());
+        // Ejections have moved out of awu_eject, but it is still copied
+        assert!(!source
+            .db
+            .exec(
+                "INSERT INTO awu_eject (Expiration, Creation, Address, Comment) VALUES(?, ?, ?, ?);",
+                aw_params!(3000, 1000, 0x7F000001u32, "Legacy")
+            )
+            .is_err());
+
+        // The destination is not given a citizen #1 of its own
+        let destination = UniverseDatabase::new_empty(database_config(&destination_path)).unwrap();
+        assert!(matches!(
+            destination.citizen_by_number(1),
+            DatabaseResult::Ok(None)
+        ));
+
+        let r = convert_database(
+            database_config(&source_path),
+            database_config(&destination_path),
+        );
+        let contents = TABLES
+            .iter()
+            .map(|table| {
+                (
+                    table_contents(&source, table),
+                    table_contents(&destination, table),
+                )
+            })
+            .collect::<Vec<_>>();
+        std::fs::remove_file(&source_path).ok();
+        std::fs::remove_file(&destination_path).ok();
+
+        r.unwrap();
+        for (table, (source_rows, destination_rows)) in TABLES.iter().zip(contents) {
+            assert_eq!(source_rows, destination_rows, "{} differs", table.name);
+        }
+        // Every table had something to copy
+        for table in TABLES {
+            assert!(
+                !table_contents(&source, table).is_empty(),
+                "{} is empty",
+                table.name
+            );
+        }
+        let citizens = TABLES.iter().position(|t| t.name == "awu_citizen").unwrap();
+        assert_eq!(table_contents(&source, &TABLES[citizens]).len(), 2);
+    }
+
+    #[test]
+    fn tables_are_unique() {
+        for (i, table) in TABLES.iter().enumerate() {
+            assert!(TABLES[i + 1..].iter().all(|t| t.name != table.name));
+            assert!(table
+                .auto_increment
+                .map_or(true, |a| table.columns.iter().any(|c| c.name() == a)));
+        }
+    }
+}
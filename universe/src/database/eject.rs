@@ -1,30 +1,138 @@
-use aw_db::{aw_params, DatabaseResult, Row};
+use std::net::{IpAddr, Ipv4Addr};
+
+use aw_db::{aw_params, from_row, DatabaseResult, FromRow, Row, Transaction};
 
 use super::UniverseDatabase;
+use crate::ejection::{legacy_targets, mapped_address, unmapped_address, EjectionTarget};
+
+const KIND_ADDRESS: u32 = 0;
+const KIND_NETWORK: u32 = 1;
+const KIND_SERIAL: u32 = 2;
+const KIND_CITIZEN: u32 = 3;
 
 pub trait EjectDB {
+    /// Creates awu_eject as it was in schema version 1. Ejections have since moved to
+    /// awu_ejection, and awu_eject is only read to copy them over.
     fn init_eject(&self) -> DatabaseResult<()>;
-    fn ejection_set(
+    /// Add an ejection, or replace the ejection of the same target. Returns its ID.
+    fn ejection_set(&self, ejection: &EjectionQuery) -> DatabaseResult<u32>;
+    fn ejection_by_id(&self, id: u32) -> DatabaseResult<Option<EjectionQuery>>;
+    fn ejection_by_target(&self, target: &EjectionTarget) -> DatabaseResult<Option<EjectionQuery>>;
+    fn ejection_list(&self) -> DatabaseResult<Vec<EjectionQuery>>;
+    /// Ejections in effect at `now` which cover an address, serial number or citizen.
+    fn ejections_matching(
         &self,
-        address: u32,
-        expiration: u32,
-        creation: u32,
-        comment: &str,
-    ) -> DatabaseResult<()>;
-    fn ejection_lookup(&self, address: u32) -> DatabaseResult<Option<EjectionQuery>>;
-    fn ejection_next(&self, address: u32) -> DatabaseResult<Option<EjectionQuery>>;
-    fn ejection_prev(&self, address: u32) -> DatabaseResult<Option<EjectionQuery>>;
-    fn ejection_delete(&self, address: u32) -> DatabaseResult<()>;
+        address: IpAddr,
+        serial: Option<u32>,
+        citizen: Option<u32>,
+        now: u32,
+    ) -> DatabaseResult<Vec<EjectionQuery>>;
+    /// Ejection of the next IPv4 address after `address`, as clients page through them.
+    fn ejection_next_ipv4(&self, address: Ipv4Addr) -> DatabaseResult<Option<EjectionQuery>>;
+    /// Ejection of the previous IPv4 address before `address`, as clients page through them.
+    fn ejection_prev_ipv4(&self, address: Ipv4Addr) -> DatabaseResult<Option<EjectionQuery>>;
+    fn ejection_delete(&self, id: u32) -> DatabaseResult<()>;
     fn ejection_clean(&self, timestamp: u32) -> DatabaseResult<()>;
 }
 
+/// An ejection as it is stored. Addresses are kept as 32 hexadecimal digits of an IPv6 address,
+/// with IPv4 mapped into it, so that they sort and compare like the addresses themselves.
+#[derive(Debug, Clone, PartialEq)]
 pub struct EjectionQuery {
-    pub address: u32,
-    pub expiration: u32,
+    pub id: u32,
+    pub kind: u32,
+    /// First address covered, empty for serial numbers and citizens
+    pub range_start: String,
+    /// Last address covered, empty for serial numbers and citizens
+    pub range_end: String,
+    /// Length of a network's prefix, in bits of its own address family
+    pub prefix: u32,
+    /// Serial number or citizen number
+    pub number: u32,
+    pub reason: String,
+    /// Citizen number of the admin who added the ejection, or 0 for the console and admin API
+    pub admin: u32,
     pub creation: u32,
-    pub comment: String,
+    pub expiration: u32,
+}
+
+from_row!(EjectionQuery {
+    id: "ID",
+    kind: "Kind",
+    range_start: "RangeStart",
+    range_end: "RangeEnd",
+    prefix: "Prefix",
+    number: "Number",
+    reason: "Reason",
+    admin: "Admin",
+    creation: "Creation",
+    expiration: "Expiration",
+});
+
+impl EjectionQuery {
+    pub fn new(
+        target: EjectionTarget,
+        reason: &str,
+        admin: u32,
+        creation: u32,
+        expiration: u32,
+    ) -> Self {
+        let (kind, prefix, number) = match target {
+            EjectionTarget::Address(_) => (KIND_ADDRESS, 0, 0),
+            EjectionTarget::Network(_, prefix) => (KIND_NETWORK, u32::from(prefix), 0),
+            EjectionTarget::Serial(serial) => (KIND_SERIAL, 0, serial),
+            EjectionTarget::Citizen(citizen) => (KIND_CITIZEN, 0, citizen),
+        };
+        let (range_start, range_end) = match target.address_range() {
+            Some((first, last)) => (address_key(first), address_key(last)),
+            None => (String::new(), String::new()),
+        };
+
+        Self {
+            id: 0,
+            kind,
+            range_start,
+            range_end,
+            prefix,
+            number,
+            reason: reason.to_string(),
+            admin,
+            creation,
+            expiration,
+        }
+    }
+
+    /// What the ejection covers, or `None` if the row is not one this universe understands.
+    pub fn target(&self) -> Option<EjectionTarget> {
+        let first = || u128::from_str_radix(&self.range_start, 16).ok();
+        match self.kind {
+            KIND_ADDRESS => Some(EjectionTarget::Address(unmapped_address(first()?))),
+            KIND_NETWORK => {
+                EjectionTarget::network(unmapped_address(first()?), u8::try_from(self.prefix).ok()?)
+            }
+            KIND_SERIAL => Some(EjectionTarget::Serial(self.number)),
+            KIND_CITIZEN => Some(EjectionTarget::Citizen(self.number)),
+            _ => None,
+        }
+    }
 }
 
+/// An ejection as it was stored in awu_eject, with serial numbers written into the comment as
+/// `serial=0x...`.
+struct LegacyEjectionQuery {
+    address: u32,
+    expiration: u32,
+    creation: u32,
+    comment: String,
+}
+
+from_row!(LegacyEjectionQuery {
+    address: "Address",
+    expiration: "Expiration",
+    creation: "Creation",
+    comment: "Comment",
+});
+
 impl EjectDB for UniverseDatabase {
     fn init_eject(&self) -> DatabaseResult<()> {
         let unsigned = self.db.unsigned_str();
@@ -49,135 +157,149 @@ impl EjectDB for UniverseDatabase {
         }
     }
 
-    fn ejection_set(
-        &self,
-        address: u32,
-        expiration: u32,
-        creation: u32,
-        comment: &str,
-    ) -> DatabaseResult<()> {
-        // Check if ejection is already in the database
+    fn ejection_set(&self, ejection: &EjectionQuery) -> DatabaseResult<u32> {
+        self.db.transaction(|tx| insert_ejection(tx, ejection))
+    }
+
+    fn ejection_by_id(&self, id: u32) -> DatabaseResult<Option<EjectionQuery>> {
         let r = self.db.exec(
-            r"SELECT * FROM awu_eject WHERE Address=?;",
+            r"SELECT * FROM awu_ejection WHERE ID=?;",
             aw_params! {
-                address
+                id
             },
         );
 
-        let rows = match r {
-            DatabaseResult::Ok(rows) => rows,
-            DatabaseResult::DatabaseError => return DatabaseResult::DatabaseError,
-        };
-
-        let r = if rows.is_empty() {
-            // Add the ejection if it is not already existent
-            self.db.exec(
-                r"INSERT INTO awu_eject (Expiration, Creation, Address, Comment)  
-                VALUES(?, ?, ?, ?);",
-                aw_params! {
-                    expiration,
-                    creation,
-                    address,
-                    comment
-                },
-            )
-        } else {
-            // Try to update the ejection if it is already present
-            self.db.exec(
-                r"UPDATE awu_eject SET Expiration=?, Creation=?, Comment=? 
-                WHERE Address=?;",
-                aw_params! {
-                    expiration,
-                    creation,
-                    comment,
-                    address
-                },
-            )
-        };
-
         match r {
-            DatabaseResult::Ok(_) => DatabaseResult::Ok(()),
+            DatabaseResult::Ok(rows) => decode_first(&rows),
             DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
         }
     }
 
-    fn ejection_lookup(&self, address: u32) -> DatabaseResult<Option<EjectionQuery>> {
+    fn ejection_by_target(&self, target: &EjectionTarget) -> DatabaseResult<Option<EjectionQuery>> {
+        let ejection = EjectionQuery::new(*target, "", 0, 0, 0);
         let r = self.db.exec(
-            r"SELECT * FROM awu_eject WHERE Address=?;",
+            r"SELECT * FROM awu_ejection
+            WHERE Kind=? AND RangeStart=? AND RangeEnd=? AND Number=?;",
             aw_params! {
-                address
+                ejection.kind,
+                ejection.range_start,
+                ejection.range_end,
+                ejection.number
             },
         );
 
-        let rows = match r {
-            DatabaseResult::Ok(rows) => rows,
-            DatabaseResult::DatabaseError => return DatabaseResult::DatabaseError,
-        };
+        match r {
+            DatabaseResult::Ok(rows) => decode_first(&rows),
+            DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
+        }
+    }
 
-        let row = match rows.first() {
-            Some(row) => row,
-            None => return DatabaseResult::Ok(None),
-        };
+    fn ejection_list(&self) -> DatabaseResult<Vec<EjectionQuery>> {
+        let r = self.db.exec(
+            r"SELECT * FROM awu_ejection ORDER BY Kind, RangeStart, Number;",
+            vec![],
+        );
 
-        match fetch_ejection(row) {
-            DatabaseResult::Ok(e) => DatabaseResult::Ok(Some(e)),
+        match r {
+            DatabaseResult::Ok(rows) => decode_all(&rows),
             DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
         }
     }
 
-    fn ejection_next(&self, address: u32) -> DatabaseResult<Option<EjectionQuery>> {
+    fn ejections_matching(
+        &self,
+        address: IpAddr,
+        serial: Option<u32>,
+        citizen: Option<u32>,
+        now: u32,
+    ) -> DatabaseResult<Vec<EjectionQuery>> {
+        let address = address_key(mapped_address(address));
         let r = self.db.exec(
-            r"SELECT * FROM awu_eject WHERE Address>? ORDER BY Address LIMIT 1;",
+            r"SELECT * FROM awu_ejection
+            WHERE RangeStart<=? AND RangeEnd>=? AND Kind IN (?, ?) AND Expiration>=?;",
             aw_params! {
-                address
+                address,
+                address,
+                KIND_ADDRESS,
+                KIND_NETWORK,
+                now
             },
         );
 
-        let rows = match r {
-            DatabaseResult::Ok(rows) => rows,
+        let mut ejections = match r {
+            DatabaseResult::Ok(rows) => match decode_all(&rows) {
+                DatabaseResult::Ok(ejections) => ejections,
+                DatabaseResult::DatabaseError => return DatabaseResult::DatabaseError,
+            },
             DatabaseResult::DatabaseError => return DatabaseResult::DatabaseError,
         };
 
-        let row = match rows.first() {
-            Some(row) => row,
-            None => return DatabaseResult::Ok(None),
-        };
+        let numbers = [(KIND_SERIAL, serial), (KIND_CITIZEN, citizen)];
+        for (kind, number) in numbers {
+            let Some(number) = number else {
+                continue;
+            };
 
-        match fetch_ejection(row) {
-            DatabaseResult::Ok(e) => DatabaseResult::Ok(Some(e)),
-            DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
+            let r = self.db.exec(
+                r"SELECT * FROM awu_ejection WHERE Kind=? AND Number=? AND Expiration>=?;",
+                aw_params! {
+                    kind,
+                    number,
+                    now
+                },
+            );
+
+            match r {
+                DatabaseResult::Ok(rows) => match decode_all(&rows) {
+                    DatabaseResult::Ok(found) => ejections.extend(found),
+                    DatabaseResult::DatabaseError => return DatabaseResult::DatabaseError,
+                },
+                DatabaseResult::DatabaseError => return DatabaseResult::DatabaseError,
+            }
         }
+
+        DatabaseResult::Ok(ejections)
     }
 
-    fn ejection_prev(&self, address: u32) -> DatabaseResult<Option<EjectionQuery>> {
+    fn ejection_next_ipv4(&self, address: Ipv4Addr) -> DatabaseResult<Option<EjectionQuery>> {
         let r = self.db.exec(
-            r"SELECT * FROM awu_eject WHERE Address<? ORDER BY Address DESC LIMIT 1;",
+            r"SELECT * FROM awu_ejection WHERE Kind=? AND RangeStart>? AND RangeStart<=?
+            ORDER BY RangeStart LIMIT 1;",
             aw_params! {
-                address
+                KIND_ADDRESS,
+                ipv4_key(address),
+                ipv4_key(Ipv4Addr::BROADCAST)
             },
         );
 
-        let rows = match r {
-            DatabaseResult::Ok(rows) => rows,
-            DatabaseResult::DatabaseError => return DatabaseResult::DatabaseError,
-        };
+        match r {
+            DatabaseResult::Ok(rows) => decode_first(&rows),
+            DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
+        }
+    }
 
-        let row = match rows.first() {
-            Some(row) => row,
-            None => return DatabaseResult::Ok(None),
-        };
+    fn ejection_prev_ipv4(&self, address: Ipv4Addr) -> DatabaseResult<Option<EjectionQuery>> {
+        let r = self.db.exec(
+            r"SELECT * FROM awu_ejection WHERE Kind=? AND RangeStart<? AND RangeStart>=?
+            ORDER BY RangeStart DESC LIMIT 1;",
+            aw_params! {
+                KIND_ADDRESS,
+                ipv4_key(address),
+                ipv4_key(Ipv4Addr::UNSPECIFIED)
+            },
+        );
 
-        match fetch_ejection(row) {
-            DatabaseResult::Ok(e) => DatabaseResult::Ok(Some(e)),
+        match r {
+            DatabaseResult::Ok(rows) => decode_first(&rows),
             DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
         }
     }
 
-    fn ejection_delete(&self, address: u32) -> DatabaseResult<()> {
+    fn ejection_delete(&self, id: u32) -> DatabaseResult<()> {
         let r = self.db.exec(
-            r"DELETE FROM awu_eject WHERE Address=?;",
+            r"DELETE FROM awu_ejection WHERE ID=?;",
             aw_params! {
-                address
+                id
             },
         );
 
@@ -189,7 +311,7 @@ impl EjectDB for UniverseDatabase {
 
     fn ejection_clean(&self, timestamp: u32) -> DatabaseResult<()> {
         let r = self.db.exec(
-            r"DELETE FROM awu_eject WHERE Expiration>0 AND Expiration<?;",
+            r"DELETE FROM awu_ejection WHERE Expiration>0 AND Expiration<?;",
             aw_params! {
                 timestamp
             },
@@ -202,31 +324,211 @@ impl EjectDB for UniverseDatabase {
     }
 }
 
-fn fetch_ejection(row: &Row) -> DatabaseResult<EjectionQuery> {
-    let address = match row.fetch_int("Address").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
+/// Schema migration creating awu_ejection, indexed both by address range and by number so
+/// that checking a client does not read every ejection.
+pub(super) fn create_ejection_table(tx: &mut Transaction) -> DatabaseResult<()> {
+    let unsigned_int = tx.unsigned_int_str();
+    let auto_increment_not_null = tx.auto_increment_not_null();
+    let statements = [
+        format!(
+            r"CREATE TABLE awu_ejection (
+            ID INTEGER PRIMARY KEY {auto_increment_not_null},
+            Kind INTEGER NOT NULL default '0',
+            RangeStart varchar(32) NOT NULL default '',
+            RangeEnd varchar(32) NOT NULL default '',
+            Prefix INTEGER NOT NULL default '0',
+            Number {unsigned_int} NOT NULL default '0',
+            Reason varchar(255) NOT NULL default '',
+            Admin {unsigned_int} NOT NULL default '0',
+            Creation INTEGER NOT NULL default '0',
+            Expiration INTEGER NOT NULL default '0'
+        );"
+        ),
+        r"CREATE INDEX awu_ejection_range ON awu_ejection (RangeStart, RangeEnd);".to_string(),
+        r"CREATE INDEX awu_ejection_number ON awu_ejection (Kind, Number);".to_string(),
+    ];
+
+    for statement in statements {
+        if tx.exec(statement, vec![]).is_err() {
+            return DatabaseResult::DatabaseError;
+        }
+    }
+
+    DatabaseResult::Ok(())
+}
+
+/// Schema migration copying awu_eject into awu_ejection. A serial number in a comment becomes
+/// an ejection of its own.
+pub(super) fn copy_legacy_ejections(tx: &mut Transaction) -> DatabaseResult<()> {
+    let rows = match tx.exec(r"SELECT * FROM awu_eject;", vec![]) {
+        DatabaseResult::Ok(rows) => rows,
+        DatabaseResult::DatabaseError => return DatabaseResult::DatabaseError,
     };
+    let legacy: Vec<LegacyEjectionQuery> = match decode_all(&rows) {
+        DatabaseResult::Ok(legacy) => legacy,
+        DatabaseResult::DatabaseError => return DatabaseResult::DatabaseError,
+    };
+
+    for old in legacy {
+        let address = Ipv4Addr::from(old.address.to_le_bytes());
+        for target in legacy_targets(address, &old.comment) {
+            let ejection =
+                EjectionQuery::new(target, &old.comment, 0, old.creation, old.expiration);
+            if insert_ejection(tx, &ejection).is_err() {
+                return DatabaseResult::DatabaseError;
+            }
+        }
+    }
+
+    DatabaseResult::Ok(())
+}
 
-    let creation = match row.fetch_int("Creation").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
+/// Add an ejection, or replace the one of the same target, keeping its ID.
+fn insert_ejection(tx: &mut Transaction, ejection: &EjectionQuery) -> DatabaseResult<u32> {
+    let r = tx.exec(
+        r"SELECT * FROM awu_ejection WHERE Kind=? AND RangeStart=? AND RangeEnd=? AND Number=?;",
+        aw_params! {
+            ejection.kind,
+            ejection.range_start,
+            ejection.range_end,
+            ejection.number
+        },
+    );
+
+    let existing = match r {
+        DatabaseResult::Ok(rows) => match decode_first::<EjectionQuery>(&rows) {
+            DatabaseResult::Ok(existing) => existing,
+            DatabaseResult::DatabaseError => return DatabaseResult::DatabaseError,
+        },
+        DatabaseResult::DatabaseError => return DatabaseResult::DatabaseError,
     };
 
-    let expiration = match row.fetch_int("Expiration").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
+    if let Some(existing) = existing {
+        let r = tx.exec(
+            r"UPDATE awu_ejection SET Prefix=?, Reason=?, Admin=?, Creation=?, Expiration=?
+            WHERE ID=?;",
+            aw_params! {
+                ejection.prefix,
+                ejection.reason,
+                ejection.admin,
+                ejection.creation,
+                ejection.expiration,
+                existing.id
+            },
+        );
+
+        return match r {
+            DatabaseResult::Ok(_) => DatabaseResult::Ok(existing.id),
+            DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
+        };
+    }
+
+    let r = tx.exec(
+        r"INSERT INTO awu_ejection
+        (Kind, RangeStart, RangeEnd, Prefix, Number, Reason, Admin, Creation, Expiration)
+        VALUES(?, ?, ?, ?, ?, ?, ?, ?, ?);",
+        aw_params! {
+            ejection.kind,
+            ejection.range_start,
+            ejection.range_end,
+            ejection.prefix,
+            ejection.number,
+            ejection.reason,
+            ejection.admin,
+            ejection.creation,
+            ejection.expiration
+        },
+    );
+
+    if r.is_err() {
+        return DatabaseResult::DatabaseError;
+    }
+
+    tx.last_insert_id("awu_ejection", "ID")
+}
+
+fn address_key(address: u128) -> String {
+    format!("{address:032x}")
+}
+
+fn ipv4_key(address: Ipv4Addr) -> String {
+    address_key(mapped_address(IpAddr::V4(address)))
+}
+
+fn decode_first<T: FromRow>(rows: &[Row]) -> DatabaseResult<Option<T>> {
+    let Some(row) = rows.first() else {
+        return DatabaseResult::Ok(None);
     };
 
-    let comment = match row.fetch_string("Comment") {
-        Some(x) => x,
-        _ => return DatabaseResult::DatabaseError,
+    match row.decode() {
+        DatabaseResult::Ok(value) => DatabaseResult::Ok(Some(value)),
+        DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
+    }
+}
+
+fn decode_all<T: FromRow>(rows: &[Row]) -> DatabaseResult<Vec<T>> {
+    let mut values = Vec::with_capacity(rows.len());
+    for row in rows {
+        match row.decode() {
+            DatabaseResult::Ok(value) => values.push(value),
+            DatabaseResult::DatabaseError => return DatabaseResult::DatabaseError,
+        }
+    }
+
+    DatabaseResult::Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        configuration::Config,
+        database::{CitizenDB, SchemaDB},
     };
 
-    DatabaseResult::Ok(EjectionQuery {
-        address,
-        expiration,
-        creation,
-        comment,
-    })
+    #[test]
+    fn legacy_ejections_are_copied_and_matched() {
+        let mut config = Config::default().sql;
+        config.sqlite_config.path = ":memory:".to_string();
+        let db = UniverseDatabase::open(config).unwrap();
+        assert!(!db.init_eject().is_err());
+        assert!(!db.init_citizen().is_err());
+        db.schema_check().unwrap();
+
+        let address = u32::from_le_bytes([10, 0, 0, 1]);
+        for (address, comment) in [(address, "spam"), (0, "serial=0x1234ABCD")] {
+            let r = db.db.exec(
+                r"INSERT INTO awu_eject (Expiration, Creation, Address, Comment)
+                VALUES(?, ?, ?, ?);",
+                aw_params! { 2000, 1000, address, comment },
+            );
+            assert!(!r.is_err());
+        }
+        db.schema_migrate(0).unwrap();
+
+        let matching = |address: &str, serial: Option<u32>, now: u32| match db.ejections_matching(
+            address.parse().unwrap(),
+            serial,
+            None,
+            now,
+        ) {
+            DatabaseResult::Ok(ejections) => ejections
+                .iter()
+                .map(|e| e.target().unwrap().to_string())
+                .collect::<Vec<_>>(),
+            DatabaseResult::DatabaseError => panic!("Could not match ejections"),
+        };
+        assert_eq!(matching("10.0.0.1", None, 1500), ["10.0.0.1"]);
+        assert_eq!(
+            matching("10.0.0.2", Some(0x1234ABCD), 1500),
+            ["serial:0x1234ABCD"]
+        );
+        assert!(matching("10.0.0.1", None, 2001).is_empty());
+
+        let network = EjectionTarget::network("2001:db8::".parse().unwrap(), 32).unwrap();
+        let ejection = EjectionQuery::new(network, "", 1, 1000, 2000);
+        assert!(!db.ejection_set(&ejection).is_err());
+        assert_eq!(matching("2001:db8:1::5", None, 1500), ["2001:db8::/32"]);
+        assert!(matching("2001:db9::", None, 1500).is_empty());
+    }
 }
@@ -1,4 +1,4 @@
-use aw_db::{aw_params, DatabaseResult, Row};
+use aw_db::{aw_params, from_row, DatabaseResult};
 
 use crate::timestamp::unix_epoch_timestamp_u32;
 
@@ -24,6 +24,26 @@ pub struct LicenseQuery {
     pub plugins: u32,
 }
 
+from_row!(LicenseQuery {
+    id: "ID",
+    name: "Name",
+    password: "Password",
+    email: "Email",
+    comment: "Comment",
+    creation: "Creation",
+    expiration: "Expiration",
+    last_start: "LastStart",
+    // It is not unexpected for LastAddress to end up in the database as a negative number.
+    last_address: "LastAddress" => |x: i64| x as u32,
+    users: "Users",
+    world_size: "WorldSize",
+    hidden: "Hidden",
+    changed: "Changed",
+    tourists: "Tourists",
+    voip: "Voip",
+    plugins: "Plugins",
+});
+
 pub trait LicenseDB {
     fn init_license(&self) -> DatabaseResult<()>;
     fn license_by_name(&self, name: &str) -> DatabaseResult<Option<LicenseQuery>>;
@@ -91,7 +111,7 @@ impl LicenseDB for UniverseDatabase {
             return DatabaseResult::Ok(None);
         };
 
-        match fetch_license(row) {
+        match row.decode() {
             DatabaseResult::Ok(lic) => DatabaseResult::Ok(Some(lic)),
             DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
         }
@@ -150,7 +170,7 @@ impl LicenseDB for UniverseDatabase {
             return DatabaseResult::Ok(None);
         };
 
-        match fetch_license(row) {
+        match row.decode() {
             DatabaseResult::Ok(lic) => DatabaseResult::Ok(Some(lic)),
             DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
         }
@@ -177,7 +197,7 @@ impl LicenseDB for UniverseDatabase {
             return DatabaseResult::Ok(None);
         };
 
-        match fetch_license(row) {
+        match row.decode() {
             DatabaseResult::Ok(lic) => DatabaseResult::Ok(Some(lic)),
             DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
         }
@@ -186,7 +206,7 @@ impl LicenseDB for UniverseDatabase {
     fn license_change(&self, lic: &LicenseQuery) -> DatabaseResult<()> {
         let r = self.db.exec(
             r"UPDATE awu_license
-            SET Changed=NOT Changed, Creation=?, Expiration=?, LastStart=?, 
+            SET Changed=1-Changed, Creation=?, Expiration=?, LastStart=?, 
             LastAddress=?, Hidden=?, Tourists=?, Users=?,
             WorldSize=?, Voip=?, Plugins=?, Password=?, 
             Email=?, Comment=? 
@@ -229,105 +249,3 @@ impl LicenseDB for UniverseDatabase {
         }
     }
 }
-
-fn fetch_license(row: &Row) -> DatabaseResult<LicenseQuery> {
-    let id = match row.fetch_int("ID").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    let name = match row.fetch_string("Name") {
-        Some(x) => x,
-        None => return DatabaseResult::DatabaseError,
-    };
-
-    let password = match row.fetch_string("Password") {
-        Some(x) => x,
-        None => return DatabaseResult::DatabaseError,
-    };
-
-    let email = match row.fetch_string("Email") {
-        Some(x) => x,
-        None => return DatabaseResult::DatabaseError,
-    };
-
-    let comment = match row.fetch_string("Comment") {
-        Some(x) => x,
-        None => return DatabaseResult::DatabaseError,
-    };
-
-    let creation = match row.fetch_int("Creation").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    let expiration = match row.fetch_int("Expiration").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    let last_start = match row.fetch_int("LastStart").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    // It is not unexpected for LastAddress to end up in the database as a negative number.
-    let last_address = match row.fetch_int("LastAddress").map(|x| x as u32) {
-        Some(x) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    let users = match row.fetch_int("Users").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    let world_size = match row.fetch_int("WorldSize").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    let hidden = match row.fetch_int("Hidden").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    let changed = match row.fetch_int("Changed").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    let tourists = match row.fetch_int("Tourists").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    let voip = match row.fetch_int("Voip").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    let plugins = match row.fetch_int("Plugins").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    DatabaseResult::Ok(LicenseQuery {
-        id,
-        name,
-        password,
-        email,
-        comment,
-        expiration,
-        last_start,
-        last_address,
-        users,
-        world_size,
-        hidden,
-        changed,
-        tourists,
-        voip,
-        plugins,
-        creation,
-    })
-}
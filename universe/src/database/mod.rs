@@ -1,4 +1,4 @@
-use aw_db::{Database, DatabaseConfig, DatabaseOpenError};
+use aw_db::{Database, DatabaseConfig, DatabaseOpenError, DatabaseResult, DatabaseStats};
 
 use crate::configuration::UniverseConfig;
 
@@ -8,15 +8,26 @@ pub use self::citizen::CitizenDB;
 pub use self::contact::ContactDB;
 pub use self::eject::EjectDB;
 pub use self::license::LicenseDB;
+pub use self::schema::{SchemaDB, SchemaError};
 pub use self::telegram::TelegramDB;
 pub mod attrib;
 pub mod cav;
 pub mod citizen;
 pub mod contact;
+pub mod convert;
 pub mod eject;
 pub mod license;
+pub mod schema;
 pub mod telegram;
 
+#[derive(thiserror::Error, Debug)]
+pub enum UniverseDatabaseError {
+    #[error("{0}")]
+    Open(#[from] DatabaseOpenError),
+    #[error("{0}")]
+    Schema(#[from] SchemaError),
+}
+
 pub struct UniverseDatabase {
     db: aw_db::Database,
 }
@@ -25,22 +36,68 @@ impl UniverseDatabase {
     pub fn new(
         config: DatabaseConfig,
         universe_config: &UniverseConfig,
-    ) -> Result<Self, DatabaseOpenError> {
-        let db = Database::new(config)?;
-        let unidb = UniverseDatabase { db };
+    ) -> Result<Self, UniverseDatabaseError> {
+        let unidb = Self::open(config)?;
+
+        unidb.init_tables()?;
+        unidb.init_contents(universe_config)?;
+
+        Ok(unidb)
+    }
+
+    /// Opens the database, creating and migrating its tables without putting anything in them.
+    pub fn new_empty(config: DatabaseConfig) -> Result<Self, UniverseDatabaseError> {
+        let unidb = Self::open(config)?;
 
-        unidb.init_tables(universe_config);
+        unidb.init_tables()?;
 
         Ok(unidb)
     }
 
-    fn init_tables(&self, universe_config: &UniverseConfig) {
-        self.init_attrib(universe_config);
-        self.init_citizen();
-        self.init_contact();
-        self.init_license();
-        self.init_telegram();
-        self.init_cav();
-        self.init_eject();
+    /// Opens the database without creating or migrating any tables.
+    pub fn open(config: DatabaseConfig) -> Result<Self, DatabaseOpenError> {
+        let db = Database::new(config)?;
+
+        Ok(UniverseDatabase { db })
+    }
+
+    /// Statistics about the statements executed so far.
+    pub fn stats(&self) -> &DatabaseStats {
+        self.db.stats()
+    }
+
+    fn init_tables(&self) -> Result<(), SchemaError> {
+        // Nothing may be touched before making sure the schema is not from a newer universe
+        let version = self.schema_check()?;
+
+        // These create any missing tables as they were in schema version 1
+        let results = [
+            self.init_attrib(),
+            self.init_citizen(),
+            self.init_contact(),
+            self.init_license(),
+            self.init_telegram(),
+            self.init_cav(),
+            self.init_eject(),
+        ];
+        if results.iter().any(DatabaseResult::is_err) {
+            return Err(SchemaError::InitFailed);
+        }
+
+        self.schema_migrate(version)
+    }
+
+    /// Stores the configured attributes and makes sure citizen #1 exists.
+    fn init_contents(&self, universe_config: &UniverseConfig) -> Result<(), SchemaError> {
+        // Citizen #1 is only set up once migrated, as it uses columns added since schema version 1
+        let results = [
+            self.attrib_set_config(universe_config),
+            self.init_administrator(),
+        ];
+        if results.iter().any(DatabaseResult::is_err) {
+            return Err(SchemaError::InitFailed);
+        }
+
+        Ok(())
     }
 }
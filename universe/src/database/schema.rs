@@ -0,0 +1,200 @@
+use aw_db::{aw_params, DatabaseResult, Transaction};
+
+use crate::timestamp::unix_epoch_timestamp_u32;
+
+use super::{citizen, eject, UniverseDatabase};
+
+/// A single step in the evolution of the database schema.
+/// Steps must use statements which work on SQLite, MySQL and Postgres.
+/// Each step runs in a transaction along with recording its version, but MySQL commits
+/// implicitly after schema changes, so a step should not mix schema changes with data changes.
+struct Migration {
+    version: u32,
+    description: &'static str,
+    apply: fn(&mut Transaction) -> DatabaseResult<()>,
+}
+
+/// Every schema migration, ordered by version.
+/// New steps are only ever appended, and a step must never change once it has been released.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Initial schema",
+        // The initial tables are created by the init_* functions of each table.
+        apply: |_| DatabaseResult::Ok(()),
+    },
+    Migration {
+        version: 2,
+        description: "Add awu_ejection for ejections of networks, serial numbers and citizens",
+        apply: eject::create_ejection_table,
+    },
+    Migration {
+        version: 3,
+        description: "Copy ejections from awu_eject into awu_ejection",
+        apply: eject::copy_legacy_ejections,
+    },
+    Migration {
+        version: 4,
+        description: "Store LastAddress of citizens and licenses as unsigned",
+        apply: widen_last_address,
+    },
+    Migration {
+        version: 5,
+        description: "Add PasswordChange to awu_citizen for passwords which must be changed",
+        apply: citizen::add_password_change_column,
+    },
+];
+
+/// The schema version this build of the universe expects.
+pub fn latest_schema_version() -> u32 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+/// Addresses are stored as a u32, which the signed LastAddress columns of the initial schema can't
+/// hold for every address on MySQL and Postgres.
+fn widen_last_address(tx: &mut Transaction) -> DatabaseResult<()> {
+    let unsigned = tx.unsigned_str();
+
+    for table in ["awu_citizen", "awu_license"] {
+        let r = tx.change_column_type(
+            table,
+            "LastAddress",
+            &format!("INTEGER {unsigned}"),
+            "NOT NULL default '0'",
+        );
+
+        if r.is_err() {
+            return DatabaseResult::DatabaseError;
+        }
+    }
+
+    DatabaseResult::Ok(())
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SchemaError {
+    #[error("Couldn't determine the version of the database schema")]
+    VersionUnavailable,
+    #[error("The database schema is version {found}, but this universe only supports up to version {supported}. Refusing to run against a newer schema")]
+    TooNew { found: u32, supported: u32 },
+    #[error("Couldn't create the initial database tables")]
+    InitFailed,
+    #[error("Migration to schema version {version} ({description}) failed")]
+    MigrationFailed {
+        version: u32,
+        description: &'static str,
+    },
+}
+
+pub trait SchemaDB {
+    fn init_schema(&self) -> DatabaseResult<()>;
+    fn schema_version(&self) -> DatabaseResult<u32>;
+    fn schema_check(&self) -> Result<u32, SchemaError>;
+    fn schema_migrate(&self, from_version: u32) -> Result<(), SchemaError>;
+}
+
+impl SchemaDB for UniverseDatabase {
+    fn init_schema(&self) -> DatabaseResult<()> {
+        let r = self.db.exec(
+            r"CREATE TABLE IF NOT EXISTS awu_schema_version (
+            Version INTEGER PRIMARY KEY NOT NULL,
+            Applied INTEGER NOT NULL default '0',
+            Description varchar(255) NOT NULL default ''
+        );",
+            vec![],
+        );
+
+        match r {
+            DatabaseResult::Ok(_) => DatabaseResult::Ok(()),
+            DatabaseResult::DatabaseError => DatabaseResult::DatabaseError,
+        }
+    }
+
+    fn schema_version(&self) -> DatabaseResult<u32> {
+        let rows = match self.db.exec(
+            r"SELECT Version FROM awu_schema_version ORDER BY Version DESC LIMIT 1;",
+            vec![],
+        ) {
+            DatabaseResult::Ok(rows) => rows,
+            DatabaseResult::DatabaseError => return DatabaseResult::DatabaseError,
+        };
+
+        // A database without any recorded versions predates versioning (or is brand new)
+        let Some(row) = rows.first() else {
+            return DatabaseResult::Ok(0);
+        };
+
+        match row.fetch_int("Version").map(u32::try_from) {
+            Some(Ok(x)) => DatabaseResult::Ok(x),
+            _ => DatabaseResult::DatabaseError,
+        }
+    }
+
+    /// Ensures the version table exists and that the schema is not newer than this build understands.
+    /// Returns the current schema version.
+    fn schema_check(&self) -> Result<u32, SchemaError> {
+        if self.init_schema().is_err() {
+            return Err(SchemaError::VersionUnavailable);
+        }
+
+        let found = match self.schema_version() {
+            DatabaseResult::Ok(version) => version,
+            DatabaseResult::DatabaseError => return Err(SchemaError::VersionUnavailable),
+        };
+
+        let supported = latest_schema_version();
+        if found > supported {
+            return Err(SchemaError::TooNew { found, supported });
+        }
+
+        Ok(found)
+    }
+
+    /// Applies every migration newer than `from_version`, in order.
+    fn schema_migrate(&self, from_version: u32) -> Result<(), SchemaError> {
+        for migration in MIGRATIONS.iter().filter(|m| m.version > from_version) {
+            log::info!(
+                "Migrating database schema to version {}: {}",
+                migration.version,
+                migration.description
+            );
+
+            let r = self.db.transaction(|tx| {
+                if (migration.apply)(tx).is_err() {
+                    return DatabaseResult::DatabaseError;
+                }
+
+                tx.exec(
+                    r"INSERT INTO awu_schema_version (Version, Applied, Description) VALUES(?, ?, ?);",
+                    aw_params!(
+                        migration.version,
+                        unix_epoch_timestamp_u32(),
+                        migration.description
+                    ),
+                )
+            });
+
+            if r.is_err() {
+                return Err(SchemaError::MigrationFailed {
+                    version: migration.version,
+                    description: migration.description,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrations_are_ordered() {
+        for pair in MIGRATIONS.windows(2) {
+            assert!(pair[0].version < pair[1].version);
+        }
+        assert_eq!(MIGRATIONS.first().map(|m| m.version), Some(1));
+    }
+}
@@ -1,4 +1,4 @@
-use aw_db::{aw_params, DatabaseResult, Row};
+use aw_db::{aw_params, from_row, DatabaseResult};
 
 use super::UniverseDatabase;
 
@@ -12,6 +12,15 @@ pub struct TelegramQuery {
     pub delivered: u32,
 }
 
+from_row!(TelegramQuery {
+    id: "ID",
+    citizen: "Citizen",
+    from: "From",
+    timestamp: "Timestamp",
+    message: "Message",
+    delivered: "Delivered",
+});
+
 pub trait TelegramDB {
     fn init_telegram(&self) -> DatabaseResult<()>;
     fn telegram_add(&self, to: u32, from: u32, timestamp: u32, message: &str)
@@ -84,7 +93,7 @@ impl TelegramDB for UniverseDatabase {
 
         let mut telegrams = Vec::<TelegramQuery>::new();
         for row in &rows {
-            match fetch_telegram(row) {
+            match row.decode() {
                 DatabaseResult::Ok(telegram) => telegrams.push(telegram),
                 DatabaseResult::DatabaseError => return DatabaseResult::DatabaseError,
             }
@@ -109,7 +118,7 @@ impl TelegramDB for UniverseDatabase {
 
         let mut telegrams = Vec::<TelegramQuery>::new();
         for row in &rows {
-            match fetch_telegram(row) {
+            match row.decode() {
                 DatabaseResult::Ok(telegram) => telegrams.push(telegram),
                 DatabaseResult::DatabaseError => return DatabaseResult::DatabaseError,
             }
@@ -133,44 +142,3 @@ impl TelegramDB for UniverseDatabase {
         }
     }
 }
-
-fn fetch_telegram(row: &Row) -> DatabaseResult<TelegramQuery> {
-    let id = match row.fetch_int("ID").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    let citizen = match row.fetch_int("Citizen").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    let from = match row.fetch_int("From").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    let timestamp = match row.fetch_int("Timestamp").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    let message = match row.fetch_string("Message") {
-        Some(x) => x,
-        None => return DatabaseResult::DatabaseError,
-    };
-
-    let delivered = match row.fetch_int("Delivered").map(u32::try_from) {
-        Some(Ok(x)) => x,
-        _ => return DatabaseResult::DatabaseError,
-    };
-
-    DatabaseResult::Ok(TelegramQuery {
-        id,
-        citizen,
-        from,
-        timestamp,
-        message,
-        delivered,
-    })
-}
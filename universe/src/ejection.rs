@@ -1,105 +1,238 @@
-use std::net::IpAddr;
+//! Ejections keep addresses, networks, browsers and citizens out of the universe until they
+//! expire.
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
 
+use aw_core::ReasonCode;
 use aw_db::DatabaseResult;
 
 use crate::{
-    client::ClientInfo,
-    database::{eject::EjectionQuery, EjectDB, UniverseDatabase},
-    timestamp::unix_epoch_timestamp_u32,
-    UniverseConnection,
+    client::ClientInfo, database::EjectDB, database::UniverseDatabase,
+    timestamp::unix_epoch_timestamp_u32, UniverseConnection, UniverseServer,
 };
 
-pub fn is_ejection_expired(ejection: &EjectionQuery) -> bool {
-    log::trace!(
-        "unix_epoch_timestamp_u32() = {}; ejection.expiration = {}",
-        unix_epoch_timestamp_u32(),
-        ejection.expiration
-    );
-    unix_epoch_timestamp_u32() > ejection.expiration
+/// What an ejection keeps out of the universe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EjectionTarget {
+    /// A single IPv4 or IPv6 address
+    Address(IpAddr),
+    /// Every address in a CIDR prefix, given by its first address and the prefix length
+    Network(IpAddr, u8),
+    /// A browser, by the volume serial number it sends when logging in
+    Serial(u32),
+    /// A citizen, from wherever they log in
+    Citizen(u32),
+}
+
+impl EjectionTarget {
+    /// The network of `prefix` bits around `address`, or `None` if the prefix is longer than the
+    /// address.
+    pub fn network(address: IpAddr, prefix: u8) -> Option<Self> {
+        let bits = prefix_bits(address, prefix)?;
+        let first = mapped_address(address) & network_mask(bits);
+
+        Some(Self::Network(unmapped_address(first), prefix))
+    }
+
+    /// First and last addresses covered, with IPv4 addresses mapped into IPv6 so that both
+    /// families can be compared alike.
+    pub fn address_range(&self) -> Option<(u128, u128)> {
+        match *self {
+            Self::Address(address) => {
+                let address = mapped_address(address);
+                Some((address, address))
+            }
+            Self::Network(address, prefix) => {
+                let mask = network_mask(prefix_bits(address, prefix)?);
+                let first = mapped_address(address) & mask;
+                Some((first, first | !mask))
+            }
+            Self::Serial(_) | Self::Citizen(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for EjectionTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Address(address) => write!(f, "{address}"),
+            Self::Network(address, prefix) => write!(f, "{address}/{prefix}"),
+            Self::Serial(serial) => write!(f, "serial:0x{serial:08X}"),
+            Self::Citizen(citizen) => write!(f, "citizen:{citizen}"),
+        }
+    }
+}
+
+impl FromStr for EjectionTarget {
+    type Err = String;
+
+    /// Parse an address like `192.0.2.7` or `2001:db8::1`, a network like `192.0.2.0/24`, or
+    /// `serial:0x1234ABCD` or `citizen:42`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(serial) = s.strip_prefix("serial:") {
+            let digits = serial
+                .strip_prefix("0x")
+                .or_else(|| serial.strip_prefix("0X"))
+                .unwrap_or(serial);
+            return u32::from_str_radix(digits, 16)
+                .map(Self::Serial)
+                .map_err(|_| format!("{serial} is not a hexadecimal serial number"));
+        }
+
+        if let Some(citizen) = s.strip_prefix("citizen:") {
+            return citizen
+                .parse()
+                .map(Self::Citizen)
+                .map_err(|_| format!("{citizen} is not a citizen number"));
+        }
+
+        match s.split_once('/') {
+            Some((address, prefix)) => {
+                let address = address
+                    .parse::<IpAddr>()
+                    .map_err(|_| format!("{address} is not an IP address"))?;
+                prefix
+                    .parse::<u8>()
+                    .ok()
+                    .and_then(|prefix| Self::network(address, prefix))
+                    .ok_or_else(|| format!("{prefix} is not a prefix length for {address}"))
+            }
+            None => s
+                .parse()
+                .map(Self::Address)
+                .map_err(|_| format!("{s} is not an address, network, serial or citizen")),
+        }
+    }
+}
+
+/// What an ejection sent by a client covers. Clients can only eject IPv4 addresses, so serial
+/// numbers are written into the comment as `serial=0x...`, with an address of 0 if there is only
+/// the serial number to eject.
+pub fn legacy_targets(address: Ipv4Addr, comment: &str) -> Vec<EjectionTarget> {
+    let serial = comment
+        .strip_prefix("serial=0x")
+        .and_then(|s| u32::from_str_radix(s, 16).ok());
+
+    let address = (!address.is_unspecified()).then_some(EjectionTarget::Address(address.into()));
+    address
+        .into_iter()
+        .chain(serial.map(EjectionTarget::Serial))
+        .collect()
 }
 
+/// `address` as an IPv6 address, with IPv4 addresses mapped into `::ffff:0:0/96`.
+pub fn mapped_address(address: IpAddr) -> u128 {
+    match address {
+        IpAddr::V4(address) => u128::from(address.to_ipv6_mapped()),
+        IpAddr::V6(address) => u128::from(address),
+    }
+}
+
+/// The address which [`mapped_address`] turned into `address`.
+pub fn unmapped_address(address: u128) -> IpAddr {
+    Ipv6Addr::from(address).to_canonical()
+}
+
+/// Length of `prefix` in IPv6 bits, once the address is mapped.
+fn prefix_bits(address: IpAddr, prefix: u8) -> Option<u32> {
+    let prefix = u32::from(prefix);
+    match address {
+        IpAddr::V4(_) if prefix <= 32 => Some(prefix + 96),
+        IpAddr::V6(_) if prefix <= 128 => Some(prefix),
+        _ => None,
+    }
+}
+
+fn network_mask(bits: u32) -> u128 {
+    u128::MAX.checked_shl(128 - bits).unwrap_or(0)
+}
+
+/// Disconnect every client which is covered by an ejection, such as one which was just added.
+/// World servers are told their worlds were stopped because they are ejected.
+pub fn disconnect_ejected_connections(server: &mut UniverseServer) {
+    for (_id, conn) in server.connections.iter_mut() {
+        match is_connection_ejected(&server.database, conn) {
+            Some(true) => {
+                log::info!("Disconnecting {:?} because it is ejected", conn.addr().ip());
+                conn.stop_worlds(ReasonCode::Ejected);
+                conn.disconnect();
+            }
+            Some(false) => log::trace!("New ejection is not logged in"),
+            None => log::debug!("Failed to check if client is ejected"),
+        }
+    }
+}
+
+/// Whether an ejection in effect covers the connection's address, its browser's serial number
+/// or its citizen, or `None` if that could not be looked up.
 pub fn is_connection_ejected(
     database: &UniverseDatabase,
     conn: &UniverseConnection,
 ) -> Option<bool> {
-    let IpAddr::V4(ip) = conn.addr().ip() else {
-        return Some(false);
-    };
-
-    let conn_serial = conn
-        .client
-        .as_ref()
+    let client = conn.client.as_ref();
+    let serial = client
         .and_then(ClientInfo::player_info)
         .and_then(|p| p.serial);
+    let citizen = client.and_then(ClientInfo::citizen_id);
 
-    // Safeguard Administrator from being ejected
-    if let Some(user_id) = conn.client.as_ref().and_then(ClientInfo::citizen_id) {
-        if user_id == 1 {
-            log::trace!("Administrator would have been ejected, but has been prevented");
-            return Some(false);
-        }
-    }
-
-    let ip_u32 = u32::from_le_bytes(ip.octets());
-
-    // Go through each ejection, check if IP or serial matches
-    let mut eject_addr: u32 = 0;
-    loop {
-        // log::trace!("Checking ejection starting from {eject_addr}");
-        match database.ejection_next(eject_addr) {
-            DatabaseResult::Ok(Some(ejection)) => {
-                eject_addr = ejection.address;
-
-                // Don't enforce expired ejections
-                if is_ejection_expired(&ejection) {
-                    log::trace!("ejection expired");
-                    continue;
-                }
-
-                // Does ejection match this connection's address?
-                if ejection.address == ip_u32 {
-                    log::trace!(
-                        "Connection is ejected because it has address {ip:?} ({ip_u32}) - {conn:?}"
-                    );
-                    return Some(true);
-                }
-
-                // Get serial from ejection comment
-                let comment = ejection.comment;
-                let Some(comment_serial) = comment
-                    .strip_prefix("serial=0x")
-                    .and_then(|s| u32::from_str_radix(s, 16).ok())
-                else {
-                    log::trace!("no serial parsed in {comment:?}");
-                    continue;
-                };
-
-                // log::trace!("Parsed serial {comment_serial:X} out of {comment:?}. conn's serial is {conn_serial:?}");
-
-                // Is the serial in the comment the same as the conn's serial?
-                if let Some(conn_serial) = conn_serial {
-                    if conn_serial == comment_serial {
-                        log::trace!(
-                            "Connection is ejected because it has serial ({conn_serial:X}) - {conn:?}"
-                        );
-                        return Some(true);
-                    }
-                }
+    let now = unix_epoch_timestamp_u32();
+    match database.ejections_matching(conn.addr().ip(), serial, citizen, now) {
+        // Safeguard Administrator from being ejected by citizen number, which still leaves
+        // ejections of their address or serial number in effect
+        DatabaseResult::Ok(ejections) => match ejections
+            .iter()
+            .find(|ejection| ejection.target() != Some(EjectionTarget::Citizen(1)))
+        {
+            Some(ejection) => {
+                log::trace!(
+                    "Connection is ejected by ejection {} ({:?}) - {conn:?}",
+                    ejection.id,
+                    ejection.target()
+                );
+                Some(true)
             }
-            DatabaseResult::Ok(None) => return Some(false),
-            DatabaseResult::DatabaseError => return None,
-        }
+            None => Some(false),
+        },
+        DatabaseResult::DatabaseError => None,
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn targets_parse_and_cover_addresses() {
+        let network = "192.0.2.77/24".parse::<EjectionTarget>().unwrap();
+        assert_eq!(network.to_string(), "192.0.2.0/24");
 
-    // match database.ejection_lookup(ip_u32) {
-    //     aw_db::DatabaseResult::Ok(Some(ejection)) => {
-    //         if !is_ejection_expired(&ejection) {
-    //             Some(true)
-    //         } else {
-    //             Some(false)
-    //         }
-    //     }
-    //     aw_db::DatabaseResult::Ok(None) => Some(false),
-    //     aw_db::DatabaseResult::DatabaseError => None,
-    // }
+        let (first, last) = network.address_range().unwrap();
+        let covered = |address: &str| {
+            let address = mapped_address(address.parse().unwrap());
+            first <= address && address <= last
+        };
+        assert!(covered("192.0.2.0"));
+        assert!(covered("192.0.2.255"));
+        assert!(covered("::ffff:192.0.2.1"));
+        assert!(!covered("192.0.3.0"));
+
+        let network = "2001:db8::/32".parse::<EjectionTarget>().unwrap();
+        assert_eq!(
+            network
+                .address_range()
+                .map(|(first, _)| unmapped_address(first)),
+            Some("2001:db8::".parse().unwrap())
+        );
+
+        assert_eq!(
+            "serial:0x1234abcd".parse(),
+            Ok(EjectionTarget::Serial(0x1234ABCD))
+        );
+        assert_eq!("citizen:42".parse(), Ok(EjectionTarget::Citizen(42)));
+        assert!("10.0.0.0/33".parse::<EjectionTarget>().is_err());
+        assert!("0.0.0.0/0".parse::<EjectionTarget>().is_ok());
+    }
 }
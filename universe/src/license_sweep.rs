@@ -0,0 +1,74 @@
+//! Stopping worlds which no longer have a license to run under.
+//!
+//! A world is started under the license of the same name, and keeps the password, user limit and
+//! size it had then. The license may expire, be deleted or be changed while the world runs, so
+//! running worlds are periodically checked against the database, and whichever no longer match
+//! are stopped. Their world server is told why, and everyone's world list is updated.
+use std::time::Duration;
+
+use aw_core::ReasonCode;
+use aw_db::DatabaseResult;
+
+use crate::{
+    database::{LicenseDB, UniverseDatabase},
+    tabs::regenerate_world_list,
+    timestamp::unix_epoch_timestamp_u32,
+    world::World,
+    UniverseServer,
+};
+
+/// How often running worlds are checked against their licenses.
+pub const SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Stop every running world whose license expired, was deleted or was changed since it started.
+pub fn sweep_licenses(server: &mut UniverseServer) {
+    let now = unix_epoch_timestamp_u32();
+    let mut stopped_any = false;
+
+    for (_id, conn) in server.connections.iter_mut() {
+        let Some(world_server) = conn.world_server() else {
+            continue;
+        };
+
+        let stopped: Vec<(String, ReasonCode)> = world_server
+            .worlds
+            .iter()
+            .filter_map(|world| {
+                license_problem(&server.database, world, now).map(|rc| (world.name.clone(), rc))
+            })
+            .collect();
+
+        for (name, rc) in stopped {
+            log::info!("Stopping world {name:?} because its license is no longer valid: {rc:?}");
+
+            conn.stop_world(&name, rc);
+            stopped_any = true;
+        }
+    }
+
+    if stopped_any {
+        for cid in server.connections.cids() {
+            regenerate_world_list(server, cid);
+        }
+    }
+}
+
+/// Why `world` may no longer run under its license, if it may not. Worlds are left running when
+/// the database can't be read.
+fn license_problem(database: &UniverseDatabase, world: &World, now: u32) -> Option<ReasonCode> {
+    let lic = match database.license_by_name(&world.name) {
+        DatabaseResult::Ok(Some(lic)) => lic,
+        DatabaseResult::Ok(None) => return Some(ReasonCode::NoSuchLicense),
+        DatabaseResult::DatabaseError => return None,
+    };
+
+    if lic.expiration != 0 && lic.expiration < now {
+        Some(ReasonCode::WorldExpired)
+    } else if lic.password != world.license_password {
+        Some(ReasonCode::InvalidPassword)
+    } else if lic.users != world.max_users || lic.world_size != world.world_size {
+        Some(ReasonCode::InvalidWorld)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,257 @@
+//! Accepting new connections, and waiting for connections to have something to handle.
+use aw_core::{AWConnection, PacketCapture, ProtocolStats};
+use std::io;
+use std::net::{SocketAddr, SocketAddrV4};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[cfg(feature = "tokio")]
+pub use event_driven::Listener;
+#[cfg(not(feature = "tokio"))]
+pub use polled::Listener;
+
+/// Open a file to capture a new connection's packets in, if captures are enabled.
+fn open_capture(capture_dir: Option<&Path>, peer: SocketAddr) -> Option<PacketCapture> {
+    let dir = capture_dir?;
+    match PacketCapture::create_in(dir, peer) {
+        Ok(capture) => Some(capture),
+        Err(why) => {
+            log::error!(
+                "Failed to capture packets of {peer} in {}: {why}",
+                dir.display()
+            );
+            None
+        }
+    }
+}
+
+/// Every connection is serviced by its own thread, so the main loop has to poll them.
+#[cfg(not(feature = "tokio"))]
+mod polled {
+    use super::*;
+    use aw_core::AWProtocol;
+    use std::{net::TcpListener, thread::sleep, time::Duration};
+
+    pub struct Listener {
+        listener: TcpListener,
+        capture_dir: Option<PathBuf>,
+        stats: Arc<ProtocolStats>,
+    }
+
+    impl Listener {
+        /// Clients beyond `_connection_limit` are left waiting by the main loop not accepting them.
+        pub fn bind(
+            addr: SocketAddrV4,
+            _connection_limit: u16,
+            capture_dir: Option<PathBuf>,
+            stats: Arc<ProtocolStats>,
+        ) -> io::Result<Self> {
+            let listener = TcpListener::bind(addr)?;
+            listener.set_nonblocking(true)?;
+            Ok(Self {
+                listener,
+                capture_dir,
+                stats,
+            })
+        }
+
+        /// Accept every client which is waiting to connect.
+        pub fn accept(&mut self) -> Vec<AWConnection> {
+            let mut connections = Vec::new();
+            while let Ok((stream, peer)) = self.listener.accept() {
+                match AWProtocol::new(stream) {
+                    Ok(mut proto) => {
+                        if let Some(capture) = open_capture(self.capture_dir.as_deref(), peer) {
+                            proto.set_capture(capture);
+                        }
+                        proto.set_stats(self.stats.clone());
+                        connections.push(AWConnection::new(proto));
+                    }
+                    Err(why) => log::error!(
+                        "Failed to create a AWProtocol while accepting new client: {why:?}"
+                    ),
+                }
+            }
+            connections
+        }
+
+        pub fn local_addr(&self) -> io::Result<SocketAddr> {
+            self.listener.local_addr()
+        }
+
+        pub fn set_connection_limit(&mut self, _limit: u16) {}
+
+        /// Wait until connections may have something to handle.
+        pub fn wait(&self) {
+            sleep(Duration::from_millis(1));
+        }
+    }
+}
+
+/// Every connection is serviced by a task on a tokio runtime, which wakes the main loop whenever
+/// a connection is accepted or receives something.
+#[cfg(feature = "tokio")]
+mod event_driven {
+    use super::*;
+    use aw_core::AWAsyncProtocol;
+    use std::{
+        sync::mpsc::{sync_channel, Receiver, SyncSender},
+        time::Duration,
+    };
+    use tokio::{
+        net::TcpListener,
+        runtime::Runtime,
+        sync::{mpsc, Semaphore},
+    };
+
+    /// The main loop still wakes this often when nothing happens, to send heartbeats and drop
+    /// dead clients.
+    const IDLE_WAKE: Duration = Duration::from_millis(100);
+
+    /// Accepted clients which may wait for the main loop to take them.
+    const ACCEPT_QUEUE: usize = 64;
+
+    pub struct Listener {
+        local_addr: SocketAddr,
+        accepted: mpsc::Receiver<AWConnection>,
+        events: Receiver<()>,
+        /// One permit for every connection which may still be opened. Each accepted connection
+        /// holds one until the main loop drops it.
+        permits: Arc<Semaphore>,
+        connection_limit: u16,
+        // Dropped last, which stops every connection task
+        runtime: Runtime,
+    }
+
+    impl Listener {
+        /// Once `connection_limit` clients are connected, no more are accepted until one leaves.
+        pub fn bind(
+            addr: SocketAddrV4,
+            connection_limit: u16,
+            capture_dir: Option<PathBuf>,
+            stats: Arc<ProtocolStats>,
+        ) -> io::Result<Self> {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .enable_io()
+                .enable_time()
+                .build()?;
+
+            let listener = std::net::TcpListener::bind(addr)?;
+            listener.set_nonblocking(true)?;
+            let local_addr = listener.local_addr()?;
+            let listener = {
+                let _guard = runtime.enter();
+                TcpListener::from_std(listener)?
+            };
+
+            let (accepted_tx, accepted) = mpsc::channel(ACCEPT_QUEUE);
+            // A single pending wake up is enough for the main loop to look at every connection
+            let (notify, events) = sync_channel(1);
+            let permits = Arc::new(Semaphore::new(usize::from(connection_limit)));
+            runtime.spawn(accept_connections(
+                listener,
+                capture_dir,
+                stats,
+                permits.clone(),
+                accepted_tx,
+                notify,
+            ));
+
+            Ok(Self {
+                local_addr,
+                accepted,
+                events,
+                permits,
+                connection_limit,
+                runtime,
+            })
+        }
+
+        /// Take every client which has connected since the last call.
+        pub fn accept(&mut self) -> Vec<AWConnection> {
+            let mut connections = Vec::new();
+            while let Ok(connection) = self.accepted.try_recv() {
+                connections.push(connection);
+            }
+            connections
+        }
+
+        pub fn local_addr(&self) -> io::Result<SocketAddr> {
+            Ok(self.local_addr)
+        }
+
+        /// Change how many clients may be connected at once. Lowering it leaves every connected
+        /// client connected, but no more are accepted until enough of them have left.
+        pub fn set_connection_limit(&mut self, limit: u16) {
+            let previous = std::mem::replace(&mut self.connection_limit, limit);
+            if limit > previous {
+                self.permits.add_permits(usize::from(limit - previous));
+            } else if limit < previous {
+                let permits = self.permits.clone();
+                let excess = u32::from(previous - limit);
+                self.runtime.spawn(async move {
+                    if let Ok(taken) = permits.acquire_many_owned(excess).await {
+                        taken.forget();
+                    }
+                });
+            }
+        }
+
+        /// Wait until connections may have something to handle.
+        pub fn wait(&self) {
+            if self.events.recv_timeout(IDLE_WAKE).is_ok() {
+                // Everything which happened so far is handled by the next pass of the main loop
+                while self.events.try_recv().is_ok() {}
+            }
+        }
+    }
+
+    async fn accept_connections(
+        listener: TcpListener,
+        capture_dir: Option<PathBuf>,
+        stats: Arc<ProtocolStats>,
+        permits: Arc<Semaphore>,
+        accepted: mpsc::Sender<AWConnection>,
+        notify: SyncSender<()>,
+    ) {
+        loop {
+            // Clients beyond the limit are left waiting to be accepted until a connection closes
+            let Ok(permit) = permits.clone().acquire_owned().await else {
+                break;
+            };
+
+            let (stream, peer) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(why) => {
+                    // Usually out of file descriptors, so give some time for clients to leave
+                    log::error!("Failed to accept new client: {why}");
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+
+            let mut proto = match AWAsyncProtocol::new(stream) {
+                Ok(proto) => proto,
+                Err(why) => {
+                    log::error!(
+                        "Failed to create a AWAsyncProtocol while accepting new client: {why:?}"
+                    );
+                    continue;
+                }
+            };
+            if let Some(capture) = open_capture(capture_dir.as_deref(), peer) {
+                proto.set_capture(capture);
+            }
+            proto.set_stats(stats.clone());
+
+            if accepted
+                .send(AWConnection::spawn(proto, notify.clone(), permit))
+                .await
+                .is_err()
+            {
+                break;
+            }
+            notify.try_send(()).ok();
+        }
+    }
+}
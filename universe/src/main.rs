@@ -3,11 +3,15 @@ mod debug_alloc;
 
 use aw_core::*;
 
+mod admin;
 mod client;
+mod license_sweep;
+mod listener;
+mod metrics;
+mod shutdown;
 mod universe_server;
 pub use universe_server::UniverseServer;
 pub mod attributes;
-pub mod universe_license;
 pub use attributes::send_attributes;
 mod database;
 pub mod packet_handler;
@@ -17,15 +21,19 @@ pub mod universe_connection;
 pub mod world;
 pub use universe_connection::UniverseConnection;
 pub mod ejection;
+pub mod password;
 pub mod player;
 pub mod timestamp;
 
 mod configuration;
+#[cfg(test)]
+mod testing;
 
 use env_logger::Builder;
 pub use log::{debug, error, info, trace, warn};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use std::{io::IsTerminal, path::Path, process};
 
 #[derive(Parser, Debug)]
 struct Args {
@@ -36,6 +44,34 @@ struct Args {
     #[clap(long, default_value = "universe.toml")]
     /// Path to the TOML configuration file for the universe server
     config_file: String,
+
+    #[clap(long)]
+    /// Check the configuration and exit, with a failure status if there are problems
+    check_config: bool,
+
+    #[clap(long)]
+    /// Write the default configuration, with any settings given, to a new configuration file
+    init_config: bool,
+
+    #[clap(flatten)]
+    overrides: configuration::ConfigOverrides,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Copy every universe table from one database to another, replacing its contents
+    Convert {
+        #[clap(long)]
+        /// Configuration file of the universe to copy from
+        from: String,
+
+        #[clap(long)]
+        /// Configuration file of the universe to copy to
+        to: String,
+    },
 }
 
 fn init_logging(level: log::LevelFilter) {
@@ -48,15 +84,102 @@ fn main() {
     let args = Args::parse();
     init_logging(args.log_level);
 
-    match configuration::Config::get_interactive(&args.config_file) {
-        Ok(config) => start_universe(config),
-        Err(err) => log::error!("Could not get universe configuration: {err}"),
+    if let Some(Command::Convert { from, to }) = args.command {
+        convert_database(&from, &to);
+        return;
+    }
+
+    if args.init_config {
+        init_config(&args.config_file, args.overrides);
+        return;
+    }
+
+    // Only ask for settings when someone is there to answer
+    let config_path = Path::new(&args.config_file);
+    if !config_path.exists() && !args.check_config && std::io::stdin().is_terminal() {
+        if let Err(err) = configuration::Config::configure(config_path) {
+            log::error!(
+                "Could not write configuration to {}: {err}",
+                args.config_file
+            );
+            process::exit(1);
+        }
+    }
+
+    let config = match configuration::Config::get(config_path, args.overrides.clone()) {
+        Ok(config) => config,
+        Err(err) => {
+            log::error!("Could not get universe configuration: {err}");
+            process::exit(1);
+        }
+    };
+
+    let problems = config.validate();
+    for problem in &problems {
+        log::error!("Invalid configuration: {problem}");
+    }
+    if !problems.is_empty() {
+        process::exit(1);
     }
+    if args.check_config {
+        log::info!("The configuration is valid.");
+        return;
+    }
+
+    start_universe(config, &args.config_file, args.overrides);
 }
 
-fn start_universe(config: configuration::Config) {
+fn start_universe(
+    config: configuration::Config,
+    config_path: &str,
+    overrides: configuration::ConfigOverrides,
+) {
     match UniverseServer::new(config) {
-        Ok(mut universe) => universe.run(),
+        Ok(mut universe) => {
+            universe.watch_config_file(config_path, overrides);
+            universe.run();
+        }
         Err(err) => log::error!("Could not create universe: {err}"),
     }
 }
+
+fn init_config(config_path: &str, overrides: configuration::ConfigOverrides) {
+    let mut config = configuration::Config::default();
+    if let Err(err) = overrides.apply(&mut config) {
+        log::error!("Could not apply settings: {err}");
+        process::exit(1);
+    }
+
+    match config.create(config_path) {
+        Ok(()) => log::info!("Wrote the default configuration to {config_path}"),
+        Err(err) => {
+            log::error!("Could not write a new configuration to {config_path}: {err}");
+            process::exit(1);
+        }
+    }
+}
+
+fn convert_database(from: &str, to: &str) {
+    let source = match configuration::Config::load(from) {
+        Ok(config) => config,
+        Err(err) => {
+            log::error!("Could not read source configuration {from}: {err}");
+            process::exit(1);
+        }
+    };
+    let destination = match configuration::Config::load(to) {
+        Ok(config) => config,
+        Err(err) => {
+            log::error!("Could not read destination configuration {to}: {err}");
+            process::exit(1);
+        }
+    };
+
+    match database::convert::convert_database(source.sql, destination.sql) {
+        Ok(()) => log::info!("Copied the universe from {from} to {to}"),
+        Err(err) => {
+            log::error!("Could not copy the universe: {err}");
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,306 @@
+//! Metrics about the universe's load, served over HTTP in the Prometheus text exposition format.
+//!
+//! Counters which the main loop keeps are stored in [`Metrics`]. Everything else, such as how many
+//! clients are connected, is read from the server whenever the metrics are scraped. Scrapes are
+//! received on a thread of their own and answered by the main loop, like admin API requests.
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    net::SocketAddr,
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use aw_core::{Direction, PacketType, ProtocolStats, ReasonCode};
+use aw_db::LATENCY_BUCKETS;
+use num_traits::FromPrimitive;
+use tiny_http::{Header, Method, Response, Server};
+
+use crate::{client::ClientInfo, configuration::MetricsConfig, player::Player, UniverseServer};
+
+/// How long a scrape may wait for the main loop to answer it.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Counters which are updated as things happen.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Traffic of every client connection
+    pub protocol: Arc<ProtocolStats>,
+    logins: HashMap<ReasonCode, u64>,
+}
+
+impl Metrics {
+    /// Count a login attempt, which was accepted if `rc` is [`ReasonCode::Success`].
+    pub fn record_login(&mut self, rc: ReasonCode) {
+        *self.logins.entry(rc).or_default() += 1;
+    }
+}
+
+/// HTTP endpoint which metrics are scraped from.
+pub struct MetricsEndpoint {
+    server: Arc<Server>,
+    scrapes: Receiver<Sender<String>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MetricsEndpoint {
+    pub fn start(config: &MetricsConfig) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let server = Arc::new(Server::http(config.bind)?);
+        let (scrapes_tx, scrapes) = channel();
+
+        let thread = {
+            let server = server.clone();
+            thread::spawn(move || serve(&server, &scrapes_tx))
+        };
+
+        Ok(Self {
+            server,
+            scrapes,
+            thread: Some(thread),
+        })
+    }
+
+    /// Address metrics are served on, which tells which port was picked when binding to port 0.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    /// Take the scrapes which arrived since the last call. Each is answered by sending the
+    /// rendered metrics.
+    pub fn pending_scrapes(&self) -> Vec<Sender<String>> {
+        self.scrapes.try_iter().collect()
+    }
+}
+
+impl Drop for MetricsEndpoint {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+fn serve(server: &Server, scrapes: &Sender<Sender<String>>) {
+    for request in server.incoming_requests() {
+        let path = request.url().split('?').next().unwrap_or_default();
+        let response = if *request.method() != Method::Get || path != "/metrics" {
+            Response::from_string("Metrics are at /metrics\n").with_status_code(404)
+        } else {
+            match scrape(scrapes) {
+                Some(text) => Response::from_string(text).with_header(
+                    Header::from_bytes("Content-Type", "text/plain; version=0.0.4")
+                        .expect("Content-Type header should be valid"),
+                ),
+                None => {
+                    Response::from_string("The universe did not answer\n").with_status_code(503)
+                }
+            }
+        };
+
+        if let Err(why) = request.respond(response) {
+            log::debug!("Could not answer metrics request: {why}");
+        }
+    }
+}
+
+fn scrape(scrapes: &Sender<Sender<String>>) -> Option<String> {
+    let (respond, response) = channel();
+    scrapes.send(respond).ok()?;
+    response.recv_timeout(RESPONSE_TIMEOUT).ok()
+}
+
+/// Render every metric of the universe.
+pub fn render(server: &UniverseServer) -> String {
+    let mut out = Exposition::default();
+
+    let mut connections = HashMap::<&str, u64>::new();
+    for (_id, conn) in server.connections.iter() {
+        let kind = match &conn.client {
+            None => "unidentified",
+            Some(ClientInfo::WorldServer(_)) => "world_server",
+            Some(ClientInfo::Player(Player::Citizen(_))) => "citizen",
+            Some(ClientInfo::Player(Player::Tourist(_))) => "tourist",
+            Some(ClientInfo::Player(Player::Bot(_))) => "bot",
+        };
+        *connections.entry(kind).or_default() += 1;
+    }
+    out.header(
+        "aw_universe_connections",
+        "gauge",
+        "Connected clients by kind",
+    );
+    for kind in ["unidentified", "world_server", "citizen", "tourist", "bot"] {
+        let count = connections.get(kind).copied().unwrap_or_default();
+        out.sample("aw_universe_connections", &[("kind", kind)], count);
+    }
+
+    let protocol = &server.metrics.protocol;
+    out.header(
+        "aw_universe_bytes_total",
+        "counter",
+        "Bytes sent and received by client connections, as they are on the wire",
+    );
+    out.sample(
+        "aw_universe_bytes_total",
+        &[("direction", "received")],
+        protocol.bytes_received(),
+    );
+    out.sample(
+        "aw_universe_bytes_total",
+        &[("direction", "sent")],
+        protocol.bytes_sent(),
+    );
+
+    let mut packets = protocol
+        .packets()
+        .into_iter()
+        .map(|(direction, opcode, count)| {
+            let direction = match direction {
+                Direction::Received => "received",
+                Direction::Sent => "sent",
+            };
+            let packet_type = match PacketType::from_i16(opcode) {
+                Some(packet_type) => format!("{packet_type:?}"),
+                None => opcode.to_string(),
+            };
+            (direction, packet_type, count)
+        })
+        .collect::<Vec<_>>();
+    packets.sort();
+    out.header(
+        "aw_universe_packets_total",
+        "counter",
+        "Packets sent and received by client connections, by type",
+    );
+    for (direction, packet_type, count) in &packets {
+        out.sample(
+            "aw_universe_packets_total",
+            &[("direction", direction), ("type", packet_type)],
+            count,
+        );
+    }
+
+    let mut logins = server
+        .metrics
+        .logins
+        .iter()
+        .map(|(rc, count)| (format!("{rc:?}"), count))
+        .collect::<Vec<_>>();
+    logins.sort();
+    out.header(
+        "aw_universe_logins_total",
+        "counter",
+        "Login attempts by the reason code they were answered with, which is Success if accepted",
+    );
+    for (reason, count) in &logins {
+        out.sample("aw_universe_logins_total", &[("reason", reason)], count);
+    }
+
+    let database = server.database.stats();
+    out.header(
+        "aw_universe_database_errors_total",
+        "counter",
+        "Database statements which failed",
+    );
+    out.sample("aw_universe_database_errors_total", &[], database.errors());
+    out.header(
+        "aw_universe_database_statement_seconds",
+        "histogram",
+        "Time taken to execute database statements",
+    );
+    for (bound, count) in LATENCY_BUCKETS.iter().zip(database.cumulative_buckets()) {
+        out.sample(
+            "aw_universe_database_statement_seconds_bucket",
+            &[("le", &bound.to_string())],
+            count,
+        );
+    }
+    out.sample(
+        "aw_universe_database_statement_seconds_bucket",
+        &[("le", "+Inf")],
+        database.statements(),
+    );
+    out.sample(
+        "aw_universe_database_statement_seconds_sum",
+        &[],
+        database.total_time().as_secs_f64(),
+    );
+    out.sample(
+        "aw_universe_database_statement_seconds_count",
+        &[],
+        database.statements(),
+    );
+
+    let worlds = server.connections.get_all_world_entries();
+    out.header("aw_universe_worlds", "gauge", "Worlds which are online");
+    out.sample("aw_universe_worlds", &[], worlds.len());
+    out.header(
+        "aw_universe_world_users",
+        "gauge",
+        "Users in each world, as last reported by its world server",
+    );
+    for world in &worlds {
+        out.sample(
+            "aw_universe_world_users",
+            &[("world", &world.name)],
+            world.user_count,
+        );
+    }
+
+    out.text
+}
+
+/// Text in the Prometheus exposition format.
+#[derive(Default)]
+struct Exposition {
+    text: String,
+}
+
+impl Exposition {
+    fn header(&mut self, name: &str, kind: &str, help: &str) {
+        self.text += &format!("# HELP {name} {help}\n# TYPE {name} {kind}\n");
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) {
+        self.text += name;
+        if !labels.is_empty() {
+            let labels = labels
+                .iter()
+                .map(|(label, value)| format!("{label}=\"{}\"", escape_label(value)))
+                .collect::<Vec<_>>();
+            self.text += &format!("{{{}}}", labels.join(","));
+        }
+        self.text += &format!(" {value}\n");
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_are_formatted() {
+        let mut out = Exposition::default();
+        out.header("aw_test", "gauge", "Something");
+        out.sample("aw_test", &[], 1);
+        out.sample("aw_test", &[("world", "A \"quoted\" \\ name")], 2.5);
+
+        assert_eq!(
+            out.text,
+            "# HELP aw_test Something\n# TYPE aw_test gauge\naw_test 1\n\
+            aw_test{world=\"A \\\"quoted\\\" \\\\ name\"} 2.5\n"
+        );
+    }
+}
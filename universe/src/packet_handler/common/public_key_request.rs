@@ -1,13 +1,26 @@
 use crate::{get_conn_mut, universe_connection::UniverseConnectionID, UniverseServer};
-use aw_core::{AWPacket, PacketType, VarID};
+use aw_core::{AWPacket, PacketType, ProtocolVersion, VarID};
 
 /// Handle a client requesting the server's public RSA key.
 /// We generate a new RSA key pair for each client since AW
 /// versions prior to 7.0 use very weak RSA encryption.
 /// We send the generated key pair to the client.
-pub fn public_key_request(server: &mut UniverseServer, cid: UniverseConnectionID) {
+/// This is the first packet a client sends, so it also decides which protocol version the
+/// client speaks, unless the configuration says which one every client speaks.
+pub fn public_key_request(
+    server: &mut UniverseServer,
+    cid: UniverseConnectionID,
+    packet: &AWPacket,
+) {
+    let protocol = server
+        .config
+        .protocol
+        .unwrap_or_else(|| ProtocolVersion::from_handshake(packet));
     let conn = get_conn_mut!(server, cid, "public_key_request");
 
+    log::debug!("{} is using protocol {protocol:?}", conn.addr().ip());
+    conn.set_protocol(protocol);
+
     let Some(key) = conn.rsa.encode_public_key() else {
         log::warn!("Failed to encode public key for client: {conn:?}");
         return;
@@ -1,28 +1,5 @@
 use crate::{attributes, get_conn_mut, universe_connection::UniverseConnectionID, UniverseServer};
-use aw_core::{AWPacket, VarID};
-
-#[derive(Debug)]
-enum StreamKeyResponseParamsError {
-    EncryptedStreamCipherKey,
-}
-
-struct StreamKeyResponseParams {
-    encrypted_stream_cipher_key: Vec<u8>,
-}
-
-impl TryFrom<&AWPacket> for StreamKeyResponseParams {
-    type Error = StreamKeyResponseParamsError;
-
-    fn try_from(value: &AWPacket) -> Result<Self, Self::Error> {
-        let encrypted_stream_cipher_key = value
-            .get_data(VarID::EncryptionKey)
-            .ok_or(StreamKeyResponseParamsError::EncryptedStreamCipherKey)?;
-
-        Ok(Self {
-            encrypted_stream_cipher_key,
-        })
-    }
-}
+use aw_core::{messages::StreamKeyResponse, AWPacket};
 
 /// Handle a client sending the server its RC4 encryption key.
 /// For all data afterwards, we use this key to decrypt traffic we receive.
@@ -35,7 +12,7 @@ pub fn stream_key_response(
 
     log::trace!("stream_key_response");
 
-    let params = match StreamKeyResponseParams::try_from(packet) {
+    let params = match StreamKeyResponse::try_from(packet) {
         Ok(params) => params,
         Err(why) => {
             log::debug!("Could not complete stream key response: {why:?}");
@@ -44,10 +21,7 @@ pub fn stream_key_response(
     };
 
     // In AW 4 and 5, the stream cipher is RC4, in 6 it is AES
-    let stream_key = match conn
-        .rsa
-        .decrypt_private(&params.encrypted_stream_cipher_key)
-    {
+    let stream_key = match conn.rsa.decrypt_private(&params.encrypted_key) {
         Ok(key) => key,
         Err(why) => {
             log::debug!("Could not decrypt client's stream key: {why:?}");
@@ -1,7 +1,7 @@
 use crate::{
     client::ClientInfo,
     database::{citizen::CitizenQuery, CitizenDB, UniverseDatabase},
-    get_conn,
+    get_conn, password,
     player::Player,
     timestamp::unix_epoch_timestamp_u32,
     universe_connection::UniverseConnectionID,
@@ -44,7 +44,10 @@ fn try_add_citizen(
         .ok_or(ReasonCode::Unauthorized)?;
     let password = packet
         .get_string(VarID::CitizenPassword)
-        .ok_or(ReasonCode::Unauthorized)?;
+        .ok_or(ReasonCode::Unauthorized)
+        .and_then(|password| {
+            password::hash_password(&password).map_err(|_| ReasonCode::UnableToInsertCitizen)
+        })?;
     let email = packet
         .get_string(VarID::CitizenEmail)
         .ok_or(ReasonCode::Unauthorized)?;
@@ -1,6 +1,6 @@
 use crate::{
     database::{citizen::CitizenQuery, CitizenDB, UniverseDatabase},
-    get_conn,
+    get_conn, password,
     universe_connection::UniverseConnectionID,
     UniverseServer,
 };
@@ -146,9 +146,9 @@ fn modify_citizen(
         id: original.id,
         changed: 0,
         name: changed.name.clone(),
-        password: changed.password.clone(),
+        password: changed_password(&original.password, &changed.password, false)?,
         email: changed.email.clone(),
-        priv_pass: changed.priv_pass.clone(),
+        priv_pass: changed_password(&original.priv_pass, &changed.priv_pass, true)?,
         comment: if admin {
             changed.comment.clone()
         } else {
@@ -185,8 +185,32 @@ fn modify_citizen(
         trial: if admin { changed.trial } else { original.trial },
     };
 
-    match database.citizen_change(&cit_query) {
-        DatabaseResult::Ok(_) => Ok(()),
-        DatabaseResult::DatabaseError => Err(ReasonCode::UnableToChangeCitizen),
+    if database.citizen_change(&cit_query).is_err() {
+        return Err(ReasonCode::UnableToChangeCitizen);
     }
+
+    // Only a password other than the one it replaces is what a required change was waiting for
+    let password_replaced = changed.password != password::PASSWORD_PLACEHOLDER
+        && !changed.password.is_empty()
+        && !password::verify_password(&changed.password, &original.password).is_valid();
+    if password_replaced
+        && database
+            .citizen_set_password_change_required(original.id, false)
+            .is_err()
+    {
+        return Err(ReasonCode::UnableToChangeCitizen);
+    }
+
+    Ok(())
+}
+
+/// Clients are sent a placeholder for the passwords which are set, so a password which comes back
+/// as the placeholder was left unchanged. An empty password clears it if `may_clear`, as for the
+/// privilege password, and is left unchanged otherwise.
+fn changed_password(original: &str, changed: &str, may_clear: bool) -> Result<String, ReasonCode> {
+    if changed == password::PASSWORD_PLACEHOLDER || (changed.is_empty() && !may_clear) {
+        return Ok(original.to_string());
+    }
+
+    password::hash_password(changed).map_err(|_| ReasonCode::UnableToChangeCitizen)
 }
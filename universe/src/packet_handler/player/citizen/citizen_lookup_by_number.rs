@@ -1,37 +1,16 @@
 use crate::{
     database::CitizenDB, get_conn, universe_connection::UniverseConnectionID, UniverseServer,
 };
-use aw_core::*;
+use aw_core::{messages::CitizenLookupByNumber, *};
 
 use super::try_citizen_lookup;
 
-struct CitizenLookupByNumberParams {
-    citizen_number: u32,
-}
-
-#[derive(Debug)]
-enum CitizenLookupByNumberParamsError {
-    CitizenNumber,
-}
-
-impl TryFrom<&AWPacket> for CitizenLookupByNumberParams {
-    type Error = CitizenLookupByNumberParamsError;
-
-    fn try_from(value: &AWPacket) -> Result<Self, Self::Error> {
-        let citizen_number = value
-            .get_uint(VarID::CitizenNumber)
-            .ok_or(CitizenLookupByNumberParamsError::CitizenNumber)?;
-
-        Ok(Self { citizen_number })
-    }
-}
-
 pub fn citizen_lookup_by_number(
     server: &UniverseServer,
     cid: UniverseConnectionID,
     packet: &AWPacket,
 ) {
-    let params = match CitizenLookupByNumberParams::try_from(packet) {
+    let params = match CitizenLookupByNumber::try_from(packet) {
         Ok(params) => params,
         Err(why) => {
             log::debug!("Could not complete citizen lookup by number: {why:?}");
@@ -1,33 +1,12 @@
 use crate::{
     database::CitizenDB, get_conn, universe_connection::UniverseConnectionID, UniverseServer,
 };
-use aw_core::*;
+use aw_core::{messages::CitizenPrev, *};
 
 use super::try_citizen_lookup;
 
-struct CitizenPrevParams {
-    citizen_id: u32,
-}
-
-#[derive(Debug)]
-enum CitizenPrevParamsError {
-    CitizenID,
-}
-
-impl TryFrom<&AWPacket> for CitizenPrevParams {
-    type Error = CitizenPrevParamsError;
-
-    fn try_from(value: &AWPacket) -> Result<Self, Self::Error> {
-        let citizen_id = value
-            .get_uint(VarID::CitizenNumber)
-            .ok_or(CitizenPrevParamsError::CitizenID)?;
-
-        Ok(Self { citizen_id })
-    }
-}
-
 pub fn citizen_prev(server: &UniverseServer, cid: UniverseConnectionID, packet: &AWPacket) {
-    let params = match CitizenPrevParams::try_from(packet) {
+    let params = match CitizenPrev::try_from(packet) {
         Ok(params) => params,
         Err(why) => {
             log::debug!("Could not complete citizen prev: {why:?}");
@@ -41,7 +20,7 @@ pub fn citizen_prev(server: &UniverseServer, cid: UniverseConnectionID, packet:
     let rc = try_citizen_lookup(conn, &mut response, || {
         server
             .database
-            .citizen_by_number(params.citizen_id.saturating_sub(1))
+            .citizen_by_number(params.citizen_number.saturating_sub(1))
     });
     response.add_int(VarID::ReasonCode, rc.into());
 
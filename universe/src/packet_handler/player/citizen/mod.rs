@@ -19,7 +19,7 @@ pub use citizen_add::citizen_add;
 mod citizen_delete;
 pub use citizen_delete::citizen_delete;
 
-use crate::{database::citizen::CitizenQuery, UniverseConnection};
+use crate::{database::citizen::CitizenQuery, password, UniverseConnection};
 use aw_core::*;
 use aw_db::DatabaseResult;
 
@@ -81,9 +81,17 @@ fn citizen_info_vars(
             AWPacketVar::byte(VarID::BetaUser, citizen.beta as u8),
             AWPacketVar::byte(VarID::CitizenEnabled, citizen.enabled as u8),
             AWPacketVar::uint(VarID::CitizenPrivacy, citizen.privacy),
-            AWPacketVar::string(VarID::CitizenPassword, citizen.password.clone()),
+            // Passwords are only stored as hashes, so the client is sent a placeholder for those
+            // which are set. citizen_change keeps a password which comes back as the placeholder.
+            AWPacketVar::string(
+                VarID::CitizenPassword,
+                password::placeholder(&citizen.password),
+            ),
             AWPacketVar::string(VarID::CitizenEmail, citizen.email.clone()),
-            AWPacketVar::string(VarID::CitizenPrivilegePassword, citizen.priv_pass.clone()),
+            AWPacketVar::string(
+                VarID::CitizenPrivilegePassword,
+                password::placeholder(&citizen.priv_pass),
+            ),
             AWPacketVar::uint(VarID::CitizenImmigration, citizen.immigration),
         ]);
     }
@@ -7,42 +7,11 @@ use crate::{
     universe_connection::UniverseConnectionID,
     UniverseConnection, UniverseServer,
 };
-use aw_core::*;
+use aw_core::{messages::ContactAdd, *};
 use aw_db::DatabaseResult;
 
-#[derive(Debug)]
-enum ContactAddParamsError {
-    ContactName,
-    ContactOptions,
-}
-
-struct ContactAddParams {
-    contact_name: String,
-    contact_options: ContactOptions,
-}
-
-impl TryFrom<&AWPacket> for ContactAddParams {
-    type Error = ContactAddParamsError;
-
-    fn try_from(value: &AWPacket) -> Result<Self, Self::Error> {
-        let contact_name = value
-            .get_string(VarID::ContactListName)
-            .ok_or(ContactAddParamsError::ContactName)?;
-
-        let contact_options = value
-            .get_uint(VarID::ContactListOptions)
-            .map(ContactOptions::from_bits_truncate)
-            .ok_or(ContactAddParamsError::ContactOptions)?;
-
-        Ok(Self {
-            contact_name,
-            contact_options,
-        })
-    }
-}
-
 pub fn contact_add(server: &mut UniverseServer, cid: UniverseConnectionID, packet: &AWPacket) {
-    let params = match ContactAddParams::try_from(packet) {
+    let params = match ContactAdd::try_from(packet) {
         Ok(params) => params,
         Err(why) => {
             log::debug!("Could not complete contact add: {why:?}");
@@ -104,7 +73,7 @@ fn alert_friend_request(from: u32, to: u32, server: &UniverseServer) {
 
 fn try_add_contact(
     conn: &UniverseConnection,
-    params: ContactAddParams,
+    params: ContactAdd,
     database: &UniverseDatabase,
 ) -> Result<(u32, u32), ReasonCode> {
     // Must be a player logged in as a citizen
@@ -112,8 +81,9 @@ fn try_add_contact(
     let citizen = client.citizen().ok_or(ReasonCode::NotLoggedIn)?;
 
     let citizen_id = citizen.cit_id;
+    let contact_options = ContactOptions::from_bits_truncate(params.options);
 
-    let contact_citizen = match database.citizen_by_name(&params.contact_name) {
+    let contact_citizen = match database.citizen_by_name(&params.name) {
         DatabaseResult::Ok(Some(cit)) => cit,
         DatabaseResult::Ok(None) => return Err(ReasonCode::NoSuchCitizen),
         DatabaseResult::DatabaseError => return Err(ReasonCode::DatabaseError),
@@ -124,7 +94,7 @@ fn try_add_contact(
         DatabaseResult::DatabaseError => return Err(ReasonCode::DatabaseError),
     };
 
-    if other_has_blocked_you && !params.contact_options.contains(ContactOptions::ALL_BLOCKED) {
+    if other_has_blocked_you && !contact_options.contains(ContactOptions::ALL_BLOCKED) {
         return Err(ReasonCode::ContactAddBlocked);
     }
 
@@ -146,7 +116,7 @@ fn try_add_contact(
         return Err(ReasonCode::UnableToSetContact);
     }
 
-    let mut options = params.contact_options;
+    let mut options = contact_options;
     options.remove(ContactOptions::FRIEND_REQUEST_ALLOWED);
     options.insert(ContactOptions::FRIEND_REQUEST_BLOCKED);
 
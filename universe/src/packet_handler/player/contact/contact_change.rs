@@ -6,42 +6,11 @@ use crate::{
     universe_connection::UniverseConnectionID,
     UniverseServer,
 };
-use aw_core::*;
+use aw_core::{messages::ContactChange, *};
 use aw_db::DatabaseResult;
 
-#[derive(Debug)]
-enum ContactChangeParamsError {
-    ContactCitizenID,
-    OptionChanges,
-}
-
-struct ContactChangeParams {
-    contact_citizen_id: u32,
-    option_changes: ContactOptions,
-}
-
-impl TryFrom<&AWPacket> for ContactChangeParams {
-    type Error = ContactChangeParamsError;
-
-    fn try_from(value: &AWPacket) -> Result<Self, Self::Error> {
-        let contact_citizen_id = value
-            .get_uint(VarID::ContactListCitizenID)
-            .ok_or(ContactChangeParamsError::ContactCitizenID)?;
-
-        let option_changes = value
-            .get_uint(VarID::ContactListOptions)
-            .and_then(ContactOptions::from_bits)
-            .ok_or(ContactChangeParamsError::OptionChanges)?;
-
-        Ok(Self {
-            contact_citizen_id,
-            option_changes,
-        })
-    }
-}
-
 pub fn contact_change(server: &mut UniverseServer, cid: UniverseConnectionID, packet: &AWPacket) {
-    let params = match ContactChangeParams::try_from(packet) {
+    let params = match ContactChange::try_from(packet) {
         Ok(params) => params,
         Err(why) => {
             log::debug!("Could not complete contact change: {why:?}");
@@ -49,6 +18,14 @@ pub fn contact_change(server: &mut UniverseServer, cid: UniverseConnectionID, pa
         }
     };
 
+    let Some(option_changes) = ContactOptions::from_bits(params.options) else {
+        log::debug!(
+            "Could not complete contact change: Invalid options {}",
+            params.options
+        );
+        return;
+    };
+
     let Some(self_citizen_id) = get_conn!(server, cid, "contact_change")
         .client
         .as_ref()
@@ -59,11 +36,11 @@ pub fn contact_change(server: &mut UniverseServer, cid: UniverseConnectionID, pa
 
     let original_options = match server
         .database
-        .contact_get(self_citizen_id, params.contact_citizen_id)
+        .contact_get(self_citizen_id, params.citizen_number)
     {
         DatabaseResult::Ok(Some(q)) => q.options,
         // The user may not have an entry for a contact with 0 yet
-        DatabaseResult::Ok(None) if params.contact_citizen_id == 0 => ContactOptions::empty(),
+        DatabaseResult::Ok(None) if params.citizen_number == 0 => ContactOptions::empty(),
         DatabaseResult::Ok(None) => return,
         DatabaseResult::DatabaseError => {
             log::error!("Could not complete contact_change due to database error.");
@@ -71,13 +48,12 @@ pub fn contact_change(server: &mut UniverseServer, cid: UniverseConnectionID, pa
         }
     };
 
-    let new_options = original_options.apply_changes(params.option_changes);
+    let new_options = original_options.apply_changes(option_changes);
 
-    match server.database.contact_set(
-        self_citizen_id,
-        params.contact_citizen_id,
-        new_options.bits(),
-    ) {
+    match server
+        .database
+        .contact_set(self_citizen_id, params.citizen_number, new_options.bits())
+    {
         DatabaseResult::Ok(_) => {}
         DatabaseResult::DatabaseError => {
             log::error!("Could not complete contact_change due to database error.");
@@ -85,10 +61,10 @@ pub fn contact_change(server: &mut UniverseServer, cid: UniverseConnectionID, pa
         }
     }
 
-    if params.option_changes.contains(ContactOptions::ALL_BLOCKED) {
+    if option_changes.contains(ContactOptions::ALL_BLOCKED) {
         match server
             .database
-            .contact_delete(params.contact_citizen_id, self_citizen_id)
+            .contact_delete(params.citizen_number, self_citizen_id)
         {
             DatabaseResult::Ok(_) => {}
             DatabaseResult::DatabaseError => {
@@ -58,8 +58,9 @@ fn try_contact_confirm(
     }
 
     // Fail if could not set the contacts
-    if database.contact_set(citizen_id, contact_id, 0).is_err()
-        || database.contact_set(contact_id, citizen_id, 0).is_err()
+    if database
+        .contact_set_mutual(citizen_id, contact_id, 0)
+        .is_err()
     {
         return Err(ReasonCode::UnableToSetContact);
     }
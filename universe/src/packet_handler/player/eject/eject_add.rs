@@ -1,44 +1,16 @@
-use aw_core::{AWPacket, PacketType, ReasonCode, VarID};
+use aw_core::{messages::EjectAdd, AWPacket, PacketType, ReasonCode, VarID};
 
 use crate::{
-    database::EjectDB, ejection::is_connection_ejected, get_conn,
-    timestamp::unix_epoch_timestamp_u32, universe_connection::UniverseConnectionID, UniverseServer,
+    client::ClientInfo,
+    database::{eject::EjectionQuery, EjectDB},
+    ejection::{disconnect_ejected_connections, legacy_targets},
+    get_conn,
+    timestamp::unix_epoch_timestamp_u32,
+    universe_connection::UniverseConnectionID,
+    UniverseServer,
 };
 
-struct EjectAddParams {
-    address: u32,
-    expiration: u32,
-    comment: String,
-}
-
-#[derive(Debug)]
-enum EjectAddParamsError {
-    Address,
-    Expiration,
-    Comment,
-}
-
-impl TryFrom<&AWPacket> for EjectAddParams {
-    type Error = EjectAddParamsError;
-
-    fn try_from(value: &AWPacket) -> Result<Self, Self::Error> {
-        let address = value
-            .get_uint(VarID::EjectionAddress)
-            .ok_or(EjectAddParamsError::Address)?;
-        let expiration = value
-            .get_uint(VarID::EjectionExpiration)
-            .ok_or(EjectAddParamsError::Expiration)?;
-        let comment = value
-            .get_string(VarID::EjectionComment)
-            .ok_or(EjectAddParamsError::Comment)?;
-
-        Ok(Self {
-            address,
-            expiration,
-            comment,
-        })
-    }
-}
+use super::address_from_client;
 
 pub fn eject_add(server: &mut UniverseServer, cid: UniverseConnectionID, packet: &AWPacket) {
     let conn = get_conn!(server, cid, "eject_add");
@@ -47,7 +19,7 @@ pub fn eject_add(server: &mut UniverseServer, cid: UniverseConnectionID, packet:
         return;
     }
 
-    let params = match EjectAddParams::try_from(packet) {
+    let params = match EjectAdd::try_from(packet) {
         Ok(params) => params,
         Err(why) => {
             log::debug!("Could not complete eject add: {why:?}");
@@ -55,27 +27,24 @@ pub fn eject_add(server: &mut UniverseServer, cid: UniverseConnectionID, packet:
         }
     };
 
+    let admin = conn
+        .client
+        .as_ref()
+        .and_then(ClientInfo::citizen_id)
+        .unwrap_or(0);
     let creation = unix_epoch_timestamp_u32();
 
-    let rc = match server.database.ejection_set(
-        params.address,
-        params.expiration,
-        creation,
-        &params.comment,
-    ) {
-        aw_db::DatabaseResult::Ok(_) => {
-            // Remove the ejected connection if it is present.
-            for (_id, conn) in server.connections.iter_mut() {
-                match is_connection_ejected(&server.database, conn) {
-                    Some(true) => conn.disconnect(),
-                    Some(false) => log::trace!("New ejection is not logged in"),
-                    None => log::debug!("Failed to check if client is ejected"),
-                }
-            }
-            ReasonCode::Success
+    let mut rc = ReasonCode::Success;
+    for target in legacy_targets(address_from_client(params.address), &params.comment) {
+        let ejection =
+            EjectionQuery::new(target, &params.comment, admin, creation, params.expiration);
+        if server.database.ejection_set(&ejection).is_err() {
+            rc = ReasonCode::DatabaseError;
         }
-        aw_db::DatabaseResult::DatabaseError => ReasonCode::DatabaseError,
-    };
+    }
+
+    // Remove the ejected connection if it is present.
+    disconnect_ejected_connections(server);
 
     let mut response = AWPacket::new(PacketType::EjectResult);
     response.add_uint(VarID::ReasonCode, rc.into());
@@ -2,9 +2,12 @@ use aw_core::{AWPacket, PacketType, ReasonCode, VarID};
 use aw_db::DatabaseResult;
 
 use crate::{
-    database::EjectDB, get_conn, universe_connection::UniverseConnectionID, UniverseServer,
+    database::EjectDB, ejection::EjectionTarget, get_conn,
+    universe_connection::UniverseConnectionID, UniverseServer,
 };
 
+use super::address_from_client;
+
 pub fn eject_delete(server: &UniverseServer, cid: UniverseConnectionID, packet: &AWPacket) {
     let conn = get_conn!(server, cid, "eject_delete");
 
@@ -19,8 +22,13 @@ pub fn eject_delete(server: &UniverseServer, cid: UniverseConnectionID, packet:
 
     let mut response = AWPacket::new(PacketType::EjectResult);
 
-    let rc = match server.database.ejection_delete(address) {
-        DatabaseResult::Ok(()) => ReasonCode::Success,
+    let target = EjectionTarget::Address(address_from_client(address).into());
+    let rc = match server.database.ejection_by_target(&target) {
+        DatabaseResult::Ok(Some(ejection)) => match server.database.ejection_delete(ejection.id) {
+            DatabaseResult::Ok(()) => ReasonCode::Success,
+            DatabaseResult::DatabaseError => ReasonCode::DatabaseError,
+        },
+        DatabaseResult::Ok(None) => ReasonCode::NoSuchEjection,
         DatabaseResult::DatabaseError => ReasonCode::DatabaseError,
     };
 
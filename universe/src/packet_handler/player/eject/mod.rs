@@ -15,8 +15,11 @@ pub use eject_prev::eject_prev;
 mod eject_delete;
 pub use eject_delete::eject_delete;
 
+use std::net::{IpAddr, Ipv4Addr};
+
 use crate::{
-    database::EjectDB, get_conn, universe_connection::UniverseConnectionID, UniverseServer,
+    database::EjectDB, ejection::EjectionTarget, get_conn,
+    universe_connection::UniverseConnectionID, UniverseServer,
 };
 
 enum EjectionLookupMethod {
@@ -40,18 +43,26 @@ fn eject_lookup_by_method(
 
     let mut response = AWPacket::new(PacketType::EjectionInfo);
 
+    // Clients only know of ejections of IPv4 addresses
+    let address = address_from_client(address);
     let db_result = match method {
-        EjectionLookupMethod::Previous => server.database.ejection_prev(address),
-        EjectionLookupMethod::Exact => server.database.ejection_lookup(address),
-        EjectionLookupMethod::Next => server.database.ejection_next(address),
+        EjectionLookupMethod::Previous => server.database.ejection_prev_ipv4(address),
+        EjectionLookupMethod::Exact => server
+            .database
+            .ejection_by_target(&EjectionTarget::Address(address.into())),
+        EjectionLookupMethod::Next => server.database.ejection_next_ipv4(address),
     };
 
     let rc = match db_result {
         DatabaseResult::Ok(Some(ejection)) => {
-            response.add_uint(VarID::EjectionAddress, ejection.address);
+            let Some(EjectionTarget::Address(IpAddr::V4(address))) = ejection.target() else {
+                log::warn!("Ejection {} is not of an IPv4 address", ejection.id);
+                return;
+            };
+            response.add_uint(VarID::EjectionAddress, address_to_client(address));
             response.add_uint(VarID::EjectionExpiration, ejection.expiration);
             response.add_uint(VarID::EjectionCreation, ejection.creation);
-            response.add_string(VarID::EjectionComment, ejection.comment);
+            response.add_string(VarID::EjectionComment, ejection.reason);
 
             ReasonCode::Success
         }
@@ -62,3 +73,12 @@ fn eject_lookup_by_method(
     response.add_uint(VarID::ReasonCode, rc.into());
     conn.send(response);
 }
+
+/// Clients send addresses with their octets in little-endian order.
+fn address_from_client(address: u32) -> Ipv4Addr {
+    Ipv4Addr::from(address.to_le_bytes())
+}
+
+fn address_to_client(address: Ipv4Addr) -> u32 {
+    u32::from_le_bytes(address.octets())
+}
@@ -1,57 +1,21 @@
 use super::check_valid_name;
 use crate::{
     database::{citizen::CitizenQuery, CitizenDB},
-    get_conn,
+    get_conn, password,
     timestamp::unix_epoch_timestamp_u32,
     universe_connection::UniverseConnectionID,
     UniverseServer,
 };
-use aw_core::{AWPacket, PacketType, ReasonCode, VarID};
+use aw_core::{messages::Immigrate, AWPacket, PacketType, ReasonCode, VarID};
 use aw_db::DatabaseResult;
 
-#[derive(Debug)]
-struct ImmigrateParams {
-    name: String,
-    password: String,
-    email: String,
-}
-
-#[derive(Debug)]
-enum ImmigrateParamsError {
-    Name,
-    Password,
-    Email,
-}
-
-impl TryFrom<&AWPacket> for ImmigrateParams {
-    type Error = ImmigrateParamsError;
-
-    fn try_from(value: &AWPacket) -> Result<Self, Self::Error> {
-        let name = value
-            .get_string(VarID::CitizenName)
-            .ok_or(ImmigrateParamsError::Name)?;
-        let password = value
-            .get_string(VarID::CitizenPassword)
-            .ok_or(ImmigrateParamsError::Password)?;
-        let email = value
-            .get_string(VarID::CitizenEmail)
-            .ok_or(ImmigrateParamsError::Email)?;
-
-        Ok(Self {
-            name,
-            password,
-            email,
-        })
-    }
-}
-
 pub fn immigrate(server: &UniverseServer, cid: UniverseConnectionID, packet: &AWPacket) {
     let conn = get_conn!(server, cid, "immigrate");
     let mut response = AWPacket::new(PacketType::ImmigrateResponse);
 
     log::trace!("immigrate");
 
-    let params = match ImmigrateParams::try_from(packet) {
+    let params = match Immigrate::try_from(packet) {
         Ok(params) => params,
         Err(why) => {
             log::debug!("Could not complete immigrate: {why:?}");
@@ -69,7 +33,7 @@ pub fn immigrate(server: &UniverseServer, cid: UniverseConnectionID, packet: &AW
     conn.send(response);
 }
 
-fn try_immigrate(server: &UniverseServer, params: ImmigrateParams) -> Result<(), ReasonCode> {
+fn try_immigrate(server: &UniverseServer, params: Immigrate) -> Result<(), ReasonCode> {
     if !server.config.allow_immigration {
         return Err(ReasonCode::ImmigrationNotAllowed);
     }
@@ -85,12 +49,14 @@ fn try_immigrate(server: &UniverseServer, params: ImmigrateParams) -> Result<(),
     }
 
     let now = unix_epoch_timestamp_u32();
+    let password_hash =
+        password::hash_password(&params.password).map_err(|_| ReasonCode::DatabaseError)?;
 
     let r = server.database.citizen_add_next(CitizenQuery {
         id: 0,
         changed: 0,
         name: params.name,
-        password: params.password,
+        password: password_hash,
         email: params.email,
         priv_pass: String::new(),
         comment: String::new(),
@@ -1,10 +1,11 @@
 use aw_core::{AWPacket, PacketType, ReasonCode, VarID};
 
 use crate::{
-    database::LicenseDB, get_conn, universe_connection::UniverseConnectionID, UniverseServer,
+    database::LicenseDB, get_conn, license_sweep, universe_connection::UniverseConnectionID,
+    UniverseServer,
 };
 
-pub fn license_delete(server: &UniverseServer, cid: UniverseConnectionID, packet: &AWPacket) {
+pub fn license_delete(server: &mut UniverseServer, cid: UniverseConnectionID, packet: &AWPacket) {
     let conn = get_conn!(server, cid, "license_delete");
 
     if !conn.has_admin_permissions() {
@@ -27,4 +28,9 @@ pub fn license_delete(server: &UniverseServer, cid: UniverseConnectionID, packet
 
     response.add_uint(VarID::ReasonCode, rc.into());
     conn.send(response);
+
+    if rc == ReasonCode::Success {
+        // Stop the world which was running under the license
+        license_sweep::sweep_licenses(server);
+    }
 }
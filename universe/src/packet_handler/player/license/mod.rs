@@ -15,7 +15,7 @@ pub use license_delete::license_delete;
 
 use crate::{
     database::{license::LicenseQuery, LicenseDB, UniverseDatabase},
-    get_conn,
+    get_conn, license_sweep,
     universe_connection::UniverseConnectionID,
     UniverseConnection, UniverseServer,
 };
@@ -77,7 +77,7 @@ fn send_license_lookup(
     conn.send(p);
 }
 
-pub fn license_change(server: &UniverseServer, cid: UniverseConnectionID, packet: &AWPacket) {
+pub fn license_change(server: &mut UniverseServer, cid: UniverseConnectionID, packet: &AWPacket) {
     let mut p = AWPacket::new(PacketType::LicenseResult);
     let conn = get_conn!(server, cid, "license_change");
 
@@ -156,9 +156,11 @@ pub fn license_change(server: &UniverseServer, cid: UniverseConnectionID, packet
         }
     }
 
-    // TODO: Kill existing world if it is now invalid/expired
     p.add_int(VarID::ReasonCode, ReasonCode::Success.into());
     conn.send(p);
+
+    // Stop the world if it no longer matches its license
+    license_sweep::sweep_licenses(server);
 }
 
 fn license_to_vars(lic: &LicenseQuery, admin: bool) -> Vec<AWPacketVar> {
@@ -188,7 +190,7 @@ fn license_to_vars(lic: &LicenseQuery, admin: bool) -> Vec<AWPacketVar> {
     result
 }
 
-fn check_valid_world_name(name: &str) -> Result<(), ReasonCode> {
+pub fn check_valid_world_name(name: &str) -> Result<(), ReasonCode> {
     if name.len() < 2 {
         return Err(ReasonCode::NameTooShort);
     }
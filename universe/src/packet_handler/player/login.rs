@@ -1,4 +1,4 @@
-use std::net::IpAddr;
+use std::{net::IpAddr, time::Instant};
 
 use super::check_valid_name;
 use crate::{
@@ -6,6 +6,7 @@ use crate::{
     database::{citizen::CitizenQuery, CitizenDB, UniverseDatabase},
     ejection::is_connection_ejected,
     get_conn_mut,
+    password::{self, PasswordCheck},
     player::{Bot, Citizen, GenericPlayer, Player},
     tabs::{regenerate_contact_list_and_mutuals, regenerate_player_list, regenerate_world_list},
     telegram::send_telegram_update_available,
@@ -13,7 +14,7 @@ use crate::{
     universe_connection::UniverseConnectionID,
     UniverseServer,
 };
-use aw_core::{AWPacket, PacketType, ReasonCode, VarID};
+use aw_core::{AWPacket, PacketType, ProtocolVersion, ReasonCode, VarID};
 use aw_db::DatabaseResult;
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
@@ -72,6 +73,7 @@ pub fn login(server: &mut UniverseServer, cid: UniverseConnectionID, packet: &AW
                 "Preventing {:?} from logging in because they are ejected",
                 conn.addr().ip()
             );
+            server.metrics.record_login(ReasonCode::Ejected);
             return;
         }
         Some(false) => {}
@@ -80,6 +82,7 @@ pub fn login(server: &mut UniverseServer, cid: UniverseConnectionID, packet: &AW
 
     response.add_int(VarID::ReasonCode, rc as i32);
     conn.send(response);
+    server.metrics.record_login(rc);
 
     for cid in server.connections.cids() {
         regenerate_player_list(server, cid)
@@ -108,6 +111,7 @@ fn validate_login(
     };
 
     let ip = conn.addr().ip();
+    let protocol = conn.protocol;
     let login_type: LoginType = {
         let login_type_num = packet
             .get_int(VarID::UserType)
@@ -120,7 +124,7 @@ fn validate_login(
             // A world server can't log in!
             Err(ReasonCode::NoSuchCitizen)
         }
-        LoginType::UnspecifiedHuman => validate_human(server, cid, ip, packet, response),
+        LoginType::UnspecifiedHuman => validate_human(server, cid, ip, protocol, packet, response),
         LoginType::Bot => validate_bot(server, ip, packet, response),
     }
 }
@@ -129,6 +133,7 @@ fn validate_human(
     server: &mut UniverseServer,
     cid: UniverseConnectionID,
     ip: IpAddr,
+    protocol: ProtocolVersion,
     packet: &AWPacket,
     response: &mut AWPacket,
 ) -> Result<Player, ReasonCode> {
@@ -164,25 +169,24 @@ fn validate_human(
             tabs: Default::default(),
         }))
     } else {
-        #[cfg(feature = "protocol_v4")]
-        let cit = check_citizen_v4(
-            server,
-            cid,
-            &username,
-            packet.get_string(VarID::Password).as_ref(), // V4 only
-            privilege_id,
-            privilege_password.as_ref(),
-        )?;
-
-        #[cfg(feature = "protocol_v6")]
-        let cit = check_citizen_v6(
-            server,
-            cid,
-            &username,
-            packet.get_data(17).as_ref(), // V6 only
-            privilege_id,
-            privilege_password.as_ref(),
-        )?;
+        let cit = match protocol {
+            ProtocolVersion::V4 => check_citizen_v4(
+                server,
+                cid,
+                &username,
+                packet.get_string(VarID::Password).as_ref(),
+                privilege_id,
+                privilege_password.as_ref(),
+            )?,
+            ProtocolVersion::V6 => check_citizen_v6(
+                server,
+                cid,
+                &username,
+                packet.get_data(VarID::PasswordDigest).as_ref(),
+                privilege_id,
+                privilege_password.as_ref(),
+            )?,
+        };
 
         // Add packet variables with citizen info
         response.add_uint(VarID::BetaUser, cit.beta);
@@ -195,6 +199,7 @@ fn validate_human(
 
         Ok(Player::Citizen(Citizen {
             cit_id: cit.id,
+            logged_in_at: Instant::now(),
             base_player: GenericPlayer {
                 build: browser_build,
                 session_id: server.connections.create_session_id(),
@@ -263,9 +268,12 @@ fn validate_bot(
         DatabaseResult::DatabaseError => return Err(ReasonCode::DatabaseError),
     };
 
-    if privilege_password != cit_query.priv_pass {
-        return Err(ReasonCode::InvalidPassword);
+    match password::verify_password(&privilege_password, &cit_query.priv_pass) {
+        PasswordCheck::Valid => {}
+        PasswordCheck::ValidLegacy => upgrade_legacy_passwords(&server.database, login_id),
+        PasswordCheck::Invalid => return Err(ReasonCode::InvalidPassword),
     }
+    check_password_change_not_required(server, login_id)?;
 
     let bots_max = cit_query.bot_limit;
     let bots_owned = server
@@ -315,7 +323,6 @@ fn validate_bot(
     }))
 }
 
-#[cfg(feature = "protocol_v4")]
 fn check_citizen_v4(
     server: &UniverseServer,
     cid: UniverseConnectionID,
@@ -335,7 +342,9 @@ fn check_citizen_v4(
     };
 
     // Is login password correct?
-    check_password(password, &database_citizen)?;
+    if check_password(password, &database_citizen)? == PasswordCheck::ValidLegacy {
+        upgrade_legacy_passwords(&server.database, database_citizen.id);
+    }
 
     check_citizen_enabled(&database_citizen)?;
     check_citizen_already_logged_in(server, &database_citizen, cid)?;
@@ -343,7 +352,6 @@ fn check_citizen_v4(
     Ok(database_citizen)
 }
 
-#[cfg(feature = "protocol_v6")]
 fn check_citizen_v6(
     server: &UniverseServer,
     cid: UniverseConnectionID,
@@ -356,13 +364,16 @@ fn check_citizen_v6(
     check_citizen_privilege(server, priv_id, priv_pass)?;
 
     // Get login citizen
-    let database_citizen = server
-        .database
-        .citizen_by_name(username)
-        .or(Err(ReasonCode::NoSuchCitizen))?;
+    let database_citizen = match server.database.citizen_by_name(username) {
+        DatabaseResult::Ok(Some(cit)) => cit,
+        DatabaseResult::Ok(None) => return Err(ReasonCode::NoSuchCitizen),
+        DatabaseResult::DatabaseError => return Err(ReasonCode::DatabaseError),
+    };
 
     // Is login password correct?
-    check_password_hash(&database_citizen, password_hash)?;
+    if check_password_hash(&database_citizen, password_hash)? == PasswordCheck::ValidLegacy {
+        upgrade_legacy_passwords(&server.database, database_citizen.id);
+    }
 
     check_citizen_enabled(&database_citizen)?;
     check_citizen_already_logged_in(server, &database_citizen, cid)?;
@@ -405,65 +416,102 @@ fn check_citizen_privilege(
 
         // Is the priv pass present and correct?
         let priv_pass = priv_pass.ok_or(ReasonCode::ActingPasswordInvalid)?;
-        if *priv_pass != priv_citizen.priv_pass {
-            return Err(ReasonCode::ActingPasswordInvalid);
+        match password::verify_password(priv_pass, &priv_citizen.priv_pass) {
+            PasswordCheck::Valid => {}
+            PasswordCheck::ValidLegacy => upgrade_legacy_passwords(&server.database, priv_id),
+            PasswordCheck::Invalid => return Err(ReasonCode::ActingPasswordInvalid),
         }
+        check_password_change_not_required(server, priv_id)?;
     }
 
     Ok(())
 }
 
-#[cfg(feature = "protocol_v4")]
+/// A citizen who has to choose a new password may only log in from a browser to change it, so
+/// they can't own bots or be acted as until they have.
+fn check_password_change_not_required(
+    server: &UniverseServer,
+    cit_id: u32,
+) -> Result<(), ReasonCode> {
+    match server.database.citizen_password_change_required(cit_id) {
+        DatabaseResult::Ok(false) => Ok(()),
+        DatabaseResult::Ok(true) => {
+            log::info!(
+                "Refused a login using citizen {cit_id}, who must change their password first"
+            );
+            Err(ReasonCode::Unauthorized)
+        }
+        DatabaseResult::DatabaseError => Err(ReasonCode::DatabaseError),
+    }
+}
+
 fn check_password(
     password: Option<&String>,
     database_citizen: &CitizenQuery,
-) -> Result<(), ReasonCode> {
+) -> Result<PasswordCheck, ReasonCode> {
     let password = password.ok_or(ReasonCode::InvalidPassword)?;
     if password.is_empty() {
         return Err(ReasonCode::InvalidPassword);
     }
-    if database_citizen.password != *password {
-        return Err(ReasonCode::InvalidPassword);
-    }
 
-    Ok(())
+    match password::verify_password(password, &database_citizen.password) {
+        PasswordCheck::Invalid => Err(ReasonCode::InvalidPassword),
+        check => Ok(check),
+    }
 }
 
-#[cfg(feature = "protocol_v6")]
 fn check_password_hash(
     database_citizen: &CitizenQuery,
     password_hash: Option<&Vec<u8>>,
-) -> Result<(), ReasonCode> {
-    use byteorder::{LittleEndian, WriteBytesExt};
-    use std::io::Write;
-
-    let mut correct_password_buf = Vec::<u8>::new();
-    correct_password_buf
-        .write_u32::<LittleEndian>(database_citizen.password.len() as u32)
-        .map_err(|_| ReasonCode::InvalidPassword)?;
-    correct_password_buf
-        .write_all(
-            &database_citizen
-                .password
-                .as_bytes()
-                .iter()
-                .rev()
-                .map(|x| *x)
-                .collect::<Vec<u8>>(),
-        )
-        .map_err(|_| ReasonCode::InvalidPassword)?;
-
-    let hashed_correct_password = md5::compute(correct_password_buf.to_vec());
-
+) -> Result<PasswordCheck, ReasonCode> {
     let Some(password_hash) = password_hash else {
         return Err(ReasonCode::InvalidPassword);
     };
 
-    if *password_hash != hashed_correct_password.to_vec() {
-        return Err(ReasonCode::InvalidPassword);
+    match password::verify_digest(password_hash, &database_citizen.password) {
+        PasswordCheck::Invalid => Err(ReasonCode::InvalidPassword),
+        check => Ok(check),
     }
+}
 
-    Ok(())
+/// Rewrites any plaintext passwords left on a citizen from before passwords were hashed.
+/// This is only called once one of them has been verified, and failure is not fatal to the login.
+fn upgrade_legacy_passwords(database: &UniverseDatabase, cit_id: u32) {
+    let mut cit_query = match database.citizen_by_number(cit_id) {
+        DatabaseResult::Ok(Some(q)) => q,
+        DatabaseResult::Ok(None) => return,
+        DatabaseResult::DatabaseError => {
+            log::debug!("Can't upgrade passwords of citizen {cit_id} due to database failure");
+            return;
+        }
+    };
+
+    if !password::needs_upgrade(&cit_query.password)
+        && !password::needs_upgrade(&cit_query.priv_pass)
+    {
+        return;
+    }
+
+    for stored in [&mut cit_query.password, &mut cit_query.priv_pass] {
+        if !password::needs_upgrade(stored) {
+            continue;
+        }
+
+        match password::hash_password(stored) {
+            Ok(hash) => *stored = hash,
+            Err(why) => {
+                log::error!("Can't upgrade passwords of citizen {cit_id}: {why}");
+                return;
+            }
+        }
+    }
+
+    match database.citizen_change(&cit_query) {
+        DatabaseResult::Ok(()) => log::info!("Upgraded legacy passwords of citizen {cit_id}"),
+        DatabaseResult::DatabaseError => {
+            log::debug!("Can't upgrade passwords of citizen {cit_id} due to database failure")
+        }
+    }
 }
 
 fn check_citizen_enabled(database_citizen: &CitizenQuery) -> Result<(), ReasonCode> {
@@ -48,7 +48,7 @@ pub use cav_get::get_cav;
 
 use aw_core::*;
 
-fn check_valid_name(mut name: &str, is_tourist: bool) -> Result<(), ReasonCode> {
+pub fn check_valid_name(mut name: &str, is_tourist: bool) -> Result<(), ReasonCode> {
     if is_tourist {
         // Tourist names must start and end with quotes
         if !name.starts_with('"') || !name.ends_with('"') {
@@ -1,7 +1,7 @@
 use crate::{
     client::ClientInfo,
     database::{telegram::TelegramQuery, CitizenDB, TelegramDB, UniverseDatabase},
-    get_conn,
+    get_conn_mut,
     timestamp::unix_epoch_timestamp_u32,
     universe_connection::UniverseConnectionID,
     UniverseConnection, UniverseServer,
@@ -9,9 +9,9 @@ use crate::{
 use aw_core::*;
 use aw_db::DatabaseResult;
 
-pub fn telegram_get(server: &UniverseServer, cid: UniverseConnectionID, packet: &AWPacket) {
+pub fn telegram_get(server: &mut UniverseServer, cid: UniverseConnectionID, packet: &AWPacket) {
     let mut response = AWPacket::new(PacketType::TelegramDeliver);
-    let conn = get_conn!(server, cid, "telegram_get");
+    let conn = get_conn_mut!(server, cid, "telegram_get");
 
     let rc = match try_telegram_get(conn, packet, &server.database) {
         Ok((telegram, more_remain)) => match server.database.citizen_by_number(telegram.from) {
@@ -40,7 +40,7 @@ pub fn telegram_get(server: &UniverseServer, cid: UniverseConnectionID, packet:
 }
 
 fn try_telegram_get(
-    conn: &UniverseConnection,
+    conn: &mut UniverseConnection,
     _packet: &AWPacket,
     database: &UniverseDatabase,
 ) -> Result<(TelegramQuery, bool), ReasonCode> {
@@ -49,8 +49,23 @@ fn try_telegram_get(
         return Err(ReasonCode::UnableToGetTelegram);
     };
 
+    let citizen_id = player.citizen_id();
+
+    // Notices from the universe come first, and are for every player
+    if let Some(notice) = conn.notices.pop_front() {
+        let more_remain = !conn.notices.is_empty()
+            || match citizen_id {
+                Some(citizen_id) => match database.telegram_get_undelivered(citizen_id) {
+                    DatabaseResult::Ok(telegrams) => !telegrams.is_empty(),
+                    DatabaseResult::DatabaseError => false,
+                },
+                None => false,
+            };
+        return Ok((notice.into_telegram(citizen_id.unwrap_or(0)), more_remain));
+    }
+
     // Must be logged in as a citizen
-    let Some(citizen_id) = player.citizen_id() else {
+    let Some(citizen_id) = citizen_id else {
         return Err(ReasonCode::UnableToGetTelegram);
     };
 
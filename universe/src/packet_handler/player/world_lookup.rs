@@ -1,7 +1,11 @@
 use std::net::IpAddr;
 
-use crate::{get_conn_mut, universe_connection::UniverseConnectionID, UniverseServer};
+use crate::{
+    database::CitizenDB, get_conn, get_conn_mut, universe_connection::UniverseConnectionID,
+    UniverseServer,
+};
 use aw_core::*;
+use aw_db::DatabaseResult;
 
 use rand::Rng;
 
@@ -26,7 +30,20 @@ pub fn world_lookup(server: &mut UniverseServer, cid: UniverseConnectionID, pack
 
     p.add_string(VarID::WorldName, world_name.clone());
 
+    let conn = get_conn!(server, cid, "world_lookup");
+    let password_change_required = match conn.client.as_ref().and_then(|x| x.citizen()) {
+        Some(citizen) => server
+            .database
+            .citizen_password_change_required(citizen.cit_id),
+        None => DatabaseResult::Ok(false),
+    };
+
     match server.connections.get_world_entry_by_name(&world_name) {
+        // Citizens who still have to change their password stay out of worlds until they do
+        Some(_) if !matches!(password_change_required, DatabaseResult::Ok(false)) => {
+            log::info!("Refused a world to {cid:?}, who must change their password first");
+            p.add_int(VarID::ReasonCode, ReasonCode::Unauthorized as i32);
+        }
         Some(world) => {
             let max_users = world.max_users;
             let world_size = world.world_size;
@@ -5,62 +5,13 @@ use crate::{
     universe_connection::UniverseConnectionID,
     UniverseServer,
 };
-use aw_core::{AWPacket, PacketType, ReasonCode, VarID};
-
-#[derive(Debug)]
-enum IdentifyParamsError {
-    WorldName,
-    Nonce,
-    SessionID,
-    PlayerIP,
-    PlayerPort,
-}
-
-struct IdentifyParams {
-    world_name: String,
-    nonce: Vec<u8>,
-    session_id: u16,
-    player_ip: u32,
-    player_port: u16,
-}
-
-impl TryFrom<&AWPacket> for IdentifyParams {
-    type Error = IdentifyParamsError;
-
-    fn try_from(value: &AWPacket) -> Result<Self, Self::Error> {
-        let world_name = value
-            .get_string(VarID::WorldName)
-            .ok_or(IdentifyParamsError::WorldName)?;
-        let nonce = value
-            .get_data(VarID::WorldUserNonce)
-            .ok_or(IdentifyParamsError::Nonce)?;
-        let session_id = value
-            .get_uint(VarID::SessionID)
-            .and_then(|sid| u16::try_from(sid).ok())
-            .ok_or(IdentifyParamsError::SessionID)?;
-        let player_ip = value
-            .get_uint(VarID::IdentifyUserIP)
-            .ok_or(IdentifyParamsError::PlayerIP)?;
-        let player_port = value
-            .get_uint(VarID::PlayerPort)
-            .and_then(|port| u16::try_from(port).ok())
-            .ok_or(IdentifyParamsError::PlayerPort)?;
-
-        Ok(Self {
-            world_name,
-            nonce,
-            session_id,
-            player_ip,
-            player_port,
-        })
-    }
-}
+use aw_core::{messages::Identify, AWPacket, PacketType, ReasonCode, VarID};
 
 /// A connection (supposed to be a world server) wants to know information about a player.
 pub fn identify(server: &mut UniverseServer, cid: UniverseConnectionID, packet: &AWPacket) {
     let mut p = AWPacket::new(PacketType::Identify);
 
-    let mut params = match IdentifyParams::try_from(packet) {
+    let mut params = match Identify::try_from(packet) {
         Ok(params) => params,
         Err(why) => {
             log::debug!("Could not complete identify: {why:?}");
@@ -102,7 +53,7 @@ pub fn identify(server: &mut UniverseServer, cid: UniverseConnectionID, packet:
 
 fn identify_player(
     server: &mut UniverseServer,
-    params: &IdentifyParams,
+    params: &Identify,
     response: &mut AWPacket,
 ) -> ReasonCode {
     let Some(player_cid) = server.connections.get_by_session_id(params.session_id) else {
@@ -1,44 +1,8 @@
 use crate::{
-    client::ClientInfo, get_conn_mut, universe_connection::UniverseConnectionID,
-    world::WorldServer, UniverseServer,
+    client::ClientInfo, ejection::is_connection_ejected, get_conn_mut,
+    universe_connection::UniverseConnectionID, world::WorldServer, UniverseServer,
 };
-use aw_core::{AWPacket, VarID};
-
-#[derive(Debug)]
-enum WorldServerStartParamsError {
-    Version,
-    Build,
-    Port,
-}
-
-struct WorldServerStartParams {
-    version: u32,
-    build: u32,
-    port: u16,
-}
-
-impl TryFrom<&AWPacket> for WorldServerStartParams {
-    type Error = WorldServerStartParamsError;
-
-    fn try_from(value: &AWPacket) -> Result<Self, Self::Error> {
-        let version = value
-            .get_uint(VarID::BrowserVersion)
-            .ok_or(WorldServerStartParamsError::Version)?;
-        let build = value
-            .get_uint(VarID::WorldBuild)
-            .ok_or(WorldServerStartParamsError::Build)?;
-        let port = value
-            .get_uint(VarID::WorldPort)
-            .and_then(|port| u16::try_from(port).ok())
-            .ok_or(WorldServerStartParamsError::Port)?;
-
-        Ok(Self {
-            version,
-            build,
-            port,
-        })
-    }
-}
+use aw_core::{messages::WorldServerStart, AWPacket};
 
 pub fn world_server_start(
     server: &mut UniverseServer,
@@ -54,7 +18,7 @@ pub fn world_server_start(
         return;
     }
 
-    let params = match WorldServerStartParams::try_from(packet) {
+    let params = match WorldServerStart::try_from(packet) {
         Ok(params) => params,
         Err(why) => {
             log::debug!("Could not complete world server start: {why:?}");
@@ -68,6 +32,20 @@ pub fn world_server_start(
         worlds: Vec::new(),
     }));
 
+    // Ejected addresses may not host worlds
+    match is_connection_ejected(&server.database, conn) {
+        Some(true) => {
+            log::info!(
+                "Preventing {:?} from starting a world server because it is ejected",
+                conn.addr().ip()
+            );
+            conn.disconnect();
+            return;
+        }
+        Some(false) => {}
+        None => log::warn!("Cannot determine whether world server is ejected"),
+    }
+
     log::info!(
         "Connection {} has made itself a world server. Version: 0x{:X}; Build: {}; Port {}",
         conn.addr().ip(),
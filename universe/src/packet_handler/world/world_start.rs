@@ -1,8 +1,9 @@
-use aw_core::{AWPacket, PacketType, ReasonCode, VarID};
+use aw_core::{messages::WorldStart, AWPacket, PacketType, ReasonCode, VarID};
 use aw_db::DatabaseResult;
 
 use crate::{
     database::{attrib::Attribute, license::LicenseQuery, AttribDB, LicenseDB},
+    ejection::is_connection_ejected,
     get_conn, get_conn_mut,
     tabs::regenerate_world_list,
     timestamp::unix_epoch_timestamp_u32,
@@ -11,49 +12,6 @@ use crate::{
     UniverseServer,
 };
 
-#[derive(Debug)]
-enum WorldStartParamsError {
-    Name,
-    Password,
-    Rating,
-    FreeEntry,
-}
-
-struct WorldStartParams {
-    world_name: String,
-    world_password: String,
-    world_rating: WorldRating,
-    world_free_entry: bool,
-}
-
-impl TryFrom<&AWPacket> for WorldStartParams {
-    type Error = WorldStartParamsError;
-
-    fn try_from(value: &AWPacket) -> Result<Self, Self::Error> {
-        let world_name = value
-            .get_string(VarID::WorldName)
-            .ok_or(WorldStartParamsError::Name)?;
-        let world_password = value
-            .get_string(VarID::WorldLicensePassword)
-            .ok_or(WorldStartParamsError::Password)?;
-        let world_rating = value
-            .get_byte(VarID::WorldRating)
-            .and_then(WorldRating::from_u8)
-            .ok_or(WorldStartParamsError::Rating)?;
-        let world_free_entry = value
-            .get_byte(VarID::WorldFreeEntry)
-            .map(|free_entry| free_entry != 0)
-            .ok_or(WorldStartParamsError::FreeEntry)?;
-
-        Ok(WorldStartParams {
-            world_name,
-            world_password,
-            world_rating,
-            world_free_entry,
-        })
-    }
-}
-
 pub fn world_start(server: &mut UniverseServer, cid: UniverseConnectionID, packet: &AWPacket) {
     let conn = get_conn!(server, cid, "world_start");
 
@@ -63,7 +21,7 @@ pub fn world_start(server: &mut UniverseServer, cid: UniverseConnectionID, packe
 
     let world_build = world_server.build;
 
-    let params = match WorldStartParams::try_from(packet) {
+    let params = match WorldStart::try_from(packet) {
         Ok(params) => params,
         Err(why) => {
             log::debug!("Could not complete world start: {why:?}");
@@ -71,16 +29,37 @@ pub fn world_start(server: &mut UniverseServer, cid: UniverseConnectionID, packe
         }
     };
 
+    let Some(rating) = WorldRating::from_u8(params.rating) else {
+        log::debug!(
+            "Could not complete world start: Invalid rating {}",
+            params.rating
+        );
+        return;
+    };
+
     let mut p = AWPacket::new(PacketType::WorldStart);
 
-    p.add_string(VarID::WorldName, params.world_name.clone());
+    p.add_string(VarID::WorldName, params.name.clone());
+
+    // The world server may have been ejected since it logged in
+    match is_connection_ejected(&server.database, conn) {
+        Some(true) => {
+            log::info!(
+                "Refusing to start world {:?} for {:?} because it is ejected",
+                params.name,
+                conn.addr().ip()
+            );
+            p.add_int(VarID::ReasonCode, ReasonCode::Ejected.into());
+            conn.send(p);
+            let conn = get_conn_mut!(server, cid, "world_start");
+            conn.disconnect();
+            return;
+        }
+        Some(false) => {}
+        None => log::warn!("Cannot determine whether world server is ejected"),
+    }
 
-    let lic = match validate_world(
-        server,
-        world_build,
-        &params.world_name,
-        &params.world_password,
-    ) {
+    let lic = match validate_world(server, world_build, &params.name, &params.password) {
         Ok(x) => x,
         Err(rc) => {
             log::info!("Unable to start world: {rc:?}");
@@ -95,7 +74,7 @@ pub fn world_start(server: &mut UniverseServer, cid: UniverseConnectionID, packe
         log::info!(
             "{:?} attempted to start a world {:?} twice.",
             &conn,
-            &params.world_name
+            &params.name
         );
         p.add_int(VarID::ReasonCode, ReasonCode::WorldAlreadyStarted.into());
         conn.send(p);
@@ -104,10 +83,11 @@ pub fn world_start(server: &mut UniverseServer, cid: UniverseConnectionID, packe
 
     let new_world = World {
         name: lic.name.clone(),
-        free_entry: params.world_free_entry,
+        free_entry: params.free_entry,
         world_size: lic.world_size,
         max_users: lic.users,
-        rating: params.world_rating,
+        license_password: lic.password.clone(),
+        rating,
         user_count: 0,
     };
 
@@ -171,8 +151,6 @@ fn validate_world(
         return Err(ReasonCode::SdkMustUpgrade);
     }
 
-    // TODO: Check for ejected client
-
     let world_lic = match server.database.license_by_name(name) {
         DatabaseResult::Ok(Some(lic)) => lic,
         DatabaseResult::Ok(None) => return Err(ReasonCode::InvalidWorld),
@@ -0,0 +1,141 @@
+//! Storage and verification of citizen passwords.
+//!
+//! Passwords are stored as argon2 hashes of the same digest that protocol 6 browsers send when
+//! logging in, so a single stored hash can be checked against either protocol version.
+//! Rows written before hashing was introduced still contain plaintext; those are recognized and
+//! can be upgraded once the password is known to be correct.
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use aw_core::login_digest;
+use rand::{distributions::Alphanumeric, Rng};
+
+/// Prefix of every PHC string produced by [`hash_password`].
+const HASH_PREFIX: &str = "$argon2";
+
+/// Length of passwords created by [`generate_password`], which is the longest a browser accepts.
+const GENERATED_PASSWORD_LENGTH: usize = 12;
+
+/// Sent to clients in place of a password which is set, since only its hash is stored.
+pub const PASSWORD_PLACEHOLDER: &str = "********";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordCheck {
+    /// The password does not match.
+    Invalid,
+    /// The password matches a stored hash.
+    Valid,
+    /// The password matches, but is stored as plaintext and should be rehashed.
+    ValidLegacy,
+}
+
+impl PasswordCheck {
+    pub fn is_valid(self) -> bool {
+        self != PasswordCheck::Invalid
+    }
+}
+
+/// Hashes a password for storage. An empty password is stored as an empty string,
+/// which means that no password has been set.
+pub fn hash_password(password: &str) -> Result<String, argon2::password_hash::Error> {
+    if password.is_empty() {
+        return Ok(String::new());
+    }
+
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default().hash_password(&login_digest(password), &salt)?;
+
+    Ok(hash.to_string())
+}
+
+/// Whether a stored password has already been hashed.
+pub fn is_hashed(stored: &str) -> bool {
+    stored.starts_with(HASH_PREFIX)
+}
+
+/// Whether a stored password still needs to be rewritten as a hash.
+pub fn needs_upgrade(stored: &str) -> bool {
+    !stored.is_empty() && !is_hashed(stored)
+}
+
+/// Checks a plaintext password against a stored password.
+pub fn verify_password(password: &str, stored: &str) -> PasswordCheck {
+    if is_hashed(stored) {
+        verify_digest(&login_digest(password), stored)
+    } else if password == stored {
+        PasswordCheck::ValidLegacy
+    } else {
+        PasswordCheck::Invalid
+    }
+}
+
+/// Checks a login digest (see [`login_digest`]) against a stored password.
+pub fn verify_digest(digest: &[u8], stored: &str) -> PasswordCheck {
+    if !is_hashed(stored) {
+        return match login_digest(stored) == digest {
+            true => PasswordCheck::ValidLegacy,
+            false => PasswordCheck::Invalid,
+        };
+    }
+
+    let Ok(hash) = PasswordHash::new(stored) else {
+        log::error!("A stored password hash could not be parsed");
+        return PasswordCheck::Invalid;
+    };
+
+    match Argon2::default().verify_password(digest, &hash) {
+        Ok(()) => PasswordCheck::Valid,
+        Err(_) => PasswordCheck::Invalid,
+    }
+}
+
+/// What clients are sent of a stored password: nothing if none is set, or else a placeholder.
+pub fn placeholder(stored: &str) -> String {
+    match stored.is_empty() {
+        true => String::new(),
+        false => PASSWORD_PLACEHOLDER.to_string(),
+    }
+}
+
+/// Creates a random password that a browser will accept.
+pub fn generate_password() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(GENERATED_PASSWORD_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashed_password_verifies() {
+        let stored = hash_password("welcome").unwrap();
+
+        assert!(is_hashed(&stored));
+        assert_eq!(verify_password("welcome", &stored), PasswordCheck::Valid);
+        assert_eq!(verify_password("Welcome", &stored), PasswordCheck::Invalid);
+        assert_eq!(
+            verify_digest(&login_digest("welcome"), &stored),
+            PasswordCheck::Valid
+        );
+    }
+
+    #[test]
+    fn legacy_password_verifies() {
+        assert_eq!(
+            verify_password("welcome", "welcome"),
+            PasswordCheck::ValidLegacy
+        );
+        assert_eq!(
+            verify_digest(&login_digest("welcome"), "welcome"),
+            PasswordCheck::ValidLegacy
+        );
+        assert_eq!(verify_password("hello", "welcome"), PasswordCheck::Invalid);
+        assert!(needs_upgrade("welcome"));
+        assert!(!needs_upgrade(""));
+    }
+}
@@ -1,4 +1,4 @@
-use std::net::IpAddr;
+use std::{net::IpAddr, time::Instant};
 
 use crate::tabs::Tabs;
 
@@ -30,6 +30,9 @@ pub struct GenericPlayer {
 #[derive(Debug)]
 pub struct Citizen {
     pub cit_id: u32,
+    /// When the citizen logged in, so their time online can be added to their total time when
+    /// they leave
+    pub logged_in_at: Instant,
     pub base_player: GenericPlayer,
 }
 
@@ -0,0 +1,112 @@
+//! Stopping the universe gracefully, so players are warned and world servers are told why they
+//! lost their universe instead of seeing their connection drop.
+//!
+//! Once a shutdown starts, no more clients are accepted, and every player is sent a warning which
+//! is repeated as the time left passes each of [`WARNINGS`]. Players are notified of the warning
+//! and fetch it like a telegram, but it is not stored, so nobody finds it waiting after the
+//! universe is back. When the time is up, every world is stopped with [`CLOSING_REASON`] and every
+//! connection is closed before the main loop ends. Players have no packet which tells them why
+//! they are disconnected, so the last warning they fetched is what explains it.
+use std::time::{Duration, Instant};
+
+use aw_core::ReasonCode;
+
+use crate::{telegram::send_notice_to_everyone, UniverseServer};
+
+/// Seconds left at which the warning is sent again.
+const WARNINGS: [u64; 8] = [3600, 1800, 600, 300, 120, 60, 30, 10];
+
+/// World servers are told their worlds stop because the universe is no longer available.
+const CLOSING_REASON: ReasonCode = ReasonCode::NotAvailable;
+
+/// A shutdown which has been started and is counting down.
+#[derive(Debug)]
+pub struct Shutdown {
+    at: Instant,
+    /// Seconds which were left when the last warning was sent
+    last_warning: Option<u64>,
+}
+
+impl Shutdown {
+    pub fn after(delay: Duration) -> Self {
+        Self {
+            at: Instant::now() + delay,
+            last_warning: None,
+        }
+    }
+
+    pub fn is_due(&self) -> bool {
+        Instant::now() >= self.at
+    }
+
+    /// Whole seconds left until the universe stops, rounded up.
+    pub fn seconds_left(&self) -> u64 {
+        let left = self.at.saturating_duration_since(Instant::now());
+        left.as_secs() + u64::from(left.subsec_nanos() > 0)
+    }
+
+    /// Seconds left to warn players about, if a warning is due. The first warning is due right
+    /// away, and another each time the time left passes one of [`WARNINGS`].
+    pub fn warning_due(&mut self) -> Option<u64> {
+        let left = self.seconds_left();
+        if left == 0 {
+            return None;
+        }
+
+        let due = match self.last_warning {
+            None => true,
+            Some(last) => WARNINGS
+                .iter()
+                .any(|&warning| left <= warning && warning < last),
+        };
+        if due {
+            self.last_warning = Some(left);
+        }
+
+        due.then_some(left)
+    }
+}
+
+/// Send every player the configured warning that the universe stops in `seconds`.
+pub fn warn_players(server: &mut UniverseServer, seconds: u64) {
+    let warning = warning(server, seconds);
+    let recipients = send_notice_to_everyone(server, &warning);
+    log::info!("Warned {recipients} players that the universe stops in {seconds} seconds.");
+}
+
+fn warning(server: &UniverseServer, seconds: u64) -> String {
+    server
+        .config
+        .shutdown
+        .warning
+        .replace("{seconds}", &seconds.to_string())
+}
+
+/// Stop every world and close every connection.
+pub fn close_connections(server: &mut UniverseServer) {
+    for (_id, conn) in server.connections.iter_mut() {
+        conn.stop_worlds(CLOSING_REASON);
+        conn.disconnect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warnings_count_down() {
+        let mut shutdown = Shutdown::after(Duration::from_secs(90));
+        assert_eq!(shutdown.warning_due(), Some(90));
+        assert_eq!(shutdown.warning_due(), None);
+
+        // As if 30 seconds had passed
+        shutdown.at -= Duration::from_secs(30);
+        assert_eq!(shutdown.warning_due(), Some(60));
+        assert_eq!(shutdown.warning_due(), None);
+
+        shutdown.at -= Duration::from_secs(60);
+        assert!(shutdown.is_due());
+        assert_eq!(shutdown.warning_due(), None);
+    }
+}
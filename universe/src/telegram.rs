@@ -2,10 +2,18 @@ use aw_core::{AWPacket, PacketType};
 use aw_db::DatabaseResult;
 
 use crate::{
-    client::ClientInfo, database::TelegramDB, get_conn, player::Player,
-    universe_connection::UniverseConnectionID, UniverseServer,
+    client::ClientInfo,
+    database::{telegram::TelegramQuery, TelegramDB},
+    get_conn,
+    player::Player,
+    timestamp::unix_epoch_timestamp_u32,
+    universe_connection::UniverseConnectionID,
+    UniverseServer,
 };
 
+/// Messages from the universe itself are sent as telegrams from the Administrator.
+const UNIVERSE_SENDER: u32 = 1;
+
 pub fn send_telegram_update_available(server: &UniverseServer, cid: UniverseConnectionID) {
     let conn = get_conn!(server, cid, "send_telegram_update_available");
 
@@ -25,3 +33,75 @@ pub fn send_telegram_update_available(server: &UniverseServer, cid: UniverseConn
         }
     }
 }
+
+/// Send a telegram from the universe to every citizen who is logged in, returning how many were
+/// sent. Tourists and bots can't receive telegrams.
+pub fn send_telegram_to_everyone(server: &UniverseServer, message: &str) -> DatabaseResult<usize> {
+    let now = unix_epoch_timestamp_u32();
+    let mut recipients = 0;
+    for cid in server.connections.cids() {
+        let Some(citizen_id) = server
+            .connections
+            .get_connection(cid)
+            .and_then(|conn| conn.client.as_ref())
+            .and_then(ClientInfo::citizen_id)
+        else {
+            continue;
+        };
+
+        if let DatabaseResult::DatabaseError =
+            server
+                .database
+                .telegram_add(citizen_id, UNIVERSE_SENDER, now, message)
+        {
+            return DatabaseResult::DatabaseError;
+        }
+        send_telegram_update_available(server, cid);
+        recipients += 1;
+    }
+
+    DatabaseResult::Ok(recipients)
+}
+
+/// A message from the universe which a player's connection holds, instead of it being stored, until
+/// the player asks for their telegrams.
+#[derive(Debug, Clone)]
+pub struct Notice {
+    pub message: String,
+    pub timestamp: u32,
+}
+
+impl Notice {
+    /// The notice as a telegram from the universe, as it is delivered.
+    pub fn into_telegram(self, citizen: u32) -> TelegramQuery {
+        TelegramQuery {
+            id: 0,
+            citizen,
+            from: UNIVERSE_SENDER,
+            timestamp: self.timestamp,
+            message: self.message,
+            delivered: 1,
+        }
+    }
+}
+
+/// Tell every player who is online, tourists and bots included, that the universe has a message
+/// for them. Like a telegram, it is delivered once they ask for it, but it is only held by their
+/// connection. Returns how many players it was sent to.
+pub fn send_notice_to_everyone(server: &mut UniverseServer, message: &str) -> usize {
+    let notice = Notice {
+        message: message.to_string(),
+        timestamp: unix_epoch_timestamp_u32(),
+    };
+
+    let mut recipients = 0;
+    for (_id, conn) in server.connections.iter_mut() {
+        if let Some(ClientInfo::Player(_)) = &conn.client {
+            conn.notices.push_back(notice.clone());
+            conn.send(AWPacket::new(PacketType::TelegramNotify));
+            recipients += 1;
+        }
+    }
+
+    recipients
+}
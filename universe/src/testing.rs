@@ -0,0 +1,1088 @@
+//! Running a real universe on an ephemeral port, so tests can drive the packet handlers end to end
+//! with [`AWClient`] sessions.
+use std::{
+    io::{Read, Write},
+    net::{Ipv4Addr, SocketAddr, TcpStream},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::channel,
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use aw_core::{
+    messages::Immigrate, AWClient, AWPacket, Login, LoginInfo, PacketType, ProtocolVersion, VarID,
+};
+use aw_db::DatabaseResult;
+
+use crate::{
+    configuration::Config,
+    database::{CitizenDB, UniverseDatabase},
+    UniverseServer,
+};
+
+/// Password of the Administrator (citizen #1) in every test universe.
+pub const ADMIN_PASSWORD: &str = "admin";
+
+/// A universe with an in-memory database, which is stopped when dropped.
+pub struct TestUniverse {
+    addr: SocketAddr,
+    admin_api_addr: Option<SocketAddr>,
+    console_socket: Option<PathBuf>,
+    metrics_addr: Option<SocketAddr>,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl TestUniverse {
+    pub fn start() -> Self {
+        Self::start_with(|_| {})
+    }
+
+    /// Start a universe with changes to the default test configuration.
+    pub fn start_with(configure: impl FnOnce(&mut Config)) -> Self {
+        Self::start_config(test_config(configure), None)
+    }
+
+    /// Start a universe whose configuration is saved to `path`, which it reloads when changed.
+    pub fn start_from_file(path: &Path, configure: impl FnOnce(&mut Config)) -> Self {
+        let config = test_config(configure);
+        std::fs::remove_file(path).ok();
+        config
+            .create(path)
+            .expect("Could not write the configuration");
+        Self::start_config(config, Some(path.to_path_buf()))
+    }
+
+    fn start_config(config: Config, config_path: Option<PathBuf>) -> Self {
+        let console_socket = config
+            .universe
+            .console
+            .as_ref()
+            .and_then(|console| console.socket.clone());
+
+        let running = Arc::new(AtomicBool::new(true));
+        let (addr_tx, addr_rx) = channel();
+
+        // The server is created on its own thread because its database connection can't be sent
+        // to another one.
+        let r = running.clone();
+        let thread = thread::spawn(move || {
+            let mut server = UniverseServer::new(config).expect("Could not create universe");
+            if let Some(path) = config_path {
+                server.watch_config_file(path, Default::default());
+            }
+            set_admin_password(&server.database);
+            let addr = server.local_addr().expect("Universe has no address");
+            addr_tx
+                .send((addr, server.admin_api_addr(), server.metrics_addr()))
+                .ok();
+            server.run_until(&r);
+        });
+
+        let (addr, admin_api_addr, metrics_addr) =
+            addr_rx.recv().expect("The universe failed to start");
+
+        Self {
+            addr,
+            admin_api_addr,
+            console_socket,
+            metrics_addr,
+            running,
+            thread: Some(thread),
+        }
+    }
+
+    /// Connect a client which has exchanged keys with the universe, but has not logged in.
+    pub fn connect(&self, version: ProtocolVersion) -> AWClient {
+        AWClient::connect(self.addr, version).expect("Could not connect to the universe")
+    }
+
+    /// Send a request to the admin API, returning the status and JSON body of the response.
+    pub fn admin_api(
+        &self,
+        method: &str,
+        path: &str,
+        token: &str,
+        body: Option<serde_json::Value>,
+    ) -> (u16, serde_json::Value) {
+        let addr = self.admin_api_addr.expect("The admin API is not enabled");
+        let body = body.map(|body| body.to_string()).unwrap_or_default();
+        let mut stream = TcpStream::connect(addr).expect("Could not connect to the admin API");
+        write!(
+            stream,
+            "{method} {path} HTTP/1.1\r\nHost: {addr}\r\nAuthorization: Bearer {token}\r\n\
+            Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+        .expect("Could not send admin API request");
+
+        let mut response = String::new();
+        stream
+            .read_to_string(&mut response)
+            .expect("Could not read admin API response");
+        let (head, body) = response
+            .split_once("\r\n\r\n")
+            .expect("Admin API response has no body");
+        let status = head
+            .split(' ')
+            .nth(1)
+            .and_then(|status| status.parse().ok())
+            .expect("Admin API response has no status");
+
+        (
+            status,
+            serde_json::from_str(body).expect("Body is not JSON"),
+        )
+    }
+
+    /// Run a command on the console socket, returning its output.
+    #[cfg(unix)]
+    pub fn console(&self, line: &str) -> String {
+        use std::{net::Shutdown, os::unix::net::UnixStream};
+
+        let path = self
+            .console_socket
+            .as_ref()
+            .expect("The console socket is not enabled");
+        let mut stream = UnixStream::connect(path).expect("Could not connect to the console");
+        writeln!(stream, "{line}").expect("Could not send console command");
+        // The console answers every line it reads, then hangs up once there are no more
+        stream
+            .shutdown(Shutdown::Write)
+            .expect("Could not finish console command");
+
+        let mut output = String::new();
+        stream
+            .read_to_string(&mut output)
+            .expect("Could not read console output");
+        output
+    }
+
+    /// Scrape the metrics endpoint.
+    pub fn metrics(&self) -> String {
+        let addr = self.metrics_addr.expect("Metrics are not enabled");
+        let mut stream = TcpStream::connect(addr).expect("Could not connect to the metrics");
+        write!(
+            stream,
+            "GET /metrics HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n"
+        )
+        .expect("Could not request metrics");
+
+        let mut response = String::new();
+        stream
+            .read_to_string(&mut response)
+            .expect("Could not read metrics");
+        let (_head, body) = response
+            .split_once("\r\n\r\n")
+            .expect("Metrics response has no body");
+        body.to_string()
+    }
+
+    pub fn tourist(&self, name: &str) -> AWClient {
+        let mut client = self.connect(ProtocolVersion::V4);
+        client
+            .login(Login::Tourist {
+                name: name.to_string(),
+            })
+            .expect("Tourist could not log in");
+        client
+    }
+
+    pub fn admin(&self) -> AWClient {
+        self.citizen("Administrator", ADMIN_PASSWORD).0
+    }
+
+    /// Connect a world server, and start a world on it with a license added by `admin`.
+    pub fn world(&self, admin: &mut AWClient, name: &str) -> AWClient {
+        let mut license = AWPacket::new(PacketType::LicenseAdd);
+        license.add_string(VarID::WorldName, name.to_string());
+        license.add_string(VarID::WorldLicensePassword, "worldpass".to_string());
+        license.add_string(VarID::WorldLicenseEmail, String::new());
+        license.add_string(VarID::WorldLicenseComment, String::new());
+        for var in [
+            VarID::WorldLicenseExpiration,
+            VarID::WorldLicenseHidden,
+            VarID::WorldLicenseTourists,
+            VarID::WorldLicenseVoip,
+            VarID::WorldLicensePlugins,
+        ] {
+            license.add_uint(var, 0);
+        }
+        license.add_uint(VarID::WorldLicenseUsers, 10);
+        license.add_uint(VarID::WorldLicenseRange, 100);
+        admin
+            .request(license, PacketType::LicenseChangeResult)
+            .expect("Could not add a license");
+
+        let mut world_server = self.connect(ProtocolVersion::V4);
+        let mut server_start = AWPacket::new(PacketType::WorldServerStart);
+        server_start.add_uint(VarID::BrowserVersion, 4);
+        server_start.add_uint(VarID::WorldBuild, 1);
+        server_start.add_uint(VarID::WorldPort, 7000);
+        world_server.send(server_start);
+
+        let mut world_start = AWPacket::new(PacketType::WorldStart);
+        world_start.add_string(VarID::WorldName, name.to_string());
+        world_start.add_string(VarID::WorldLicensePassword, "worldpass".to_string());
+        world_start.add_byte(VarID::WorldRating, 0);
+        world_start.add_byte(VarID::WorldFreeEntry, 1);
+        world_server
+            .request(world_start, PacketType::WorldStart)
+            .expect("Could not start the world");
+
+        world_server
+    }
+
+    /// Log in as an existing citizen.
+    pub fn citizen(&self, name: &str, password: &str) -> (AWClient, LoginInfo) {
+        let mut client = self.connect(ProtocolVersion::V4);
+        let info = client
+            .login(Login::Citizen {
+                name: name.to_string(),
+                password: password.to_string(),
+                privilege: None,
+            })
+            .expect("Citizen could not log in");
+        (client, info)
+    }
+
+    /// Immigrate a new citizen and log in as them.
+    pub fn immigrant(&self, name: &str, password: &str) -> (AWClient, LoginInfo) {
+        let mut client = self.connect(ProtocolVersion::V4);
+        client
+            .request(
+                immigrate_packet(name, password),
+                PacketType::ImmigrateResponse,
+            )
+            .expect("Could not immigrate");
+
+        self.citizen(name, password)
+    }
+}
+
+impl Drop for TestUniverse {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() && !thread::panicking() {
+                panic!("The universe panicked");
+            }
+        }
+    }
+}
+
+pub fn immigrate_packet(name: &str, password: &str) -> AWPacket {
+    Immigrate {
+        name: name.to_string(),
+        password: password.to_string(),
+        email: String::new(),
+    }
+    .into()
+}
+
+/// The default test configuration, which serves an in-memory database on an ephemeral port, with
+/// changes made by `configure`.
+fn test_config(configure: impl FnOnce(&mut Config)) -> Config {
+    let mut config = Config::default();
+    config.universe.bind_ip = Ipv4Addr::LOCALHOST;
+    config.universe.license_ip = Ipv4Addr::LOCALHOST;
+    config.universe.port = 0;
+    config.sql.sqlite_config.path = ":memory:".to_string();
+    configure(&mut config);
+    config
+}
+
+/// The Administrator is created with a random password, which is replaced with a known one.
+/// It is stored as plaintext to avoid hashing it for every test.
+fn set_admin_password(database: &UniverseDatabase) {
+    let DatabaseResult::Ok(Some(mut admin)) = database.citizen_by_number(1) else {
+        panic!("The universe has no Administrator");
+    };
+    admin.password = ADMIN_PASSWORD.to_string();
+    assert!(!database.citizen_change(&admin).is_err());
+    assert!(!database
+        .citizen_set_password_change_required(1, false)
+        .is_err());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::{AdminApiConfig, ConsoleConfig, MetricsConfig};
+    use crate::timestamp::unix_epoch_timestamp_u32;
+    use aw_core::{messages::CitizenLookupByNumber, ClientError, PacketTypeResult, ReasonCode};
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[test]
+    fn tourists_log_in() {
+        let universe = TestUniverse::start();
+
+        for version in [ProtocolVersion::V4, ProtocolVersion::V6] {
+            let mut client = universe.connect(version);
+            let info = client
+                .login(Login::Tourist {
+                    name: "Visitor".to_string(),
+                })
+                .unwrap();
+            assert_eq!(info.name, "\"Visitor\"");
+            assert_eq!(info.citizen_number, None);
+            assert_eq!(client.world_list().unwrap(), vec![]);
+        }
+    }
+
+    #[test]
+    fn clients_beyond_the_connection_limit_wait() {
+        let universe = TestUniverse::start_with(|config| {
+            config.universe.connection_limit = 1;
+            config.universe.player_limit = 1;
+        });
+        let first = universe.tourist("First");
+
+        let (connected_tx, connected) = channel();
+        let addr = universe.addr;
+        thread::spawn(move || {
+            connected_tx
+                .send(AWClient::connect(addr, ProtocolVersion::V4).is_ok())
+                .ok();
+        });
+        assert!(connected.recv_timeout(Duration::from_millis(500)).is_err());
+
+        drop(first);
+        assert_eq!(connected.recv_timeout(Duration::from_secs(5)), Ok(true));
+    }
+
+    #[test]
+    fn citizens_immigrate_and_log_in() {
+        let universe = TestUniverse::start();
+        let mut client = universe.connect(ProtocolVersion::V4);
+
+        let immigrate = |client: &mut AWClient, name| {
+            client.request(
+                immigrate_packet(name, "secret"),
+                PacketType::ImmigrateResponse,
+            )
+        };
+        assert!(immigrate(&mut client, "Alice").is_ok());
+        assert!(matches!(
+            immigrate(&mut client, "Alice"),
+            Err(ClientError::Rejected(ReasonCode::NameAlreadyUsed))
+        ));
+
+        let login = |version, password: &str| {
+            universe.connect(version).login(Login::Citizen {
+                name: "Alice".to_string(),
+                password: password.to_string(),
+                privilege: None,
+            })
+        };
+        assert!(matches!(
+            login(ProtocolVersion::V4, "wrong"),
+            Err(ClientError::Rejected(ReasonCode::InvalidPassword))
+        ));
+        for version in [ProtocolVersion::V4, ProtocolVersion::V6] {
+            let info = login(version, "secret").unwrap();
+            assert_eq!(info.name, "Alice");
+            assert_eq!(info.citizen_number, Some(2));
+        }
+    }
+
+    #[test]
+    fn contacts_are_added_and_confirmed() {
+        let universe = TestUniverse::start();
+        let (mut alice, alice_info) = universe.immigrant("Alice", "secret");
+        let (mut bob, bob_info) = universe.immigrant("Bob", "secret");
+
+        let mut packet = AWPacket::new(PacketType::ContactAdd);
+        packet.add_string(VarID::ContactListName, "Bob".to_string());
+        packet.add_uint(VarID::ContactListOptions, 0);
+        let response = alice.request(packet, PacketType::ContactAdd).unwrap();
+        assert_eq!(
+            response.get_uint(VarID::ContactListCitizenID),
+            bob_info.citizen_number
+        );
+
+        let mut packet = AWPacket::new(PacketType::ContactConfirm);
+        packet.add_uint(
+            VarID::ContactListCitizenID,
+            alice_info.citizen_number.unwrap(),
+        );
+        packet.add_uint(VarID::ContactListOptions, 0);
+        bob.request(packet, PacketType::ContactConfirm).unwrap();
+
+        let contacts = alice.contact_list().unwrap();
+        assert_eq!(contacts.len(), 1);
+        assert_eq!(contacts[0].name, "Bob");
+        assert_eq!(Some(contacts[0].citizen_number), bob_info.citizen_number);
+    }
+
+    #[test]
+    fn telegrams_are_sent_and_delivered() {
+        let universe = TestUniverse::start();
+        let (mut alice, _) = universe.immigrant("Alice", "secret");
+        let (mut bob, _) = universe.immigrant("Bob", "secret");
+
+        let notified = Arc::new(AtomicBool::new(false));
+        let n = notified.clone();
+        bob.on(PacketType::TelegramNotify, move |_| {
+            n.store(true, Ordering::SeqCst);
+        });
+
+        alice.telegram_send("Bob", "Hello Bob").unwrap();
+        assert!(matches!(
+            alice.telegram_send("Nobody", "Hello?"),
+            Err(ClientError::Rejected(ReasonCode::NoSuchCitizen))
+        ));
+
+        bob.poll(Duration::from_millis(200)).unwrap();
+        assert!(notified.load(Ordering::SeqCst));
+
+        let telegram = bob
+            .request(
+                AWPacket::new(PacketType::TelegramGet),
+                PacketType::TelegramDeliver,
+            )
+            .unwrap();
+        assert_eq!(
+            telegram.get_string(VarID::TelegramCitizenName).as_deref(),
+            Some("Alice")
+        );
+        assert_eq!(
+            telegram.get_string(VarID::TelegramMessage).as_deref(),
+            Some("Hello Bob")
+        );
+
+        // It is only delivered once
+        assert!(matches!(
+            bob.request(
+                AWPacket::new(PacketType::TelegramGet),
+                PacketType::TelegramDeliver
+            ),
+            Err(ClientError::Rejected(ReasonCode::UnableToGetTelegram))
+        ));
+    }
+
+    #[test]
+    fn worlds_start_and_stop() {
+        let universe = TestUniverse::start();
+        let mut admin = universe.admin();
+
+        let mut packet = AWPacket::new(PacketType::LicenseAdd);
+        packet.add_string(VarID::WorldName, "Test".to_string());
+        packet.add_string(VarID::WorldLicensePassword, "worldpass".to_string());
+        packet.add_string(VarID::WorldLicenseEmail, String::new());
+        packet.add_string(VarID::WorldLicenseComment, String::new());
+        packet.add_uint(VarID::WorldLicenseExpiration, 0);
+        for var in [
+            VarID::WorldLicenseHidden,
+            VarID::WorldLicenseTourists,
+            VarID::WorldLicenseVoip,
+            VarID::WorldLicensePlugins,
+        ] {
+            packet.add_uint(var, 0);
+        }
+        packet.add_uint(VarID::WorldLicenseUsers, 10);
+        packet.add_uint(VarID::WorldLicenseRange, 100);
+        admin
+            .request(packet, PacketType::LicenseChangeResult)
+            .unwrap();
+
+        let mut world_server = universe.connect(ProtocolVersion::V4);
+        let mut packet = AWPacket::new(PacketType::WorldServerStart);
+        packet.add_uint(VarID::BrowserVersion, 4);
+        packet.add_uint(VarID::WorldBuild, 1);
+        packet.add_uint(VarID::WorldPort, 7000);
+        world_server.send(packet);
+
+        let world_start = |password: &str| {
+            let mut packet = AWPacket::new(PacketType::WorldStart);
+            packet.add_string(VarID::WorldName, "Test".to_string());
+            packet.add_string(VarID::WorldLicensePassword, password.to_string());
+            packet.add_byte(VarID::WorldRating, 0);
+            packet.add_byte(VarID::WorldFreeEntry, 1);
+            packet
+        };
+        assert!(matches!(
+            world_server.request(world_start("wrong"), PacketType::WorldStart),
+            Err(ClientError::Rejected(ReasonCode::InvalidPassword))
+        ));
+        world_server
+            .request(world_start("worldpass"), PacketType::WorldStart)
+            .unwrap();
+        assert!(matches!(
+            world_server.request(world_start("worldpass"), PacketType::WorldStart),
+            Err(ClientError::Rejected(ReasonCode::WorldAlreadyStarted))
+        ));
+
+        let mut tourist = universe.tourist("Visitor");
+        let worlds = tourist.world_list().unwrap();
+        assert_eq!(worlds.len(), 1);
+        assert_eq!(worlds[0].name, "Test");
+        assert_eq!(worlds[0].status, 1);
+
+        let world_stop = || {
+            let mut packet = AWPacket::new(PacketType::WorldStop);
+            packet.add_string(VarID::WorldName, "Test".to_string());
+            packet
+        };
+        world_server
+            .request(world_stop(), PacketType::WorldStop)
+            .unwrap();
+        assert!(matches!(
+            world_server.request(world_stop(), PacketType::WorldStop),
+            Err(ClientError::Rejected(ReasonCode::NoSuchWorld))
+        ));
+
+        // Players are told that the world is now hidden
+        tourist
+            .wait_until(|packet| {
+                packet.get_string(VarID::WorldListName).as_deref() == Some("Test")
+                    && packet.get_byte(VarID::WorldListStatus) == Some(3)
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn ejections_disconnect_clients() {
+        let universe = TestUniverse::start();
+        let mut admin = universe.admin();
+        let mut tourist = universe.tourist("Visitor");
+        let mut world_server = universe.world(&mut admin, "Test");
+
+        let eject_add = |address: Ipv4Addr| {
+            let mut packet = AWPacket::new(PacketType::EjectAdd);
+            packet.add_uint(VarID::EjectionAddress, u32::from_le_bytes(address.octets()));
+            packet.add_uint(VarID::EjectionExpiration, unix_epoch_timestamp_u32() + 3600);
+            packet.add_string(VarID::EjectionComment, String::new());
+            packet
+        };
+
+        // Clients still see ejections of IPv4 addresses as they sent them
+        let elsewhere = Ipv4Addr::new(192, 0, 2, 1);
+        admin
+            .request(eject_add(elsewhere), PacketType::EjectResult)
+            .unwrap();
+        let mut lookup = AWPacket::new(PacketType::EjectLookup);
+        lookup.add_uint(
+            VarID::EjectionAddress,
+            u32::from_le_bytes(elsewhere.octets()),
+        );
+        let info = admin.request(lookup, PacketType::EjectionInfo).unwrap();
+        assert_eq!(
+            info.get_uint(VarID::EjectionAddress),
+            Some(u32::from_le_bytes(elsewhere.octets()))
+        );
+
+        // The ejection covers the Administrator too, who may be disconnected before the result
+        admin.send(eject_add(Ipv4Addr::LOCALHOST));
+        assert!(matches!(
+            tourist.poll(Duration::from_secs(1)),
+            Err(ClientError::Disconnected)
+        ));
+
+        // World servers are told why their worlds stopped
+        let stop = world_server.wait_for(PacketType::WorldStop).unwrap();
+        assert_eq!(
+            stop.get_int(VarID::ReasonCode),
+            Some(ReasonCode::Ejected as i32)
+        );
+        assert!(matches!(
+            world_server.poll(Duration::from_secs(1)),
+            Err(ClientError::Disconnected)
+        ));
+
+        // The Administrator is only safe from ejections of their citizen number
+        assert!(matches!(
+            admin.poll(Duration::from_secs(1)),
+            Err(ClientError::Disconnected)
+        ));
+
+        // Logging in again is refused
+        let login = universe.connect(ProtocolVersion::V4).login(Login::Tourist {
+            name: "Visitor".to_string(),
+        });
+        assert!(matches!(login, Err(ClientError::Disconnected)));
+
+        let mut world_server = universe.connect(ProtocolVersion::V4);
+        let mut server_start = AWPacket::new(PacketType::WorldServerStart);
+        server_start.add_uint(VarID::BrowserVersion, 4);
+        server_start.add_uint(VarID::WorldBuild, 1);
+        server_start.add_uint(VarID::WorldPort, 7000);
+        world_server.send(server_start);
+        assert!(matches!(
+            world_server.poll(Duration::from_secs(1)),
+            Err(ClientError::Disconnected)
+        ));
+    }
+
+    #[test]
+    fn admin_api_manages_citizens_and_players() {
+        let universe = TestUniverse::start_with(|config| {
+            config.universe.admin_api = Some(AdminApiConfig {
+                bind: (Ipv4Addr::LOCALHOST, 0).into(),
+                token: "token".to_string(),
+            });
+        });
+
+        let (status, _) = universe.admin_api("GET", "/players", "wrong", None);
+        assert_eq!(status, 401);
+
+        let (status, citizen) = universe.admin_api(
+            "POST",
+            "/citizens",
+            "token",
+            Some(json!({ "name": "Bob Smith", "password": "secret" })),
+        );
+        assert_eq!(status, 200);
+        assert_eq!(citizen["number"], 2);
+        assert!(citizen.get("password").is_none());
+
+        let (status, citizen) =
+            universe.admin_api("GET", "/citizens?name=Bob%20Smith", "token", None);
+        assert_eq!((status, &citizen["number"]), (200, &json!(2)));
+        let (status, _) = universe.admin_api("DELETE", "/citizens/1", "token", None);
+        assert_eq!(status, 400);
+
+        let (mut bob, _) = universe.citizen("Bob Smith", "secret");
+        let (_, players) = universe.admin_api("GET", "/players", "token", None);
+        assert_eq!(players[0]["name"], "Bob Smith");
+
+        let path = format!("/players/{}/kick", players[0]["session_id"]);
+        let (status, _) = universe.admin_api("POST", &path, "token", None);
+        assert_eq!(status, 200);
+        assert!(matches!(
+            bob.poll(Duration::from_secs(1)),
+            Err(ClientError::Disconnected)
+        ));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn console_commands_manage_citizens_and_players() {
+        let socket =
+            std::env::temp_dir().join(format!("universe_console_{}.sock", std::process::id()));
+        let universe = TestUniverse::start_with(|config| {
+            config.universe.console = Some(ConsoleConfig {
+                stdin: false,
+                socket: Some(socket),
+            });
+        });
+
+        let output = universe.console("citizen add \"Bob Smith\" secret");
+        assert_eq!(output.trim(), "Added citizen #2 Bob Smith");
+
+        let (mut bob, _) = universe.citizen("Bob Smith", "secret");
+        assert!(universe.console("who").contains("Bob Smith"));
+
+        let output = universe.console("kick bob smith");
+        assert_eq!(output.trim(), "Kicked bob smith");
+        assert!(matches!(
+            bob.poll(Duration::from_secs(1)),
+            Err(ClientError::Disconnected)
+        ));
+
+        // Ejecting citizen #1 leaves the Administrator connected
+        let mut admin = universe.admin();
+        let output = universe.console("eject citizen:1 1h");
+        assert!(output.starts_with("Ejected"), "{output}");
+        assert!(admin.world_list().is_ok());
+
+        assert!(universe.console("frobnicate").starts_with("Error:"));
+    }
+
+    #[test]
+    fn metrics_are_served() {
+        let universe = TestUniverse::start_with(|config| {
+            config.universe.metrics = Some(MetricsConfig {
+                bind: (Ipv4Addr::LOCALHOST, 0).into(),
+            });
+        });
+
+        let _tourist = universe.tourist("Visitor");
+        let wrong_password = universe.connect(ProtocolVersion::V4).login(Login::Citizen {
+            name: "Administrator".to_string(),
+            password: "wrong".to_string(),
+            privilege: None,
+        });
+        assert!(wrong_password.is_err());
+
+        let metrics = universe.metrics();
+        let lines = metrics.lines().collect::<Vec<_>>();
+        for line in [
+            "aw_universe_connections{kind=\"tourist\"} 1",
+            "aw_universe_logins_total{reason=\"Success\"} 1",
+            "aw_universe_logins_total{reason=\"InvalidPassword\"} 1",
+            "aw_universe_packets_total{direction=\"received\",type=\"Login\"} 2",
+            "aw_universe_worlds 0",
+        ] {
+            assert!(lines.contains(&line), "Missing {line:?} in:\n{metrics}");
+        }
+        assert!(metrics.contains("aw_universe_database_statement_seconds_count "));
+    }
+
+    #[test]
+    fn worlds_stop_when_their_license_changes() {
+        let universe = TestUniverse::start();
+        let mut admin = universe.admin();
+        let mut test_server = universe.world(&mut admin, "Test");
+        let mut other_server = universe.world(&mut admin, "Other");
+        let mut tourist = universe.tourist("Visitor");
+
+        let mut license = AWPacket::new(PacketType::LicenseChange);
+        license.add_string(VarID::WorldName, "Test".to_string());
+        license.add_string(VarID::WorldLicensePassword, "worldpass".to_string());
+        license.add_string(VarID::WorldLicenseEmail, String::new());
+        license.add_string(VarID::WorldLicenseComment, String::new());
+        for var in [
+            VarID::WorldLicenseExpiration,
+            VarID::WorldLicenseHidden,
+            VarID::WorldLicenseTourists,
+            VarID::WorldLicenseVoip,
+            VarID::WorldLicensePlugins,
+        ] {
+            license.add_uint(var, 0);
+        }
+        license.add_uint(VarID::WorldLicenseUsers, 20);
+        license.add_uint(VarID::WorldLicenseRange, 100);
+        admin.request(license, PacketType::LicenseResult).unwrap();
+
+        let stop = test_server.wait_for(PacketType::WorldStop).unwrap();
+        assert_eq!(stop.get_string(VarID::WorldName).as_deref(), Some("Test"));
+        assert_eq!(
+            stop.get_int(VarID::ReasonCode),
+            Some(ReasonCode::InvalidWorld as i32)
+        );
+
+        let mut delete = AWPacket::new(PacketType::LicenseDelete);
+        delete.add_string(VarID::WorldName, "Other".to_string());
+        admin
+            .request(delete, PacketType::LicenseChangeResult)
+            .unwrap();
+
+        let stop = other_server.wait_for(PacketType::WorldStop).unwrap();
+        assert_eq!(
+            stop.get_int(VarID::ReasonCode),
+            Some(ReasonCode::NoSuchLicense as i32)
+        );
+
+        // Players are told that the world is gone
+        tourist
+            .wait_until(|packet| {
+                packet.get_string(VarID::WorldListName).as_deref() == Some("Other")
+                    && packet.get_byte(VarID::WorldListStatus) == Some(3)
+            })
+            .unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shutdowns_warn_players_and_stop_worlds() {
+        use crate::configuration::ConsoleConfig;
+        use std::os::unix::net::UnixStream;
+
+        let socket =
+            std::env::temp_dir().join(format!("universe_shutdown_{}.sock", std::process::id()));
+        let universe = TestUniverse::start_with(|config| {
+            config.universe.console = Some(ConsoleConfig {
+                stdin: false,
+                socket: Some(socket.clone()),
+            });
+        });
+        let mut admin = universe.admin();
+        let mut world_server = universe.world(&mut admin, "Test");
+        let mut tourist = universe.tourist("Visitor");
+
+        let mut console = UnixStream::connect(&socket).unwrap();
+        writeln!(console, "shutdown 2").unwrap();
+        console.shutdown(std::net::Shutdown::Write).unwrap();
+        console.read_to_string(&mut String::new()).unwrap();
+
+        // Players are told there is a warning for them, which they fetch like a telegram
+        tourist.wait_for(PacketType::TelegramNotify).unwrap();
+        let warning = tourist
+            .request(
+                AWPacket::new(PacketType::TelegramGet),
+                PacketType::TelegramDeliver,
+            )
+            .unwrap();
+        assert_eq!(
+            warning.get_string(VarID::TelegramMessage).as_deref(),
+            Some("The universe is shutting down in 2 seconds.")
+        );
+        assert_eq!(
+            warning.get_string(VarID::TelegramCitizenName).as_deref(),
+            Some("Administrator")
+        );
+        assert_eq!(warning.get_byte(VarID::TelegramsMoreRemain), Some(0));
+        assert!(AWClient::connect(universe.addr, ProtocolVersion::V4).is_err());
+
+        // The warning is not left waiting as a telegram
+        admin.wait_for(PacketType::TelegramNotify).unwrap();
+        admin
+            .request(
+                AWPacket::new(PacketType::TelegramGet),
+                PacketType::TelegramDeliver,
+            )
+            .unwrap();
+        assert!(matches!(
+            admin.request(
+                AWPacket::new(PacketType::TelegramGet),
+                PacketType::TelegramDeliver
+            ),
+            Err(ClientError::Rejected(ReasonCode::UnableToGetTelegram))
+        ));
+
+        // Nothing else is sent to players before they are disconnected
+        let mut received = Vec::new();
+        let disconnected = loop {
+            match tourist.next_packet(Duration::from_secs(5)) {
+                Ok(Some(packet)) => received.push(packet.get_type()),
+                Ok(None) => break false,
+                Err(why) => break matches!(why, ClientError::Disconnected),
+            }
+        };
+        received.retain(|t| *t != PacketTypeResult::PacketType(PacketType::Heartbeat));
+        assert!(disconnected);
+        assert_eq!(received, vec![]);
+
+        let stop = world_server.wait_for(PacketType::WorldStop).unwrap();
+        assert_eq!(stop.get_string(VarID::WorldName).as_deref(), Some("Test"));
+        assert_eq!(
+            stop.get_int(VarID::ReasonCode),
+            Some(ReasonCode::NotAvailable as i32)
+        );
+        assert!(matches!(
+            world_server.poll(Duration::from_secs(1)),
+            Err(ClientError::Disconnected)
+        ));
+    }
+
+    #[test]
+    fn changed_configuration_is_reloaded() {
+        use crate::database::attrib::Attribute;
+
+        let path =
+            std::env::temp_dir().join(format!("universe_reload_{}.toml", std::process::id()));
+        let universe = TestUniverse::start_from_file(&path, |config| {
+            config.universe.user_list = true;
+        });
+        let mut admin = universe.admin();
+
+        // Make sure the new file's modification time differs from the first one
+        thread::sleep(Duration::from_millis(10));
+        let mut config = Config::load(&path).unwrap();
+        config.universe.user_list = false;
+        std::fs::write(&path, toml::to_string(&config).unwrap()).unwrap();
+
+        let reloaded = admin.wait_until(|packet| {
+            packet.get_type() == PacketTypeResult::PacketType(PacketType::Attributes)
+                && packet.get_string(Attribute::Userlist).as_deref() == Some("N")
+        });
+        std::fs::remove_file(&path).ok();
+        assert!(reloaded.is_ok());
+    }
+
+    #[test]
+    fn citizen_privacy_is_stored_but_not_enforced() {
+        let universe = TestUniverse::start();
+        let (mut alice, _) = universe.immigrant("Alice", "secret");
+        let (mut bob, bob_info) = universe.immigrant("Bob", "secret");
+
+        change_privacy(&mut bob, &bob_info, u32::MAX);
+        alice.telegram_send("Bob", "Hello Bob").unwrap();
+        assert!(player_names(&mut alice).contains(&"Bob".to_string()));
+
+        let lookup = CitizenLookupByNumber {
+            citizen_number: bob_info.citizen_number.unwrap(),
+        };
+        let info = bob.request(lookup.into(), PacketType::CitizenInfo).unwrap();
+        assert_eq!(info.get_uint(VarID::CitizenPrivacy), Some(u32::MAX));
+    }
+
+    #[test]
+    fn privilege_passwords_are_set_and_cleared() {
+        let universe = TestUniverse::start();
+        let (mut alice, alice_info) = universe.immigrant("Alice", "secret");
+
+        let privilege_password = |client: &mut AWClient| {
+            let lookup = CitizenLookupByNumber {
+                citizen_number: alice_info.citizen_number.unwrap(),
+            };
+            let info = client
+                .request(lookup.into(), PacketType::CitizenInfo)
+                .unwrap();
+            (
+                info.get_string(VarID::CitizenPassword).unwrap(),
+                info.get_string(VarID::CitizenPrivilegePassword).unwrap(),
+            )
+        };
+        assert_eq!(
+            privilege_password(&mut alice),
+            ("********".into(), "".into())
+        );
+
+        let packet = citizen_change_packet(&alice_info, "privileged", 0);
+        alice
+            .request(packet, PacketType::CitizenChangeResult)
+            .unwrap();
+        assert_eq!(
+            privilege_password(&mut alice),
+            ("********".into(), "********".into())
+        );
+
+        // Sending back the placeholder keeps the password, and an empty one clears it
+        let packet = citizen_change_packet(&alice_info, "********", 0);
+        alice
+            .request(packet, PacketType::CitizenChangeResult)
+            .unwrap();
+        assert_eq!(privilege_password(&mut alice).1, "********");
+        let packet = citizen_change_packet(&alice_info, "", 0);
+        alice
+            .request(packet, PacketType::CitizenChangeResult)
+            .unwrap();
+        assert_eq!(
+            privilege_password(&mut alice),
+            ("********".into(), "".into())
+        );
+
+        // The main password is still the one chosen at immigration
+        drop(alice);
+        universe.citizen("Alice", "secret");
+    }
+
+    #[test]
+    fn bots_wait_for_required_password_changes() {
+        let path =
+            std::env::temp_dir().join(format!("universe_password_{}.db", std::process::id()));
+        std::fs::remove_file(&path).ok();
+        let configure = |config: &mut Config| {
+            config.sql.sqlite_config.path = path.to_string_lossy().into_owned();
+        };
+        let universe = TestUniverse::start_with(configure);
+        let mut config = Config::default();
+        configure(&mut config);
+        let database = UniverseDatabase::new(config.sql, &config.universe).unwrap();
+        assert!(!database
+            .citizen_set_password_change_required(1, true)
+            .is_err());
+        let required = || {
+            matches!(
+                database.citizen_password_change_required(1),
+                DatabaseResult::Ok(true)
+            )
+        };
+        let bot = || {
+            universe.connect(ProtocolVersion::V4).login(Login::Bot {
+                owner: 1,
+                privilege_password: "privileged".to_string(),
+                name: "Helper".to_string(),
+                application: "Test".to_string(),
+            })
+        };
+
+        let (mut admin, admin_info) = universe.citizen("Administrator", ADMIN_PASSWORD);
+        let packet = citizen_change_packet(&admin_info, "privileged", 0);
+        admin
+            .request(packet, PacketType::CitizenChangeResult)
+            .unwrap();
+        assert!(required());
+        assert!(matches!(
+            bot(),
+            Err(ClientError::Rejected(ReasonCode::Unauthorized))
+        ));
+
+        // Sending back the password it already has isn't a change
+        let mut packet = citizen_change_packet(&admin_info, "privileged", 0);
+        packet.remove_var(VarID::CitizenPassword);
+        packet.add_string(VarID::CitizenPassword, ADMIN_PASSWORD.to_string());
+        admin
+            .request(packet, PacketType::CitizenChangeResult)
+            .unwrap();
+        assert!(required());
+
+        let mut packet = citizen_change_packet(&admin_info, "privileged", 0);
+        packet.remove_var(VarID::CitizenPassword);
+        packet.add_string(VarID::CitizenPassword, "chosen".to_string());
+        admin
+            .request(packet, PacketType::CitizenChangeResult)
+            .unwrap();
+        assert!(!required());
+        bot().expect("Bot could not log in");
+
+        drop(universe);
+        std::fs::remove_file(&path).ok();
+    }
+
+    /// Change the privacy settings of the citizen who is logged in, leaving the rest unchanged.
+    fn change_privacy(client: &mut AWClient, info: &LoginInfo, privacy: u32) {
+        client
+            .request(
+                citizen_change_packet(info, "", privacy),
+                PacketType::CitizenChangeResult,
+            )
+            .unwrap();
+    }
+
+    /// A CitizenChange for the citizen who is logged in, which leaves their password unchanged.
+    fn citizen_change_packet(info: &LoginInfo, privilege_password: &str, privacy: u32) -> AWPacket {
+        let mut packet = AWPacket::new(PacketType::CitizenChange);
+        packet.add_string(VarID::CitizenName, info.name.clone());
+        packet.add_uint(VarID::CitizenNumber, info.citizen_number.unwrap());
+        packet.add_string(
+            VarID::CitizenPrivilegePassword,
+            privilege_password.to_string(),
+        );
+        for var in [
+            VarID::CitizenEmail,
+            VarID::CitizenComment,
+            VarID::CitizenPassword,
+            VarID::CitizenURL,
+        ] {
+            packet.add_string(var, String::new());
+        }
+        for var in [
+            VarID::CitizenExpiration,
+            VarID::CitizenBotLimit,
+            VarID::BetaUser,
+            VarID::CitizenEnabled,
+            VarID::CAVTemplate,
+            VarID::CAVEnabled,
+            VarID::TrialUser,
+        ] {
+            packet.add_uint(var, 0);
+        }
+        packet.add_uint(VarID::CitizenPrivacy, privacy);
+        packet
+    }
+
+    /// Names of the players listed to the client, once updates to the list have been sent.
+    fn player_names(client: &mut AWClient) -> Vec<String> {
+        client.poll(Duration::from_millis(200)).unwrap();
+        client.send(AWPacket::new(PacketType::UserList));
+
+        let mut names = Vec::new();
+        loop {
+            let packet = client
+                .wait_until(|packet| {
+                    matches!(
+                        packet.get_type(),
+                        PacketTypeResult::PacketType(
+                            PacketType::UserList | PacketType::UserListResult
+                        )
+                    )
+                })
+                .unwrap();
+
+            // Players who just left the list are sent once more as hidden (state 0)
+            match packet.get_string(VarID::UserListName) {
+                Some(_) if packet.get_byte(VarID::UserListState) == Some(0) => {}
+                Some(name) => names.push(name),
+                None if packet.get_byte(VarID::UserListMore) == Some(0) => return names,
+                None => {}
+            }
+        }
+    }
+}
@@ -1,11 +1,19 @@
-use std::{collections::HashMap, net::SocketAddr, time::Instant};
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
 
-use aw_core::{AWConnection, AWCryptRSA, AWPacket, AWPacketGroup, PacketType, ProtocolMessage};
+use aw_core::{
+    messages::WorldStop, AWConnection, AWCryptRSA, AWPacket, AWPacketGroup, PacketType,
+    ProtocolMessage, ProtocolVersion, ReasonCode, VarID,
+};
 
 use crate::{
     client::ClientInfo,
     player::{GenericPlayer, Player},
     tabs::{WorldListEntry, WorldStatus},
+    telegram::Notice,
     world::{World, WorldServer},
 };
 
@@ -13,10 +21,13 @@ use crate::{
 pub struct UniverseConnection {
     connection: AWConnection,
     pub rsa: AWCryptRSA,
+    pub protocol: ProtocolVersion,
     pub last_heartbeat_sent: Instant,
     pub last_heartbeat_received: Instant,
     /// A connection may not have one of these yet if they just connected.
     pub client: Option<ClientInfo>,
+    /// Messages from the universe which the player hasn't asked for yet.
+    pub notices: VecDeque<Notice>,
 }
 
 impl UniverseConnection {
@@ -24,9 +35,11 @@ impl UniverseConnection {
         Self {
             connection,
             rsa: AWCryptRSA::new(),
+            protocol: ProtocolVersion::default(),
             last_heartbeat_sent: Instant::now(),
             last_heartbeat_received: Instant::now(),
             client: None,
+            notices: VecDeque::new(),
         }
     }
 
@@ -56,6 +69,18 @@ impl UniverseConnection {
         self.connection.disconnect()
     }
 
+    /// Wait up to `timeout` for a disconnected connection to send what was queued for it.
+    pub fn wait_closed(&self, timeout: Duration) -> bool {
+        self.connection.wait_closed(timeout)
+    }
+
+    /// Decide which version of the protocol the client speaks. This must happen before any keys
+    /// are exchanged.
+    pub fn set_protocol(&mut self, protocol: ProtocolVersion) {
+        self.protocol = protocol;
+        self.connection.set_cipher_kind(protocol.cipher_kind());
+    }
+
     pub fn set_recv_key(&self, key: &[u8]) {
         self.connection.set_recv_key(key)
     }
@@ -107,6 +132,33 @@ impl UniverseConnection {
     pub fn world_server_mut(&mut self) -> Option<&mut WorldServer> {
         self.client.as_mut().and_then(ClientInfo::world_server_mut)
     }
+
+    /// Tell a world server that its world `name` was stopped because of `rc`, and forget about
+    /// the world.
+    pub fn stop_world(&mut self, name: &str, rc: ReasonCode) {
+        let Some(world) = self
+            .world_server_mut()
+            .and_then(|world_server| world_server.remove_world(name))
+        else {
+            return;
+        };
+
+        let mut packet = AWPacket::from(WorldStop { name: world.name });
+        packet.add_int(VarID::ReasonCode, rc.into());
+        self.send(packet);
+    }
+
+    /// Stop every world of a world server, telling it why.
+    pub fn stop_worlds(&mut self, rc: ReasonCode) {
+        let names: Vec<String> = self
+            .world_server()
+            .map(|world_server| world_server.worlds.iter().map(|w| w.name.clone()).collect())
+            .unwrap_or_default();
+
+        for name in names {
+            self.stop_world(&name, rc);
+        }
+    }
 }
 
 #[derive(Eq, Hash, PartialEq, Copy, Clone, Debug)]
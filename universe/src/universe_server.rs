@@ -1,41 +1,81 @@
+//! The universe's main loop, which serves every connection from a single thread.
+//!
+//! On unix, signals control a running universe. SIGHUP and SIGTERM ask it to stop, like Ctrl-C:
+//! the first starts the delayed shutdown of [`crate::shutdown`], and another stops it right away.
+//! SIGUSR1 reloads the configuration, which also happens whenever its file changes. SIGHUP is kept
+//! for stopping rather than reloading, because a universe started from a terminal gets it when the
+//! terminal goes away.
 use aw_core::*;
-use aw_db::DatabaseOpenError;
 
 use crate::{
+    admin::{self, AdminApi, AdminApiStartError, Console},
+    attributes,
     client::ClientInfo,
     configuration,
-    database::UniverseDatabase,
-    get_conn, packet_handler,
+    database::{attrib::Attribute, AttribDB, CitizenDB, UniverseDatabase, UniverseDatabaseError},
+    get_conn, license_sweep,
+    listener::Listener,
+    metrics::{self, Metrics, MetricsEndpoint},
+    packet_handler,
+    player::Citizen,
+    shutdown::{self, Shutdown},
     tabs::{regenerate_contact_list, regenerate_player_list, regenerate_world_list},
     universe_connection::{UniverseConnectionID, UniverseConnections},
-    universe_license::LicenseGenerator,
     UniverseConnection,
 };
+use aw_db::DatabaseResult;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::{
     collections::HashMap,
-    net::{SocketAddrV4, TcpListener},
+    net::{SocketAddr, SocketAddrV4},
+    path::PathBuf,
 };
 use std::{
-    sync::atomic::{AtomicBool, Ordering},
-    thread::sleep,
-    time::Duration,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
-use std::{sync::Arc, time::Instant};
+
+/// Longest time to wait for closed connections to send what is queued for them, as they are
+/// serviced by threads or tasks which end with the process.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often the configuration file is checked for changes.
+const CONFIG_CHECK_INTERVAL: Duration = Duration::from_secs(1);
 
 pub struct UniverseServer {
     pub config: configuration::UniverseConfig,
     pub license_generator: LicenseGenerator,
     pub connections: UniverseConnections,
     pub database: UniverseDatabase,
-    listener: TcpListener,
+    /// File the configuration was read from, which `reload_config` reads again.
+    config_path: Option<PathBuf>,
+    /// Settings given on the command line, which replace those of the file when reloading.
+    config_overrides: configuration::ConfigOverrides,
+    /// When the configuration file was last modified, as of the last time it was checked.
+    config_modified: Option<SystemTime>,
+    config_checked: Instant,
+    licenses_swept: Instant,
+    /// Attributes as clients were last sent them by a reload.
+    attributes: HashMap<Attribute, String>,
+    pub metrics: Metrics,
+    listener: Listener,
+    admin_api: Option<AdminApi>,
+    console: Option<Console>,
+    metrics_endpoint: Option<MetricsEndpoint>,
+    shutdown: Option<Shutdown>,
 }
 
 #[derive(thiserror::Error, Debug)]
 pub enum UniverseStartError {
     #[error("The Universe failed to open its database: {0}")]
-    DatabaseOpenError(#[from] DatabaseOpenError),
+    DatabaseError(#[from] UniverseDatabaseError),
     #[error("The Universe failed to initialize networking: {0}")]
     IoError(#[from] std::io::Error),
+    #[error("The Universe failed to start the admin API: {0}")]
+    AdminApi(#[from] AdminApiStartError),
+    #[error("The Universe failed to serve metrics on {0}: {1}")]
+    Metrics(SocketAddr, Box<dyn std::error::Error + Send + Sync>),
 }
 
 impl UniverseServer {
@@ -50,55 +90,295 @@ impl UniverseServer {
         let license_socket_addr =
             SocketAddrV4::new(config.universe.license_ip, config.universe.port);
 
-        let listener = TcpListener::bind(bind_socket)?;
-        listener.set_nonblocking(true)?;
+        let attributes = match database.attrib_get() {
+            DatabaseResult::Ok(attributes) => attributes,
+            DatabaseResult::DatabaseError => HashMap::new(),
+        };
+
+        let metrics = Metrics::default();
+        let listener = Listener::bind(
+            bind_socket,
+            config.universe.connection_limit,
+            config.universe.capture_dir.clone(),
+            metrics.protocol.clone(),
+        )?;
+
+        let admin_api = match &config.universe.admin_api {
+            Some(admin_config) => {
+                let admin_api = AdminApi::start(admin_config)?;
+                log::info!("Admin API listening on {}", admin_config.bind);
+                Some(admin_api)
+            }
+            None => None,
+        };
+
+        let console = config
+            .universe
+            .console
+            .as_ref()
+            .map(Console::start)
+            .transpose()?;
+
+        let metrics_endpoint = match &config.universe.metrics {
+            Some(metrics_config) => {
+                let endpoint = MetricsEndpoint::start(metrics_config)
+                    .map_err(|why| UniverseStartError::Metrics(metrics_config.bind, why))?;
+                log::info!("Serving metrics on http://{}/metrics", metrics_config.bind);
+                Some(endpoint)
+            }
+            None => None,
+        };
 
         Ok(Self {
             config: config.universe,
             license_generator: LicenseGenerator::new(&license_socket_addr),
             connections: UniverseConnections::new(),
             database,
+            config_path: None,
+            config_overrides: Default::default(),
+            config_modified: None,
+            config_checked: Instant::now(),
+            licenses_swept: Instant::now(),
+            attributes,
+            metrics,
             listener,
+            admin_api,
+            console,
+            metrics_endpoint,
+            shutdown: None,
         })
     }
 
     pub fn run(&mut self) {
         log::info!(
-            "Starting universe on {}:{}. Providing licenses for {}. Protocol version {}.",
+            "Starting universe on {}:{}. Providing licenses for {}.",
             self.config.bind_ip,
             self.config.port,
             self.config.license_ip,
-            Self::protocol_version(),
         );
 
-        let running = Arc::new(AtomicBool::new(true));
+        // The first request to stop starts counting down, and another stops the universe now
+        let stop_requests = Arc::new(AtomicUsize::new(0));
+        let reload_requested = Arc::new(AtomicBool::new(false));
 
-        let r = running.clone();
+        let requests = stop_requests.clone();
         if let Err(why) = ctrlc::set_handler(move || {
-            r.store(false, Ordering::SeqCst);
+            requests.fetch_add(1, Ordering::SeqCst);
         }) {
             log::error!("Could not set up Ctrl-C handler: {why}");
             log::error!("The server will still work, but it may not shut down properly.");
         }
+        #[cfg(unix)]
+        if let Err(why) = handle_signals(stop_requests.clone(), reload_requested.clone()) {
+            log::error!("Could not set up handling of SIGHUP, SIGTERM and SIGUSR1: {why}");
+        }
+
+        let mut handled_requests = 0;
+        self.serve(|server| {
+            if reload_requested.swap(false, Ordering::SeqCst) {
+                if let Err(why) = server.reload_config() {
+                    log::error!("Could not reload the configuration: {why}");
+                }
+            }
+
+            let requests = stop_requests.load(Ordering::SeqCst);
+            if requests == handled_requests {
+                return true;
+            }
+            handled_requests = requests;
 
-        while running.load(Ordering::SeqCst) {
+            if server.shutdown.is_some() {
+                log::info!("Asked to stop again, so stopping now.");
+                return false;
+            }
+            server.shutdown_after(Duration::from_secs(server.config.shutdown.delay));
+            true
+        });
+
+        log::info!("Shutting down universe.");
+    }
+
+    /// Serve clients until `running` is cleared.
+    pub fn run_until(&mut self, running: &AtomicBool) {
+        self.serve(|_| running.load(Ordering::SeqCst));
+    }
+
+    /// Serve clients while `keep_running` is true and no shutdown is due, then close every
+    /// connection.
+    fn serve(&mut self, mut keep_running: impl FnMut(&mut Self) -> bool) {
+        while keep_running(self) && !self.shutdown_due() {
             self.accept_new_clients();
             self.service_clients();
             self.remove_dead_clients();
             self.connections.send_tab_updates();
             self.connections.send_heartbeats();
-            sleep(Duration::from_millis(1));
+            self.handle_admin_requests();
+            self.handle_console_commands();
+            self.handle_metrics_scrapes();
+            self.check_config_file();
+            self.sweep_licenses();
+            self.count_down_shutdown();
+            self.listener.wait();
         }
 
-        log::info!("Shutting down universe.");
+        self.close_connections();
+    }
+
+    /// Address the universe is listening on, which tells which port was picked when binding
+    /// to port 0.
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Stop accepting clients, and stop serving the others once `delay` has passed. Players are
+    /// warned while counting down. A shutdown which was already started is rescheduled.
+    pub fn shutdown_after(&mut self, delay: Duration) {
+        log::info!("Shutting down in {} seconds.", delay.as_secs());
+        self.shutdown = Some(Shutdown::after(delay));
+    }
+
+    fn shutdown_due(&self) -> bool {
+        self.shutdown.as_ref().is_some_and(Shutdown::is_due)
+    }
+
+    fn count_down_shutdown(&mut self) {
+        if let Some(seconds) = self.shutdown.as_mut().and_then(Shutdown::warning_due) {
+            shutdown::warn_players(self, seconds);
+        }
+    }
+
+    /// Stop every world and close every connection, saving what is kept about them.
+    fn close_connections(&mut self) {
+        if self.connections.iter().len() == 0 {
+            return;
+        }
+
+        log::info!("Closing {} connections.", self.connections.iter().len());
+        shutdown::close_connections(self);
+
+        let deadline = Instant::now() + CLOSE_TIMEOUT;
+        for (_id, conn) in self.connections.iter() {
+            if !conn.wait_closed(deadline.saturating_duration_since(Instant::now())) {
+                log::warn!("Gave up on sending what was left for {}.", conn.addr());
+            }
+        }
+        self.remove_dead_clients();
+    }
+
+    /// Stop worlds which no longer have a valid license, every [`license_sweep::SWEEP_INTERVAL`].
+    fn sweep_licenses(&mut self) {
+        if self.licenses_swept.elapsed() < license_sweep::SWEEP_INTERVAL {
+            return;
+        }
+        self.licenses_swept = Instant::now();
+        license_sweep::sweep_licenses(self);
+    }
+
+    /// Remember the file the configuration was read from, so it is reloaded when it changes.
+    /// `overrides` are applied again each time.
+    pub fn watch_config_file(
+        &mut self,
+        path: impl Into<PathBuf>,
+        overrides: configuration::ConfigOverrides,
+    ) {
+        let path = path.into();
+        self.config_modified = modified_time(&path);
+        self.config_path = Some(path);
+        self.config_overrides = overrides;
+    }
+
+    /// Reload the configuration when its file was modified since it was last checked.
+    fn check_config_file(&mut self) {
+        if self.config_checked.elapsed() < CONFIG_CHECK_INTERVAL {
+            return;
+        }
+        self.config_checked = Instant::now();
+
+        let modified = self.config_path.as_deref().and_then(modified_time);
+        if modified.is_none() || modified == self.config_modified {
+            return;
+        }
+
+        log::info!("The configuration file changed.");
+        if let Err(why) = self.reload_config() {
+            log::error!("Could not reload the configuration: {why}");
+        }
+    }
+
+    /// Read the configuration file again and apply the settings which can change while running.
+    /// Attributes are read from the database again as well, and sent to every client if they
+    /// changed.
+    pub fn reload_config(&mut self) -> Result<(), String> {
+        let path = self
+            .config_path
+            .as_ref()
+            .ok_or("The universe was not started from a configuration file")?;
+        self.config_modified = modified_time(path);
+        let mut new = configuration::Config::load(path)?;
+        self.config_overrides.clone().apply(&mut new)?;
+        let problems = new.validate();
+        if !problems.is_empty() {
+            return Err(problems.join(", "));
+        }
+        let new = new.universe;
+        let config = &mut self.config;
+
+        if new.license_ip != config.license_ip
+            || new.bind_ip != config.bind_ip
+            || new.port != config.port
+            || new.protocol != config.protocol
+            || new.capture_dir != config.capture_dir
+            || new.admin_api != config.admin_api
+            || new.console != config.console
+            || new.metrics != config.metrics
+        {
+            log::warn!(
+                "Addresses, the protocol, captures, the admin API, the console and metrics change on restart."
+            );
+        }
+
+        config.user_list = new.user_list;
+        config.allow_citizen_changes = new.allow_citizen_changes;
+        config.allow_immigration = new.allow_immigration;
+        config.connection_limit = new.connection_limit;
+        self.listener.set_connection_limit(new.connection_limit);
+        config.player_limit = new.player_limit;
+
+        if self.database.attrib_set_config(&self.config).is_err() {
+            return Err("Could not update the attributes in the database".to_string());
+        }
+
+        log::info!("Reloaded configuration from {}.", path.display());
+        self.send_changed_attributes();
+        Ok(())
     }
 
-    fn protocol_version() -> &'static str {
-        #[cfg(feature = "protocol_v4")]
-        return "4";
+    fn send_changed_attributes(&mut self) {
+        let DatabaseResult::Ok(attributes) = self.database.attrib_get() else {
+            log::error!("Could not read the attributes to tell whether they changed.");
+            return;
+        };
+        if attributes == self.attributes {
+            return;
+        }
+
+        log::info!("Attributes changed, so sending them to everyone.");
+        for (_id, conn) in self.connections.iter() {
+            attributes::send_attributes(conn, &self.database);
+        }
+        self.attributes = attributes;
+    }
+
+    /// Address the admin API is listening on, if it is enabled.
+    pub fn admin_api_addr(&self) -> Option<SocketAddr> {
+        self.admin_api.as_ref().and_then(AdminApi::local_addr)
+    }
 
-        #[cfg(feature = "protocol_v6")]
-        return "6";
+    /// Address metrics are served on, if they are enabled.
+    pub fn metrics_addr(&self) -> Option<SocketAddr> {
+        self.metrics_endpoint
+            .as_ref()
+            .and_then(MetricsEndpoint::local_addr)
     }
 
     fn accept_new_clients(&mut self) {
@@ -109,19 +389,19 @@ impl UniverseServer {
             return;
         }
 
-        while let Ok((stream, addr)) = self.listener.accept() {
-            let proto = match AWProtocol::new(stream) {
-                Ok(proto) => proto,
-                Err(why) => {
-                    log::error!(
-                        "Failed to create a AWProtocol while accepting new client: {why:?}"
-                    );
-                    continue;
-                }
-            };
-            let conn = UniverseConnection::new(AWConnection::new(proto));
-            self.connections.add_connection(conn);
-            log::info!("{} connected.", addr.ip());
+        for mut conn in self.listener.accept() {
+            if self.shutdown.is_some() {
+                log::info!(
+                    "Turning {} away as the universe is shutting down.",
+                    conn.addr().ip()
+                );
+                conn.disconnect();
+                continue;
+            }
+
+            log::info!("{} connected.", conn.addr().ip());
+            self.connections
+                .add_connection(UniverseConnection::new(conn));
         }
     }
 
@@ -173,6 +453,11 @@ impl UniverseServer {
         for cid in &disconnected_conn_ids {
             let conn = get_conn!(self, *cid, "remove_dead_clients");
             log::info!("Removed client {}", conn.addr().ip());
+            if let Some(ClientInfo::Player(player)) = &conn.client {
+                if let Some(citizen) = player.citizen() {
+                    save_time_online(&self.database, citizen);
+                }
+            }
         }
 
         // Figure out whether the player lists need to be remade, and remake them if so.
@@ -217,6 +502,43 @@ impl UniverseServer {
         }
     }
 
+    fn handle_admin_requests(&mut self) {
+        let Some(admin_api) = &self.admin_api else {
+            return;
+        };
+
+        for request in admin_api.pending_requests() {
+            admin::handle_request(self, request);
+        }
+    }
+
+    fn handle_console_commands(&mut self) {
+        let Some(console) = &self.console else {
+            return;
+        };
+
+        for command in console.pending_commands() {
+            let output = admin::run_command(self, &command.line);
+            command.respond(output);
+        }
+    }
+
+    fn handle_metrics_scrapes(&mut self) {
+        let Some(endpoint) = &self.metrics_endpoint else {
+            return;
+        };
+
+        let scrapes = endpoint.pending_scrapes();
+        if scrapes.is_empty() {
+            return;
+        }
+
+        let text = metrics::render(self);
+        for respond in scrapes {
+            respond.send(text.clone()).ok();
+        }
+    }
+
     fn handle_messages(&mut self, messages: Vec<ProtocolMessage>, cid: UniverseConnectionID) {
         for message in messages {
             match message {
@@ -229,6 +551,7 @@ impl UniverseServer {
                     }
                 }
                 ProtocolMessage::StreamKey(_)
+                | ProtocolMessage::CipherKind(_)
                 | ProtocolMessage::Encrypt(_)
                 | ProtocolMessage::PacketGroup(_) => {
                     panic!("Should not receive these message types on this end.");
@@ -270,7 +593,7 @@ impl UniverseServer {
             PacketType::ContactChange => packet_handler::contact_change(self, cid, packet),
             PacketType::ContactDelete => packet_handler::contact_delete(self, cid, packet),
             PacketType::ContactList => packet_handler::contact_list(self, cid, packet),
-            PacketType::PublicKeyRequest => packet_handler::public_key_request(self, cid),
+            PacketType::PublicKeyRequest => packet_handler::public_key_request(self, cid, packet),
             PacketType::Heartbeat => packet_handler::heartbeat(self, cid),
             PacketType::Identify => packet_handler::identify(self, cid, packet),
             PacketType::LicenseAdd => packet_handler::license_add(self, cid, packet),
@@ -306,3 +629,97 @@ impl UniverseServer {
         }
     }
 }
+
+/// Add the time a citizen has been online to their total time.
+fn save_time_online(database: &UniverseDatabase, citizen: &Citizen) {
+    let seconds = u32::try_from(citizen.logged_in_at.elapsed().as_secs()).unwrap_or(u32::MAX);
+
+    let mut query = match database.citizen_by_number(citizen.cit_id) {
+        DatabaseResult::Ok(Some(query)) => query,
+        DatabaseResult::Ok(None) => return,
+        DatabaseResult::DatabaseError => {
+            log::error!(
+                "Could not save the time citizen {} was online.",
+                citizen.cit_id
+            );
+            return;
+        }
+    };
+
+    query.total_time = query.total_time.saturating_add(seconds);
+    if let DatabaseResult::DatabaseError = database.citizen_change(&query) {
+        log::error!(
+            "Could not save the time citizen {} was online.",
+            citizen.cit_id
+        );
+    }
+}
+
+fn modified_time(path: &std::path::Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Count SIGHUP and SIGTERM as requests to stop, like Ctrl-C, and SIGUSR1 as a request to reload
+/// the configuration.
+#[cfg(unix)]
+fn handle_signals(
+    stop_requests: Arc<AtomicUsize>,
+    reload_requested: Arc<AtomicBool>,
+) -> std::io::Result<()> {
+    use signal_hook::{
+        consts::{SIGHUP, SIGTERM, SIGUSR1},
+        iterator::Signals,
+    };
+
+    let mut signals = Signals::new([SIGHUP, SIGTERM, SIGUSR1])?;
+    thread::spawn(move || {
+        for signal in signals.forever() {
+            log::info!("Received signal {signal}.");
+            if signal == SIGUSR1 {
+                reload_requested.store(true, Ordering::SeqCst);
+            } else {
+                stop_requests.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use signal_hook::{
+        consts::{SIGHUP, SIGUSR1},
+        low_level::raise,
+    };
+
+    fn wait_until(condition: impl Fn() -> bool) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !condition() {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        true
+    }
+
+    #[test]
+    fn signals_stop_or_reload() {
+        let stop_requests = Arc::new(AtomicUsize::new(0));
+        let reload_requested = Arc::new(AtomicBool::new(false));
+        handle_signals(stop_requests.clone(), reload_requested.clone()).unwrap();
+
+        raise(SIGUSR1).unwrap();
+        assert!(wait_until(|| reload_requested.load(Ordering::SeqCst)));
+        assert_eq!(stop_requests.load(Ordering::SeqCst), 0);
+
+        reload_requested.store(false, Ordering::SeqCst);
+        raise(SIGHUP).unwrap();
+        assert!(wait_until(|| stop_requests.load(Ordering::SeqCst) == 1));
+        assert!(!reload_requested.load(Ordering::SeqCst));
+    }
+}
@@ -22,6 +22,8 @@ pub struct World {
     pub free_entry: bool,
     pub world_size: u32,
     pub max_users: u32,
+    /// Password of the license as the world was started, so changing it stops the world.
+    pub license_password: String,
     pub rating: WorldRating,
     pub user_count: u32,
 }